@@ -0,0 +1,30 @@
+use std::process::Command;
+
+// players can only play each other on the same build, so every build is told apart by the commit
+// it was made from, and whether it had changes on top of it
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+    println!("cargo:rerun-if-changed=src");
+
+    let commit = git(&["rev-parse", "--short=12", "HEAD"]);
+    let dirty = git(&["status", "--porcelain", "--untracked-files=no"])
+        .map(|status| !status.is_empty())
+        .unwrap_or(false);
+
+    if let Some(commit) = commit {
+        let suffix = if dirty { "-dirty" } else { "" };
+        println!("cargo:rustc-env=FG_BUILD_ID={}{}", commit, suffix);
+    }
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if output.status.success() {
+        String::from_utf8(output.stdout)
+            .ok()
+            .map(|output| output.trim().to_string())
+    } else {
+        None
+    }
+}
@@ -0,0 +1,271 @@
+//! Helps two peers behind NATs connect to each other, without either forwarding ports.
+//!
+//! Both peers register with the server under the same session name, and the server tells each
+//! the public address it saw the other at. Both then send to each other at the same time,
+//! so each NAT already has a mapping for the other peer by the time their packets arrive.
+//! The socket that punched through can then be handed to the quinn endpoint.
+
+#[cfg(test)]
+mod nat;
+
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind},
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_PORT: u16 = 10798;
+// a session is forgotten if neither peer has registered for this long
+const SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+const REGISTER_TIMEOUT: Duration = Duration::from_secs(30);
+const PUNCH_INTERVAL: Duration = Duration::from_millis(100);
+const PUNCH_ATTEMPTS: usize = 50;
+// sent after hearing from the peer, in case they haven't heard from us yet
+const PUNCH_REPEATS: usize = 3;
+const PUNCH: &[u8] = b"fg_punch";
+const MAX_PACKET_SIZE: usize = 512;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Request {
+    /// Pairs the sender up with whoever else registers with the same session name.
+    Register(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Response {
+    /// The public address the other peer in the session was seen at.
+    Peer(SocketAddr),
+}
+
+/// Anything datagrams can be sent over, so punching can be tested through a simulated NAT.
+pub trait Datagrams {
+    fn send_to(&self, data: &[u8], addr: SocketAddr) -> io::Result<usize>;
+    fn recv_from(&self, data: &mut [u8]) -> io::Result<(usize, SocketAddr)>;
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Datagrams for UdpSocket {
+    fn send_to(&self, data: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        UdpSocket::send_to(self, data, addr)
+    }
+    fn recv_from(&self, data: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        UdpSocket::recv_from(self, data)
+    }
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UdpSocket::set_read_timeout(self, timeout)
+    }
+}
+
+struct Session {
+    first: SocketAddr,
+    second: Option<SocketAddr>,
+    last_seen: Instant,
+}
+
+impl Session {
+    fn peer_of(&self, addr: SocketAddr) -> Option<SocketAddr> {
+        if self.first == addr {
+            self.second
+        } else if self.second == Some(addr) {
+            Some(self.first)
+        } else {
+            None
+        }
+    }
+}
+
+pub struct Server {
+    socket: UdpSocket,
+    sessions: HashMap<String, Session>,
+}
+
+impl Server {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind(addr)?,
+            sessions: HashMap::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Serves requests forever. A request that can't be read or answered is logged and skipped,
+    /// since it only affects that peer, e.g. a reset from one that already left.
+    pub fn run(&mut self) {
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut buffer) {
+                Ok(value) => value,
+                Err(err) => {
+                    eprintln!("couldn't receive a request: {}", err);
+                    continue;
+                }
+            };
+            if let Ok(request) = bincode::deserialize(&buffer[..len]) {
+                if let Err(err) = self.handle_request(request, from) {
+                    eprintln!("couldn't answer {}: {}", from, err);
+                }
+            }
+        }
+    }
+
+    fn handle_request(&mut self, request: Request, from: SocketAddr) -> io::Result<()> {
+        self.sessions
+            .retain(|_, session| session.last_seen.elapsed() < SESSION_TIMEOUT);
+
+        match request {
+            Request::Register(name) => {
+                let session = self.sessions.entry(name).or_insert(Session {
+                    first: from,
+                    second: None,
+                    last_seen: Instant::now(),
+                });
+                session.last_seen = Instant::now();
+
+                let newly_paired = session.first != from && session.second.is_none();
+                if newly_paired {
+                    session.second = Some(from);
+                }
+
+                // anyone past the first two is ignored, and peers keep registering until
+                // they hear back, so a lost response is sent again
+                if let Some(peer) = session.peer_of(from) {
+                    self.send(Response::Peer(peer), from)?;
+                    if newly_paired {
+                        self.send(Response::Peer(from), peer)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send(&self, response: Response, to: SocketAddr) -> io::Result<()> {
+        let data =
+            bincode::serialize(&response).map_err(|err| io::Error::new(ErrorKind::Other, err))?;
+        self.socket.send_to(&data, to)?;
+        Ok(())
+    }
+}
+
+/// Registers `session` with the rendezvous server at `server`, and punches through to
+/// whichever peer registers the same session, returning their public address.
+pub fn punch<S: Datagrams>(
+    socket: &S,
+    server: SocketAddr,
+    session: &str,
+) -> io::Result<SocketAddr> {
+    socket.set_read_timeout(Some(PUNCH_INTERVAL))?;
+    let result = register(socket, server, session).and_then(|peer| {
+        punch_through(socket, peer)?;
+        Ok(peer)
+    });
+    socket.set_read_timeout(None)?;
+    result
+}
+
+fn register<S: Datagrams>(socket: &S, server: SocketAddr, session: &str) -> io::Result<SocketAddr> {
+    let request = bincode::serialize(&Request::Register(session.to_string()))
+        .map_err(|err| io::Error::new(ErrorKind::Other, err))?;
+    let mut buffer = [0; MAX_PACKET_SIZE];
+    let started = Instant::now();
+
+    while started.elapsed() < REGISTER_TIMEOUT {
+        socket.send_to(&request, server)?;
+        match recv(socket, &mut buffer)? {
+            Some((len, from)) if from == server => {
+                if let Ok(Response::Peer(peer)) = bincode::deserialize(&buffer[..len]) {
+                    return Ok(peer);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Err(io::Error::new(
+        ErrorKind::TimedOut,
+        "nobody else registered the session",
+    ))
+}
+
+fn punch_through<S: Datagrams>(socket: &S, peer: SocketAddr) -> io::Result<()> {
+    let mut buffer = [0; MAX_PACKET_SIZE];
+
+    for _ in 0..PUNCH_ATTEMPTS {
+        socket.send_to(PUNCH, peer)?;
+        match recv(socket, &mut buffer)? {
+            Some((len, from)) if from == peer && &buffer[..len] == PUNCH => {
+                for _ in 0..PUNCH_REPEATS {
+                    socket.send_to(PUNCH, peer)?;
+                }
+                return Ok(());
+            }
+            _ => (),
+        }
+    }
+
+    Err(io::Error::new(
+        ErrorKind::TimedOut,
+        "never heard back from the peer",
+    ))
+}
+
+// waits out the read timeout, with `None` if nothing arrived
+fn recv<S: Datagrams>(socket: &S, buffer: &mut [u8]) -> io::Result<Option<(usize, SocketAddr)>> {
+    match socket.recv_from(buffer) {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut => {
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv4Addr, thread};
+
+    use super::*;
+    use crate::nat::Nat;
+
+    #[test]
+    fn punches_through_two_nats() {
+        let mut server = Server::bind((Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+        let server_addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let a = Nat::spawn().unwrap();
+        let b = Nat::spawn().unwrap();
+
+        // neither NAT lets anything in from an address nobody behind it has sent to
+        a.socket.send_to(b"hello", b.public).unwrap();
+        b.socket
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        assert!(b.socket.recv_from(&mut buffer).is_err());
+
+        let a_socket = a.socket.clone();
+        let a_peer = thread::spawn(move || punch(&a_socket, server_addr, "session"));
+        let b_peer = punch(&b.socket, server_addr, "session").unwrap();
+        let a_peer = a_peer.join().unwrap().unwrap();
+
+        assert_eq!(a_peer, b.public);
+        assert_eq!(b_peer, a.public);
+
+        // clears out the repeats sent after punching through
+        b.socket.set_read_timeout(Some(PUNCH_INTERVAL)).unwrap();
+        while b.socket.recv_from(&mut buffer).is_ok() {}
+
+        a.socket.send_to(b"hello", a_peer).unwrap();
+        let (len, from) = b.socket.recv_from(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], b"hello");
+        assert_eq!(from, a.public);
+    }
+}
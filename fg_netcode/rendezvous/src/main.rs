@@ -0,0 +1,17 @@
+use std::net::Ipv4Addr;
+
+use rendezvous::{Server, DEFAULT_PORT};
+
+fn main() -> std::io::Result<()> {
+    let port = match std::env::args().nth(1) {
+        Some(port) => port
+            .parse()
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?,
+        None => DEFAULT_PORT,
+    };
+
+    let mut server = Server::bind((Ipv4Addr::UNSPECIFIED, port).into())?;
+    println!("listening on {}", server.local_addr()?);
+    server.run();
+    Ok(())
+}
@@ -0,0 +1,92 @@
+//! A NAT that only lets in packets from addresses the host behind it has sent to,
+//! simulated on localhost so punching can be tested without a real one.
+
+use std::{
+    collections::HashSet,
+    io,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use crate::{Datagrams, MAX_PACKET_SIZE};
+
+pub struct Nat {
+    /// The address everyone outside the NAT sees the host at.
+    pub public: SocketAddr,
+    /// The host's socket, which can only reach the outside through the NAT.
+    pub socket: NattedSocket,
+}
+
+/// Sends everything through the NAT, along with who it's meant for.
+#[derive(Clone)]
+pub struct NattedSocket {
+    inner: Arc<UdpSocket>,
+    gateway: SocketAddr,
+}
+
+impl Nat {
+    pub fn spawn() -> io::Result<Self> {
+        let inside = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let outside = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let host = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+
+        let public = outside.local_addr()?;
+        let gateway = inside.local_addr()?;
+        let host_addr = host.local_addr()?;
+        let sent_to = Arc::new(Mutex::new(HashSet::new()));
+
+        {
+            let inside = inside.try_clone()?;
+            let outside = outside.try_clone()?;
+            let sent_to = sent_to.clone();
+            thread::spawn(move || -> io::Result<()> {
+                let mut buffer = [0; MAX_PACKET_SIZE * 2];
+                loop {
+                    let (len, _) = inside.recv_from(&mut buffer)?;
+                    let (to, data): (SocketAddr, Vec<u8>) =
+                        bincode::deserialize(&buffer[..len]).unwrap();
+                    sent_to.lock().unwrap().insert(to);
+                    outside.send_to(&data, to)?;
+                }
+            });
+        }
+        thread::spawn(move || -> io::Result<()> {
+            let mut buffer = [0; MAX_PACKET_SIZE];
+            loop {
+                let (len, from) = outside.recv_from(&mut buffer)?;
+                if sent_to.lock().unwrap().contains(&from) {
+                    let data = bincode::serialize(&(from, &buffer[..len])).unwrap();
+                    inside.send_to(&data, host_addr)?;
+                }
+            }
+        });
+
+        Ok(Self {
+            public,
+            socket: NattedSocket {
+                inner: Arc::new(host),
+                gateway,
+            },
+        })
+    }
+}
+
+impl Datagrams for NattedSocket {
+    fn send_to(&self, data: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        let packet = bincode::serialize(&(addr, data)).unwrap();
+        self.inner.send_to(&packet, self.gateway)?;
+        Ok(data.len())
+    }
+    fn recv_from(&self, data: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let mut buffer = [0; MAX_PACKET_SIZE * 2];
+        let (len, _) = self.inner.recv_from(&mut buffer)?;
+        let (from, packet): (SocketAddr, Vec<u8>) = bincode::deserialize(&buffer[..len]).unwrap();
+        data[..packet.len()].copy_from_slice(&packet);
+        Ok((packet.len(), from))
+    }
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display},
+};
+
+use fg_datastructures::roster::RosterCharacter;
+use serde::{Deserialize, Serialize};
+
+/// Bumped whenever what's sent between players changes.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Everything two players need to have the same of, or their games fall out of sync.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Compatibility {
+    pub protocol: u32,
+    pub build: String,
+    /// A hash of each character's loaded data.
+    pub characters: BTreeMap<RosterCharacter, u32>,
+}
+
+/// Something a joining player has that's different from the host.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Incompatibility {
+    Protocol {
+        host: u32,
+        client: u32,
+    },
+    Build {
+        host: String,
+        client: String,
+    },
+    /// The character's data is different, or one side doesn't have the character at all.
+    Character(RosterCharacter),
+}
+
+impl Compatibility {
+    pub fn new(build: String, characters: BTreeMap<RosterCharacter, u32>) -> Self {
+        Self {
+            protocol: PROTOCOL_VERSION,
+            build,
+            characters,
+        }
+    }
+
+    /// Checks a joining player against the host, listing everything that differs.
+    pub fn check(&self, client: &Compatibility) -> Result<(), Vec<Incompatibility>> {
+        let mut differences = Vec::new();
+        if self.protocol != client.protocol {
+            differences.push(Incompatibility::Protocol {
+                host: self.protocol,
+                client: client.protocol,
+            });
+        }
+        if self.build != client.build {
+            differences.push(Incompatibility::Build {
+                host: self.build.clone(),
+                client: client.build.clone(),
+            });
+        }
+        let characters: BTreeSet<_> = self
+            .characters
+            .keys()
+            .chain(client.characters.keys())
+            .filter(|character| self.characters.get(character) != client.characters.get(character))
+            .copied()
+            .collect();
+        differences.extend(characters.into_iter().map(Incompatibility::Character));
+
+        if differences.is_empty() {
+            Ok(())
+        } else {
+            Err(differences)
+        }
+    }
+}
+
+impl Display for Incompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Incompatibility::Protocol { host, client } => {
+                write!(f, "network protocol {} (the host has {})", client, host)
+            }
+            Incompatibility::Build { host, client } => {
+                write!(f, "build {} (the host has {})", client, host)
+            }
+            Incompatibility::Character(character) => write!(f, "{}'s data", character),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_every_difference() {
+        let host = Compatibility::new(
+            "0.1.0".to_string(),
+            std::iter::once((RosterCharacter::Yuyuko, 1)).collect(),
+        );
+        assert_eq!(host.check(&host.clone()), Ok(()));
+
+        let client = Compatibility {
+            protocol: PROTOCOL_VERSION + 1,
+            build: "0.2.0".to_string(),
+            characters: std::iter::once((RosterCharacter::Yuyuko, 2)).collect(),
+        };
+        assert_eq!(
+            host.check(&client),
+            Err(vec![
+                Incompatibility::Protocol {
+                    host: PROTOCOL_VERSION,
+                    client: PROTOCOL_VERSION + 1,
+                },
+                Incompatibility::Build {
+                    host: "0.1.0".to_string(),
+                    client: "0.2.0".to_string(),
+                },
+                Incompatibility::Character(RosterCharacter::Yuyuko),
+            ])
+        );
+
+        // a character only one side has counts too
+        let client = Compatibility::new("0.1.0".to_string(), BTreeMap::new());
+        assert_eq!(
+            host.check(&client),
+            Err(vec![Incompatibility::Character(RosterCharacter::Yuyuko)])
+        );
+    }
+}
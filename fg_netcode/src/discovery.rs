@@ -0,0 +1,159 @@
+use std::{
+    io::{self, ErrorKind},
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// The port lobbies are announced to, which is just below the ones lobbies are hosted on.
+pub const DISCOVERY_PORT: u16 = 10799;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+// a few announcements can be lost before a lobby is forgotten
+const LOBBY_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_ANNOUNCEMENT_SIZE: usize = 512;
+
+/// What a host tells everyone on the LAN about its lobby.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LobbyAnnouncement {
+    pub name: String,
+    pub players: usize,
+    pub version: String,
+    /// The port the lobby is hosted on, at whichever address the announcement came from.
+    pub port: u16,
+    pub password: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredLobby {
+    pub addr: SocketAddr,
+    pub announcement: LobbyAnnouncement,
+    last_seen: Instant,
+}
+
+/// Broadcasts a lobby's announcement every so often.
+pub struct Announcer {
+    socket: UdpSocket,
+    target: SocketAddr,
+    last_sent: Option<Instant>,
+}
+
+impl Announcer {
+    pub fn new() -> io::Result<Self> {
+        Self::with_target((Ipv4Addr::BROADCAST, DISCOVERY_PORT).into())
+    }
+
+    fn with_target(target: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            target,
+            last_sent: None,
+        })
+    }
+
+    /// Meant to be called every frame, and only sends once `ANNOUNCE_INTERVAL` has passed.
+    pub fn announce(&mut self, announcement: &LobbyAnnouncement) -> io::Result<()> {
+        if self
+            .last_sent
+            .map_or(false, |last_sent| last_sent.elapsed() < ANNOUNCE_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.last_sent = Some(Instant::now());
+
+        let data = bincode::serialize(announcement)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+        self.socket.send_to(&data, self.target)?;
+        Ok(())
+    }
+}
+
+/// Listens for the lobbies being announced on the LAN.
+pub struct Discovery {
+    socket: UdpSocket,
+    lobbies: Vec<DiscoveredLobby>,
+}
+
+impl Discovery {
+    pub fn new() -> io::Result<Self> {
+        Self::bind((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT).into())
+    }
+
+    fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            lobbies: Vec::new(),
+        })
+    }
+
+    /// Takes in every announcement that's arrived, and forgets lobbies that stopped announcing.
+    pub fn poll(&mut self) {
+        let mut buffer = [0; MAX_ANNOUNCEMENT_SIZE];
+        // stops at `WouldBlock` once there's nothing left, and anything else is tried next poll
+        while let Ok((len, from)) = self.socket.recv_from(&mut buffer) {
+            let announcement: LobbyAnnouncement = match bincode::deserialize(&buffer[..len]) {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+            let addr = SocketAddr::new(from.ip(), announcement.port);
+            let lobby = DiscoveredLobby {
+                addr,
+                announcement,
+                last_seen: Instant::now(),
+            };
+
+            match self.lobbies.iter_mut().find(|lobby| lobby.addr == addr) {
+                Some(existing) => *existing = lobby,
+                None => self.lobbies.push(lobby),
+            }
+        }
+
+        self.lobbies
+            .retain(|lobby| lobby.last_seen.elapsed() < LOBBY_TIMEOUT);
+    }
+
+    pub fn lobbies(&self) -> &[DiscoveredLobby] {
+        &self.lobbies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn announcements_are_discovered() {
+        let mut discovery = Discovery::bind((Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+        let target = discovery.socket.local_addr().unwrap();
+        let mut announcer = Announcer::with_target(target).unwrap();
+
+        let announcement = LobbyAnnouncement {
+            name: "Host".to_string(),
+            players: 1,
+            version: "0.1.0".to_string(),
+            port: 10800,
+            password: false,
+        };
+        announcer.announce(&announcement).unwrap();
+        // too soon after the last one, so this one isn't sent
+        announcer
+            .announce(&LobbyAnnouncement {
+                players: 2,
+                ..announcement.clone()
+            })
+            .unwrap();
+
+        std::thread::sleep(Duration::from_millis(10));
+        discovery.poll();
+
+        assert_eq!(discovery.lobbies().len(), 1);
+        let lobby = &discovery.lobbies()[0];
+        assert_eq!(lobby.announcement, announcement);
+        assert_eq!(lobby.addr, (Ipv4Addr::LOCALHOST, 10800).into());
+    }
+}
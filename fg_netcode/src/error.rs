@@ -1,9 +1,20 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+use crate::compatibility::Incompatibility;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum JoinLobbyError {
     InLobby,
     Denied,
+    IncorrectPassword,
     InvalidLobby,
     NetworkError,
+    /// The host's certificate isn't the one it had the first time it was connected to.
+    CertificateChanged,
+    /// The host banned this player, or someone at the same address, earlier in the session.
+    Banned,
+    /// The host isn't letting anyone new in.
+    Locked,
+    /// The player's build or game data is different from the host's, so their games would desync.
+    Incompatible(Vec<Incompatibility>),
 }
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum HostLobbyError {
@@ -20,7 +31,15 @@ pub enum JoinGameError {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub struct SpectateGameError;
+pub enum SpectateGameError {
+    NoSuchGame,
+    AlreadyInGame,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LeaveGameError {
+    NotInGame,
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum CreateGameError {
@@ -28,6 +47,7 @@ pub enum CreateGameError {
     OutOfGames,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum UpdateMetaError {
     InvalidPermission,
     InvalidUpdate,
@@ -35,6 +55,12 @@ pub enum UpdateMetaError {
     NetworkError,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SendMatchError {
+    /// The data is bigger than `game::MAX_RELIABLE_SIZE`, so the peer would refuse it.
+    TooLarge,
+}
+
 pub enum NetworkError {
     PeerDisconnected,
     HostDisconneted,
@@ -1,39 +1,186 @@
 use bytes::Bytes;
+use crossbeam_channel::TryRecvError;
+use fg_datastructures::{player_data::PlayerData, roster::RosterCharacter};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
 
-use crate::player_list::Player;
+use crate::{error::SendMatchError, player_list::Player};
 
-pub struct Game {}
+/// The most data that can be sent reliably at once.
+pub const MAX_RELIABLE_SIZE: usize = 4096;
 
+/// The ready check for whichever game the user is seated in.
+#[derive(Debug)]
+pub struct Game {
+    actions: mpsc::Sender<GameAction>,
+    messages: crossbeam_channel::Receiver<GameMessage>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameAction {
+    Ready,
+    Pass,
+}
+
+#[derive(Debug)]
 pub enum GameMessage {
-    PlayersReady,
+    /// The host started the game's match, which begins `MatchStart::start_frame` frames
+    /// after this arrives, already shortened by however long it took to get here.
+    PlayersReady(Match, MatchStart),
+}
+
+/// What the host agreed to start a game's match with, once both seated players were ready.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatchStart {
+    pub characters: PlayerData<RosterCharacter>,
+    pub first_to: usize,
+    /// How many frames after the host confirmed the start that the match begins.
+    pub start_frame: usize,
 }
 
 impl Game {
-    pub fn pass(&mut self) {}
-    pub fn ready(&mut self) {}
+    pub fn new(
+        actions: mpsc::Sender<GameAction>,
+        messages: crossbeam_channel::Receiver<GameMessage>,
+    ) -> Self {
+        Self { actions, messages }
+    }
+
+    /// Takes back a ready, so the game won't start until the user readies again.
+    pub fn pass(&mut self) {
+        self.actions.blocking_send(GameAction::Pass).unwrap();
+    }
+    pub fn ready(&mut self) {
+        self.actions.blocking_send(GameAction::Ready).unwrap();
+    }
     pub fn poll(&mut self) -> Option<GameMessage> {
-        None
+        match self.messages.try_recv() {
+            Ok(value) => Some(value),
+            Err(TryRecvError::Disconnected) => panic!("Backing network was disconnected."),
+            Err(TryRecvError::Empty) => None,
+        }
     }
 }
 
-pub struct Match {}
+/// A packet for the backend to send to another member of the match.
+#[derive(Debug)]
+pub struct MatchPacket {
+    pub to: Player,
+    pub data: Bytes,
+    pub reliable: bool,
+}
+
+/// A running match, which is over once this is dropped.
+#[derive(Debug)]
+pub struct Match {
+    players: Vec<Player>,
+    user: Player,
+    outgoing: mpsc::UnboundedSender<MatchPacket>,
+    messages: crossbeam_channel::Receiver<MatchMessage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MatchMessage {
+    /// Data sent unreliably, which may arrive out of order or not at all.
     Packet(WhoIs, Bytes),
+    /// Data sent reliably, which always arrives.
+    Control(WhoIs, Bytes),
     Disconnected(WhoIs),
 }
 
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub enum WhoIs {
     P1,
     P2,
-    Spectator,
+    Spectator(Player),
+}
+
+impl WhoIs {
+    /// Who `player` is in a game seated as `players`, with the two players first.
+    pub fn from_seat(players: &[Player], player: Player) -> Self {
+        match players.iter().position(|item| *item == player) {
+            Some(0) => WhoIs::P1,
+            Some(1) => WhoIs::P2,
+            _ => WhoIs::Spectator(player),
+        }
+    }
 }
 
 impl Match {
+    /// `players` are all the members of the match, with the two players first.
+    pub fn new(
+        players: Vec<Player>,
+        user: Player,
+        outgoing: mpsc::UnboundedSender<MatchPacket>,
+        messages: crossbeam_channel::Receiver<MatchMessage>,
+    ) -> Self {
+        Self {
+            players,
+            user,
+            outgoing,
+            messages,
+        }
+    }
+
     pub fn poll(&mut self) -> Option<MatchMessage> {
-        None
+        match self.messages.try_recv() {
+            Ok(value) => Some(value),
+            Err(TryRecvError::Disconnected) => panic!("Backing network was disconnected."),
+            Err(TryRecvError::Empty) => None,
+        }
+    }
+
+    /// Sends `data` unreliably, for anything that's sent again if it's lost, like inputs.
+    pub fn send(&mut self, to: WhoIs, data: impl Into<Bytes>) {
+        self.send_packet(to, data.into(), false);
+    }
+
+    /// Sends `data` reliably and in order, for anything that can't be lost.
+    /// It can be at most `MAX_RELIABLE_SIZE` bytes.
+    pub fn send_reliable(
+        &mut self,
+        to: WhoIs,
+        data: impl Into<Bytes>,
+    ) -> Result<(), SendMatchError> {
+        let data = data.into();
+        if data.len() > MAX_RELIABLE_SIZE {
+            return Err(SendMatchError::TooLarge);
+        }
+        self.send_packet(to, data, true);
+        Ok(())
+    }
+
+    fn send_packet(&mut self, to: WhoIs, data: Bytes, reliable: bool) {
+        if let Some(to) = self.player(to).filter(|to| *to != self.user) {
+            let _ = self.outgoing.send(MatchPacket { to, data, reliable });
+        }
+    }
+
+    pub fn who_is(&self, player: Player) -> WhoIs {
+        WhoIs::from_seat(&self.players, player)
+    }
+
+    pub fn player(&self, who: WhoIs) -> Option<Player> {
+        match who {
+            WhoIs::P1 => self.players.get(0).copied(),
+            WhoIs::P2 => self.players.get(1).copied(),
+            WhoIs::Spectator(player) => Some(player).filter(|player| {
+                self.players
+                    .iter()
+                    .skip(2)
+                    .any(|spectator| spectator == player)
+            }),
+        }
+    }
+
+    pub fn user(&self) -> WhoIs {
+        self.who_is(self.user)
     }
-    pub fn send(&mut self) {}
-    pub fn who_is(&self, _player: Player) -> WhoIs {
-        WhoIs::P1
+
+    pub fn spectators(&self) -> impl Iterator<Item = WhoIs> + '_ {
+        self.players
+            .iter()
+            .skip(2)
+            .map(|player| WhoIs::Spectator(*player))
     }
 }
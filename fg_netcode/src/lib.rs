@@ -1,3 +1,5 @@
+pub mod compatibility;
+pub mod discovery;
 pub mod error;
 pub mod game;
 pub mod lobby;
@@ -6,14 +8,33 @@ pub mod player_list;
 
 use std::net::SocketAddr;
 
+use compatibility::Compatibility;
 use crossbeam_channel::{bounded, Receiver, Sender, TryRecvError};
 use error::{HostLobbyError, JoinLobbyError};
 use lobby::Lobby;
 use player_info::PlayerInfo;
 
+/// What actually hosts and joins lobbies, answering through the channel `Networking` polls.
+pub trait NetworkingBackend {
+    /// Anyone joining has to give `password`, if it's set, and match `compatibility`.
+    fn request_host(
+        &mut self,
+        player: PlayerInfo,
+        password: Option<String>,
+        compatibility: Compatibility,
+    );
+    fn request_join(
+        &mut self,
+        id: SocketAddr,
+        player: PlayerInfo,
+        password: Option<String>,
+        compatibility: Compatibility,
+    );
+}
+
 pub struct Networking {
     rx: Receiver<NetworkingMessage>,
-    _tx: Sender<NetworkingMessage>,
+    backend: Box<dyn NetworkingBackend>,
 }
 
 #[derive(Debug)]
@@ -22,18 +43,58 @@ pub enum NetworkingMessage {
     Join(Result<Lobby, JoinLobbyError>),
 }
 
+// stands in when there's no network to use, and turns down every request
+struct Offline(Sender<NetworkingMessage>);
+
+impl NetworkingBackend for Offline {
+    fn request_host(&mut self, _: PlayerInfo, _: Option<String>, _: Compatibility) {
+        let _ = self
+            .0
+            .try_send(NetworkingMessage::Host(Err(HostLobbyError::NetworkError)));
+    }
+    fn request_join(&mut self, _: SocketAddr, _: PlayerInfo, _: Option<String>, _: Compatibility) {
+        let _ = self
+            .0
+            .try_send(NetworkingMessage::Join(Err(JoinLobbyError::NetworkError)));
+    }
+}
+
 impl Default for Networking {
     fn default() -> Self {
         let (tx, rx) = bounded(4);
-        Self { _tx: tx, rx }
+        Self::with_backend(rx, Box::new(Offline(tx)))
     }
 }
 impl Networking {
     pub fn new() -> Self {
         Self::default()
     }
-    pub fn request_host(&mut self, _player: PlayerInfo) {}
-    pub fn request_join(&mut self, _id: SocketAddr, _player: PlayerInfo) {}
+    /// `rx` receives whatever `backend` answers each request with.
+    pub fn with_backend(
+        rx: Receiver<NetworkingMessage>,
+        backend: Box<dyn NetworkingBackend>,
+    ) -> Self {
+        Self { rx, backend }
+    }
+    /// Anyone joining has to give `password`, if it's set, and match `compatibility`.
+    pub fn request_host(
+        &mut self,
+        player: PlayerInfo,
+        password: Option<String>,
+        compatibility: Compatibility,
+    ) {
+        self.backend.request_host(player, password, compatibility)
+    }
+    pub fn request_join(
+        &mut self,
+        id: SocketAddr,
+        player: PlayerInfo,
+        password: Option<String>,
+        compatibility: Compatibility,
+    ) {
+        self.backend
+            .request_join(id, player, password, compatibility)
+    }
 
     pub fn poll(&mut self) -> Option<NetworkingMessage> {
         match self.rx.try_recv() {
@@ -1,6 +1,12 @@
+pub mod chat;
 pub mod lobby_state;
 
-use crate::{game::Game, player_info::PlayerInfo, player_list::Player};
+use crate::{
+    error::{CreateGameError, JoinGameError, LeaveGameError, SpectateGameError, UpdateMetaError},
+    game::{Game, MatchStart},
+    player_info::PlayerInfo,
+    player_list::Player,
+};
 use crossbeam_channel::TryRecvError;
 use fg_datastructures::player_data::PlayerData;
 use serde::{Deserialize, Serialize};
@@ -8,22 +14,59 @@ use tokio::sync::{mpsc, watch};
 
 use self::lobby_state::LobbyState;
 
+/// Names a game for as long as it exists, unlike its place in `LobbyState::games`,
+/// which moves whenever a game before it is removed.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct GameId(pub usize);
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct GameInfo {
-    pub player_list: Vec<Player>,
+    pub id: GameId,
+    /// Who's in the two player slots, in seat order.
+    pub players: Vec<Player>,
+    pub spectators: Vec<Player>,
     pub ready: PlayerData<bool>,
+    pub first_to: usize,
+    /// Set by the host once both players are ready, and cleared when either stops being ready.
+    pub start: Option<MatchStart>,
 }
 
 impl GameInfo {
-    pub fn spectators(&self) -> &[Player] {
-        if self.player_list.len() > 2 {
-            &self.player_list[2..]
-        } else {
-            &self.player_list[0..0]
+    pub const PLAYER_SLOTS: usize = 2;
+
+    pub fn new(id: GameId, player: Player) -> Self {
+        Self {
+            id,
+            players: vec![player],
+            spectators: vec![],
+            ready: [false, false].into(),
+            first_to: 2,
+            start: None,
         }
     }
+
+    pub fn spectators(&self) -> &[Player] {
+        &self.spectators
+    }
     pub fn players(&self) -> &[Player] {
-        &self.player_list[0..2.min(self.player_list.len())]
+        &self.players
+    }
+    /// Everyone in the game, with the players first.
+    pub fn members(&self) -> Vec<Player> {
+        self.players
+            .iter()
+            .chain(self.spectators.iter())
+            .copied()
+            .collect()
+    }
+    pub fn contains(&self, player: &Player) -> bool {
+        self.players.contains(player) || self.spectators.contains(player)
+    }
+    pub fn is_full(&self) -> bool {
+        self.players.len() >= Self::PLAYER_SLOTS
+    }
+    pub fn all_ready(&self) -> bool {
+        self.is_full() && self.ready.iter().all(|ready| *ready)
     }
 }
 
@@ -32,31 +75,43 @@ pub struct Lobby {
     state: watch::Receiver<LobbyState>,
     message: crossbeam_channel::Receiver<LobbyMessage>,
     action: mpsc::Sender<LobbyAction>,
+    game: Game,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct InvalidGame;
-
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LobbyAction {
     CreateGame,
-    JoinGame(usize),
+    JoinGame(GameId),
+    SpectateGame(GameId),
+    LeaveGame,
     UpdatePlayerInfo(PlayerInfo),
+    Chat(String),
+    /// Only the host can kick, ban, or lock.
+    Kick(Player),
+    /// Kicks the player, and keeps their identity and address out for the rest of the session.
+    Ban(Player),
+    Lock(bool),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum JoinGameError {
-    InGame,
-    InvalidGame,
+/// The result of a game request, once the host has handled it.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LobbyMessage {
+    CreateGame(Result<GameId, CreateGameError>),
+    JoinGame(Result<GameId, JoinGameError>),
+    SpectateGame(Result<GameId, SpectateGameError>),
+    LeaveGame(Result<GameId, LeaveGameError>),
+    Kick(Result<Player, UpdateMetaError>),
+    Ban(Result<Player, UpdateMetaError>),
+    Lock(Result<bool, UpdateMetaError>),
+    /// The host removed the user from the lobby, which is the last message the lobby sends.
+    Removed(Removal),
 }
 
+/// Why the host removed a player from the lobby.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct InGame;
-
-#[derive(Debug, PartialEq, Eq)]
-pub enum LobbyMessage {
-    CreateGame(Result<usize, InGame>),
-    JoinGame(Result<usize, JoinGameError>),
+pub enum Removal {
+    Kicked,
+    Banned,
 }
 
 impl Lobby {
@@ -64,11 +119,13 @@ impl Lobby {
         state: watch::Receiver<LobbyState>,
         message: crossbeam_channel::Receiver<LobbyMessage>,
         action: mpsc::Sender<LobbyAction>,
+        game: Game,
     ) -> Self {
         Self {
             state,
             message,
             action,
+            game,
         }
     }
 
@@ -76,11 +133,43 @@ impl Lobby {
         self.state.borrow()
     }
 
+    pub fn game(&mut self) -> &mut Game {
+        &mut self.game
+    }
+
     pub fn create_game(&self) {
         self.action.blocking_send(LobbyAction::CreateGame).unwrap();
     }
-    pub fn join_game(&mut self, _idx: usize) -> Result<(), InvalidGame> {
-        Err(InvalidGame)
+    pub fn join_game(&self, game: GameId) {
+        self.action
+            .blocking_send(LobbyAction::JoinGame(game))
+            .unwrap();
+    }
+    pub fn spectate_game(&self, game: GameId) {
+        self.action
+            .blocking_send(LobbyAction::SpectateGame(game))
+            .unwrap();
+    }
+    pub fn leave_game(&self) {
+        self.action.blocking_send(LobbyAction::LeaveGame).unwrap();
+    }
+
+    pub fn chat(&self, text: String) {
+        self.action.blocking_send(LobbyAction::Chat(text)).unwrap();
+    }
+
+    pub fn kick(&self, player: Player) {
+        self.action
+            .blocking_send(LobbyAction::Kick(player))
+            .unwrap();
+    }
+    pub fn ban(&self, player: Player) {
+        self.action.blocking_send(LobbyAction::Ban(player)).unwrap();
+    }
+    pub fn lock(&self, locked: bool) {
+        self.action
+            .blocking_send(LobbyAction::Lock(locked))
+            .unwrap();
     }
 
     pub fn update_player_data<F: FnOnce(&mut PlayerInfo)>(&self, update: F) {
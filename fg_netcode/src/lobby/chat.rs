@@ -0,0 +1,27 @@
+use std::time::SystemTime;
+
+use crate::player_list::Player;
+use serde::{Deserialize, Serialize};
+
+/// How many messages are kept, oldest first.
+pub const CHAT_HISTORY: usize = 32;
+/// Longer messages are cut off, so any message fits in a single packet.
+pub const MAX_CHAT_LENGTH: usize = 128;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChatMessage {
+    pub sender: Player,
+    pub text: String,
+    /// When the host received the message.
+    pub sent: SystemTime,
+}
+
+impl ChatMessage {
+    pub fn new(sender: Player, text: String) -> Self {
+        Self {
+            sender,
+            text: text.chars().take(MAX_CHAT_LENGTH).collect(),
+            sent: SystemTime::now(),
+        }
+    }
+}
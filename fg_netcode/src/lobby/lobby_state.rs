@@ -1,16 +1,33 @@
 use crate::{
+    error::{CreateGameError, JoinGameError, LeaveGameError, SpectateGameError, UpdateMetaError},
+    game::MatchStart,
     player_info::PlayerInfo,
     player_list::{Player, PlayerList},
 };
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 
-use super::GameInfo;
+/// Room for a whole lobby with a full chat history, which is the most a backend needs to read
+/// for any lobby update.
+pub const MAX_LOBBY_STATE_SIZE: usize = 64 * 1024;
+
+use super::{
+    chat::{ChatMessage, CHAT_HISTORY},
+    GameId, GameInfo,
+};
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct LobbyState {
     pub player_list: PlayerList,
     pub games: Vec<GameInfo>,
+    // every replica creates games in the same order, so they all hand out the same ids
+    next_game: usize,
     pub user: Player,
+    pub chat: VecDeque<ChatMessage>,
+    /// Set by the host to keep anyone new from joining.
+    pub locked: bool,
+    /// How many of the host's updates have been applied, so a replica can tell if it missed one.
+    pub version: u64,
 }
 
 impl LobbyState {
@@ -24,14 +41,146 @@ impl LobbyState {
             user,
             player_list,
             games: vec![],
+            next_game: 0,
+            chat: VecDeque::new(),
+            locked: false,
+            version: 0,
         }
     }
     pub fn remove(&mut self, removed: &Player) -> Option<PlayerInfo> {
         let res = self.player_list.remove(removed);
+        let _ = self.leave_game(*removed);
+        res
+    }
+
+    /// The game `player` is playing or spectating in.
+    pub fn game_of(&self, player: Player) -> Option<GameId> {
+        self.games
+            .iter()
+            .find(|game| game.contains(&player))
+            .map(|game| game.id)
+    }
+
+    pub fn game(&self, id: GameId) -> Option<&GameInfo> {
+        self.games.iter().find(|game| game.id == id)
+    }
+    fn game_mut(&mut self, id: GameId) -> Option<&mut GameInfo> {
+        self.games.iter_mut().find(|game| game.id == id)
+    }
+
+    pub fn create_game(&mut self, player: Player) -> Result<GameId, CreateGameError> {
+        if self.game_of(player).is_some() {
+            return Err(CreateGameError::AlreadyInGame);
+        }
+        let id = GameId(self.next_game);
+        self.next_game = self
+            .next_game
+            .checked_add(1)
+            .ok_or(CreateGameError::OutOfGames)?;
+        self.games.push(GameInfo::new(id, player));
+        Ok(id)
+    }
+
+    pub fn join_game(&mut self, player: Player, id: GameId) -> Result<GameId, JoinGameError> {
+        if self.game_of(player).is_some() {
+            return Err(JoinGameError::AlreadyInGame);
+        }
+        let game = self.game_mut(id).ok_or(JoinGameError::NoSuchGame)?;
+        if game.start.is_some() {
+            Err(JoinGameError::GameAlreadyStarted)
+        } else if game.is_full() {
+            Err(JoinGameError::GameFull)
+        } else {
+            game.players.push(player);
+            Ok(id)
+        }
+    }
+
+    pub fn spectate_game(
+        &mut self,
+        player: Player,
+        id: GameId,
+    ) -> Result<GameId, SpectateGameError> {
+        if self.game_of(player).is_some() {
+            return Err(SpectateGameError::AlreadyInGame);
+        }
+        let game = self.game_mut(id).ok_or(SpectateGameError::NoSuchGame)?;
+        game.spectators.push(player);
+        Ok(id)
+    }
+
+    /// Frees up the player's slot, and removes the game if nobody is left in it.
+    pub fn leave_game(&mut self, player: Player) -> Result<GameId, LeaveGameError> {
+        let idx = self
+            .games
+            .iter()
+            .position(|game| game.contains(&player))
+            .ok_or(LeaveGameError::NotInGame)?;
+        let game = &mut self.games[idx];
+        let id = game.id;
+        if game.players.contains(&player) {
+            // whoever is left has to ready again
+            game.ready = [false, false].into();
+            game.start = None;
+        }
+        game.players.retain(|item| *item != player);
+        game.spectators.retain(|item| *item != player);
+        if game.players.is_empty() && game.spectators.is_empty() {
+            self.games.remove(idx);
+        }
+        Ok(id)
+    }
+
+    pub fn add_chat(&mut self, message: ChatMessage) {
+        if self.chat.len() >= CHAT_HISTORY {
+            self.chat.pop_front();
+        }
+        self.chat.push_back(message);
+    }
+
+    pub fn set_ready(&mut self, player: Player, ready: bool) {
         for game in self.games.iter_mut() {
-            game.player_list.retain(|item| item != removed);
+            if let Some(seat) = game.players.iter().position(|item| *item == player) {
+                game.ready[seat] = ready;
+                if !ready {
+                    game.start = None;
+                }
+            }
+        }
+    }
+
+    /// Only starts a game whose players are all ready, and that hasn't started already.
+    pub fn start_game(&mut self, id: GameId, start: MatchStart) {
+        if let Some(game) = self.game_mut(id) {
+            if game.all_ready() && game.start.is_none() {
+                game.start = Some(start);
+            }
+        }
+    }
+
+    /// Whether `by` can remove `target` from the lobby, which only the host can do to others.
+    pub fn can_remove(&self, by: Player, target: Player) -> Result<(), UpdateMetaError> {
+        if !self.is_host(by) {
+            Err(UpdateMetaError::InvalidPermission)
+        } else if by == target || self.player_list.get(target).is_none() {
+            Err(UpdateMetaError::InvalidUpdate)
+        } else {
+            Ok(())
         }
-        res
+    }
+
+    pub fn remove_player(&mut self, by: Player, target: Player) -> Result<Player, UpdateMetaError> {
+        self.can_remove(by, target)?;
+        self.remove(&target);
+        Ok(target)
+    }
+
+    pub fn lock(&mut self, by: Player, locked: bool) -> Result<bool, UpdateMetaError> {
+        if !self.is_host(by) {
+            return Err(UpdateMetaError::InvalidPermission);
+        }
+        self.locked = locked;
+        Ok(locked)
     }
 
     pub fn is_user_host(&self) -> bool {
@@ -61,3 +210,65 @@ impl LobbyState {
         &self.games
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{LobbyState, MAX_LOBBY_STATE_SIZE};
+    use crate::{
+        error::JoinGameError,
+        lobby::chat::{ChatMessage, CHAT_HISTORY, MAX_CHAT_LENGTH},
+        player_info::PlayerInfo,
+    };
+
+    fn info(port: u16) -> PlayerInfo {
+        PlayerInfo {
+            name: format!("Player {}", port),
+            character: Default::default(),
+            addr: format!("127.0.0.1:{}", port).parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn games_keep_their_id() {
+        let mut lobby_state = LobbyState::new(info(10800));
+        let host = lobby_state.user;
+        let player = lobby_state.player_list.insert(info(10801));
+        let player2 = lobby_state.player_list.insert(info(10802));
+
+        let first = lobby_state.create_game(host).unwrap();
+        let second = lobby_state.create_game(player).unwrap();
+
+        // the first game is removed before the join for the second one is applied
+        lobby_state.leave_game(host).unwrap();
+        assert_eq!(lobby_state.join_game(player2, second), Ok(second));
+        assert_eq!(
+            lobby_state.game(second).unwrap().players(),
+            &[player, player2]
+        );
+
+        // and a game that's gone stays gone, even though there's a game where it was
+        assert_eq!(
+            lobby_state.join_game(host, first),
+            Err(JoinGameError::NoSuchGame)
+        );
+        assert_ne!(lobby_state.create_game(host), Ok(first));
+    }
+
+    #[test]
+    fn full_chat_fits() {
+        let mut lobby_state = LobbyState::new(info(10800));
+        for port in 10801..10816 {
+            lobby_state.player_list.insert(info(port));
+        }
+        // the longest messages there can be, made of the widest chars there are
+        for _ in 0..CHAT_HISTORY {
+            lobby_state.add_chat(ChatMessage::new(
+                lobby_state.user,
+                "\u{1F600}".repeat(MAX_CHAT_LENGTH * 2),
+            ));
+        }
+
+        let size = bincode::serialized_size(&lobby_state).unwrap() as usize;
+        assert!(size < MAX_LOBBY_STATE_SIZE, "{}", size);
+    }
+}
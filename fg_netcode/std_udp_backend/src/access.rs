@@ -0,0 +1,165 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    num::NonZeroU32,
+};
+
+use fg_netcode::{compatibility::Incompatibility, error::JoinLobbyError};
+use ring::{
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::cert::Fingerprint;
+
+/// Who the host lets into the lobby, which is anyone unless either is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LobbyAccess {
+    pub password: Option<String>,
+    /// The identities of the only players that are let in.
+    pub allowed: Option<HashSet<Fingerprint>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Denial {
+    IncorrectPassword,
+    NotAllowed,
+    Banned,
+    Locked,
+    Incompatible(Vec<Incompatibility>),
+}
+
+/// What whoever hosts checks joining players against.
+/// Only a hash of the password is kept, so it can be passed on to everyone that
+/// might take over as host without giving the password itself away.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Admission {
+    password: Option<PasswordHash>,
+    allowed: Option<HashSet<Fingerprint>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PasswordHash {
+    salt: [u8; 16],
+    hash: [u8; 32],
+}
+
+// slows down guessing the password from a copy of the hash
+const PASSWORD_ITERATIONS: u32 = 100_000;
+
+fn password_iterations() -> NonZeroU32 {
+    NonZeroU32::new(PASSWORD_ITERATIONS).unwrap()
+}
+
+impl PasswordHash {
+    fn new(password: &str) -> Self {
+        let mut salt = [0; 16];
+        SystemRandom::new().fill(&mut salt).unwrap();
+        let mut hash = [0; 32];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            password_iterations(),
+            &salt,
+            password.as_bytes(),
+            &mut hash,
+        );
+        Self { salt, hash }
+    }
+
+    /// Takes the same time no matter how much of the password is right.
+    fn verify(&self, password: &str) -> bool {
+        pbkdf2::verify(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            password_iterations(),
+            &self.salt,
+            password.as_bytes(),
+            &self.hash,
+        )
+        .is_ok()
+    }
+}
+
+impl From<LobbyAccess> for Admission {
+    fn from(access: LobbyAccess) -> Self {
+        Self {
+            password: access.password.as_deref().map(PasswordHash::new),
+            allowed: access.allowed,
+        }
+    }
+}
+
+impl Admission {
+    /// `identity` is only set if the player proved they hold its key.
+    pub(crate) fn check(
+        &self,
+        password: Option<&str>,
+        identity: Option<Fingerprint>,
+    ) -> Result<(), Denial> {
+        if let Some(expected) = &self.password {
+            if !expected.verify(password.unwrap_or_default()) {
+                return Err(Denial::IncorrectPassword);
+            }
+        }
+        if let Some(allowed) = &self.allowed {
+            if !identity.map_or(false, |identity| allowed.contains(&identity)) {
+                return Err(Denial::NotAllowed);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Who the host banned this session, which is forgotten once the lobby closes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Bans {
+    identities: HashSet<Fingerprint>,
+    addrs: HashSet<IpAddr>,
+}
+
+impl Bans {
+    pub(crate) fn ban(&mut self, identity: Option<Fingerprint>, addr: IpAddr) {
+        self.identities.extend(identity);
+        self.addrs.insert(addr);
+    }
+
+    /// Banned players stay out whether they come back with a new identity or a new address.
+    pub(crate) fn check(&self, identity: Option<Fingerprint>, addr: IpAddr) -> Result<(), Denial> {
+        let banned_identity =
+            identity.map_or(false, |identity| self.identities.contains(&identity));
+        if banned_identity || self.addrs.contains(&addr) {
+            Err(Denial::Banned)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The bans, and what they're checked against, which every replica keeps
+/// so they still hold after the host that made them leaves.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Moderation {
+    pub(crate) bans: Bans,
+    /// The identity each player proved when they joined, by the address they joined from.
+    pub(crate) identities: HashMap<SocketAddr, Fingerprint>,
+}
+
+impl Moderation {
+    /// Bans the player at `addr`, along with the identity they joined with.
+    pub(crate) fn ban(&mut self, addr: SocketAddr) {
+        self.bans
+            .ban(self.identities.get(&addr).copied(), addr.ip());
+    }
+}
+
+impl From<Denial> for JoinLobbyError {
+    fn from(value: Denial) -> Self {
+        match value {
+            Denial::IncorrectPassword => JoinLobbyError::IncorrectPassword,
+            Denial::NotAllowed => JoinLobbyError::Denied,
+            Denial::Banned => JoinLobbyError::Banned,
+            Denial::Locked => JoinLobbyError::Locked,
+            Denial::Incompatible(differences) => JoinLobbyError::Incompatible(differences),
+        }
+    }
+}
@@ -1,21 +1,25 @@
 mod lobby;
 
-use std::{collections::HashMap, net::SocketAddr};
+use std::collections::HashMap;
 
 use crate::{
+    access::Moderation,
+    cert::IdentityProof,
     connection::{handle_incoming, ConnectionType},
     lobby_state,
-    request::{Disconnected, JoinRequest, JoinResponse},
+    request::{ConnectRequest, Disconnected, JoinRequest, JoinResponse},
     util::{self},
     NetworkingAction, QuinnHandle,
 };
 use fg_netcode::{
+    compatibility::Compatibility,
     error::{HostLobbyError, JoinLobbyError},
-    lobby::{lobby_state::LobbyState, Lobby},
+    lobby::{lobby_state::MAX_LOBBY_STATE_SIZE, Lobby},
     player_info::PlayerInfo,
     NetworkingMessage,
 };
 use futures_util::StreamExt;
+use quinn::NewConnection;
 use tokio::{select, sync::mpsc};
 
 use self::lobby::LobbyBackend;
@@ -57,7 +61,7 @@ impl NetworkBackend {
         select! {
             Some(action) = self.actions.recv() => {
                 match action {
-                    NetworkingAction::Host(_) => self
+                    NetworkingAction::Host(..) => self
                         .messages
                         .send(NetworkingMessage::Host(Err(HostLobbyError::InLobby)))
 
@@ -86,9 +90,10 @@ impl NetworkBackend {
         quinn: &mut QuinnHandle,
     ) -> Option<LobbyBackend> {
         match action {
-            NetworkingAction::Host(info) => {
+            NetworkingAction::Host(info, access, compatibility) => {
                 let result = lobby_state::host(info);
-                let (lobby_interface, backend) = LobbyBackend::new(result);
+                let (lobby_interface, backend) =
+                    LobbyBackend::new(result, access.into(), Moderation::default(), compatibility);
 
                 self.messages
                     .send(NetworkingMessage::Host(Ok(lobby_interface)))
@@ -96,47 +101,72 @@ impl NetworkBackend {
 
                 Some(backend)
             }
-            NetworkingAction::ConnectTo(info, addr) => {
-                if let Ok((interface, backend)) = self.try_connect(addr, info, quinn).await {
-                    self.messages
-                        .send(NetworkingMessage::Join(Ok(interface)))
-                        .unwrap();
-                    Some(backend)
-                } else {
-                    self.messages
-                        .send(NetworkingMessage::Join(Err(JoinLobbyError::Denied)))
-                        .unwrap();
-                    None
+            NetworkingAction::ConnectTo(info, addr, password, compatibility) => {
+                let result = match quinn.connect(addr).await {
+                    Ok((conn, host)) => {
+                        let identity = quinn.identity.prove(host);
+                        self.try_join(conn, info, password, identity, compatibility)
+                            .await
+                    }
+                    Err(err) => Err(err),
+                };
+                match result {
+                    Ok((interface, backend)) => {
+                        self.messages
+                            .send(NetworkingMessage::Join(Ok(interface)))
+                            .unwrap();
+                        Some(backend)
+                    }
+                    Err(err) => {
+                        self.messages
+                            .send(NetworkingMessage::Join(Err(err)))
+                            .unwrap();
+                        None
+                    }
                 }
             }
         }
     }
 
-    async fn try_connect(
+    async fn try_join(
         &mut self,
-        addr: SocketAddr,
+        conn: NewConnection,
         info: PlayerInfo,
-        quinn: &mut QuinnHandle,
-    ) -> Result<(Lobby, LobbyBackend), Disconnected> {
-        println!("pre-conn client for {}", info.name);
-        let conn = quinn.endpoint.connect(&addr, "lobby_server")?.await?;
-        println!("post-conn client for {}", info.name);
-
+        password: Option<String>,
+        identity: IdentityProof,
+        compatibility: Compatibility,
+    ) -> Result<(Lobby, LobbyBackend), JoinLobbyError> {
         let remote_addr = conn.connection.remote_address();
 
-        let (send, recv) = conn.connection.open_bi().await?;
+        let (send, recv) = conn
+            .connection
+            .open_bi()
+            .await
+            .map_err(Disconnected::from)?;
 
         util::write_to(
-            &JoinRequest {
+            &ConnectRequest::Join(JoinRequest {
                 target: remote_addr,
                 info,
-            },
+                password,
+                identity,
+                compatibility: compatibility.clone(),
+            }),
             send,
         )
-        .await?;
-
-        let (this_addr, mut lobby_state) =
-            util::read_from::<(SocketAddr, LobbyState)>(1000, recv).await?;
+        .await
+        .map_err(Disconnected::from)?;
+
+        let (this_addr, mut lobby_state, access, moderation) =
+            match util::read_from::<JoinResponse>(MAX_LOBBY_STATE_SIZE, recv)
+                .await
+                .map_err(Disconnected::from)?
+            {
+                JoinResponse::Accepted(this_addr, lobby_state, access, moderation) => {
+                    (this_addr, lobby_state, access, moderation)
+                }
+                JoinResponse::Denied(denial) => return Err(denial.into()),
+            };
 
         let peer_id = lobby_state.host_id();
 
@@ -149,7 +179,9 @@ impl NetworkBackend {
 
         let result = lobby_state::join(lobby_state);
 
-        let (lobby, mut lobby_backend) = LobbyBackend::new(result);
+        // it matched the host's, so it's what to check for if this player takes over
+        let (lobby, mut lobby_backend) =
+            LobbyBackend::new(result, access, moderation, compatibility);
 
         lobby_backend.attach_peer(conn, peer_id, ConnectionType::PeerToHost);
 
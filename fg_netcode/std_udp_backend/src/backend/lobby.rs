@@ -1,13 +1,24 @@
-use std::{borrow::Borrow, collections::HashMap, ops::Deref};
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    net::SocketAddr,
+    ops::Deref,
+    time::{Duration, Instant},
+};
 
 use crate::{
-    connection::{handle_incoming, ConnectionType, Peer},
+    access::{Admission, Denial, Moderation},
+    connection::{handle_incoming, ConnectionType, MatchData, Peer},
     lobby_state::{LobbyStateAction, LobbyTaskResult},
-    request::{Disconnected, JoinRequest, JoinResponse},
+    request::{ClientPacket, ConnectRequest, Disconnected, HostPacket, JoinResponse, StreamPacket},
     util, QuinnHandle,
 };
 use fg_netcode::{
-    lobby::{lobby_state::LobbyState, Lobby, LobbyAction, LobbyMessage},
+    compatibility::Compatibility,
+    game::{Game, GameAction, GameMessage, Match, MatchMessage, MatchPacket, MatchStart, WhoIs},
+    lobby::{
+        chat::ChatMessage, lobby_state::LobbyState, GameInfo, Lobby, LobbyAction, LobbyMessage,
+    },
     player_info::PlayerInfo,
     player_list::Player,
 };
@@ -18,39 +29,98 @@ use tokio::{
     sync::{broadcast, mpsc, oneshot, watch},
 };
 
+// the request carries the player's certificate along with their info
+const MAX_JOIN_REQUEST_SIZE: usize = 4 * 1024;
+const FRAMES_PER_SECOND: f32 = 60.0;
+// long enough for the start to reach everyone in the game before it begins
+const MATCH_START_DELAY: usize = 60;
+
 pub struct LobbyBackend {
     pub from_frontend: mpsc::Receiver<LobbyAction>,
     pub from_network: mpsc::Receiver<LobbyStateAction>,
 
     pub to_self: mpsc::Sender<LobbyStateAction>,
 
-    pub to_network: broadcast::Sender<LobbyStateAction>,
+    pub to_network: broadcast::Sender<StreamPacket>,
     pub to_local: mpsc::Sender<LobbyStateAction>,
 
     pub lobby_state: watch::Receiver<LobbyState>,
+    // the version of the last update sent out, while the user is host
+    version: u64,
 
     pub connection_list: HashMap<Player, Peer>,
+
+    pub access: Admission,
+    pub compatibility: Compatibility,
+    // every replica keeps its own copy, which its connections send along with snapshots
+    moderation: watch::Receiver<Moderation>,
+    to_moderation: watch::Sender<Moderation>,
+
+    pub from_game: mpsc::Receiver<GameAction>,
+    pub to_game: crossbeam_channel::Sender<GameMessage>,
+    // kept apart from `lobby_state`, so waiting on a change here doesn't skip one elsewhere
+    pub game_state: watch::Receiver<LobbyState>,
+
+    pub to_match: mpsc::UnboundedSender<(Player, MatchData)>,
+    pub from_peers: mpsc::UnboundedReceiver<(Player, MatchData)>,
+    pub active_match: Option<MatchBackend>,
+    // set once a match starts, and cleared once the game is no longer ready,
+    // so a match that just ended doesn't start again before everyone passes
+    pub played: bool,
+}
+
+pub struct MatchBackend {
+    pub players: Vec<Player>,
+    pub outgoing: mpsc::UnboundedReceiver<MatchPacket>,
+    pub messages: crossbeam_channel::Sender<MatchMessage>,
 }
 
 impl LobbyBackend {
-    pub fn new(interface: LobbyTaskResult) -> (Lobby, Self) {
+    pub fn new(
+        interface: LobbyTaskResult,
+        access: Admission,
+        moderation: Moderation,
+        compatibility: Compatibility,
+    ) -> (Lobby, Self) {
         let (to_backend, from_frontend) = mpsc::channel(4);
-
-        let lobby_interface = Lobby::new(interface.state.clone(), interface.recv, to_backend);
+        let (to_game_backend, from_game) = mpsc::channel(4);
+        let (to_game, game_messages) = crossbeam_channel::bounded(4);
+
+        let lobby_interface = Lobby::new(
+            interface.state.clone(),
+            interface.recv,
+            to_backend,
+            Game::new(to_game_backend, game_messages),
+        );
 
         let (to_network, _) = broadcast::channel(4);
         let (to_self, from_network) = mpsc::channel(4);
+        let (to_match, from_peers) = mpsc::unbounded_channel();
+        let version = interface.state.borrow().version;
+        let (to_moderation, moderation) = watch::channel(moderation);
 
         (
             lobby_interface,
             LobbyBackend {
                 connection_list: HashMap::new(),
+                access,
+                compatibility,
+                moderation,
+                to_moderation,
                 from_frontend,
                 from_network,
                 to_self,
                 to_network,
                 to_local: interface.actions,
+                version,
+                game_state: interface.state.clone(),
                 lobby_state: interface.state,
+                from_game,
+                to_game,
+                to_match,
+                from_peers,
+                active_match: None,
+                played: false,
             },
         )
     }
@@ -67,9 +137,11 @@ impl LobbyBackend {
                 conn,
                 peer_id,
                 self.lobby_state.clone(),
+                self.moderation.clone(),
                 connection_type,
                 self.to_self.clone(),
                 self.to_network.subscribe(),
+                self.to_match.clone(),
             ),
         );
     }
@@ -79,57 +151,112 @@ impl LobbyBackend {
         &self.connection_list[&host]
     }
 
-    async fn in_game(&mut self, quinn: &mut QuinnHandle) {
-        // select! {
-        //     Some(action) = self.local_actions.recv() => self.handle_action_in_game(action).await,
-        //     Some(incoming) = quinn.incoming.next() => self.handle_incoming(incoming).await.unwrap(),
-        //     _ = self.lobby_state.changed() => (),
-        // }
-    }
-
-    async fn handle_incoming(&mut self, incoming: Connecting) -> Result<(), Disconnected> {
+    async fn handle_incoming(
+        &mut self,
+        incoming: Connecting,
+        quinn: &mut QuinnHandle,
+    ) -> Result<(), Disconnected> {
         println!("pre incoming: {}", incoming.remote_address());
         let mut conn = incoming.await?;
         let remote_addr = conn.connection.remote_address();
         println!("post incoming: {}", remote_addr);
         let lobby_state = { self.lobby_state.borrow().clone() };
-
-        if lobby_state.is_user_host() {
-            let (join_response, join_request) =
-                conn.bi_streams.next().await.ok_or(Disconnected)??;
-
-            let request = util::read_from::<JoinRequest>(1000, join_request).await?;
-
-            self.to_local
-                .send(LobbyStateAction::UpdateAddr(
+        let (response, request) = conn.bi_streams.next().await.ok_or(Disconnected)??;
+
+        match util::read_from::<ConnectRequest>(MAX_JOIN_REQUEST_SIZE, request).await? {
+            ConnectRequest::Join(request) if lobby_state.is_user_host() => {
+                let identity = request.identity.verify(quinn.identity.fingerprint());
+                // checked first, so a player that can't play here anyway isn't asked for more
+                let admitted = self
+                    .compatibility
+                    .check(&request.compatibility)
+                    .map_err(Denial::Incompatible)
+                    .and_then(|()| {
+                        self.moderation
+                            .borrow()
+                            .bans
+                            .check(identity, remote_addr.ip())
+                    })
+                    .and_then(|()| {
+                        if lobby_state.locked {
+                            Err(Denial::Locked)
+                        } else {
+                            self.access.check(request.password.as_deref(), identity)
+                        }
+                    });
+                if let Err(denial) = admitted {
+                    util::write_to(&JoinResponse::Denied(denial), response).await?;
+                    return Ok(());
+                }
+
+                self.to_local
+                    .send(LobbyStateAction::UpdateAddr(
+                        lobby_state.user,
+                        request.target,
+                    ))
+                    .await
+                    .unwrap();
+
+                self.lobby_state.changed().await.unwrap();
+
+                self.broadcast(LobbyStateAction::UpdateAddr(
                     lobby_state.user,
                     request.target,
-                ))
-                .await
-                .unwrap();
-
-            self.lobby_state.changed().await.unwrap();
-
-            let _ = self.to_network.send(LobbyStateAction::UpdateAddr(
-                lobby_state.user,
-                request.target,
-            ));
-
-            let info = PlayerInfo {
-                addr: remote_addr,
-                ..request.info
-            };
-
-            self.to_local
-                .send(LobbyStateAction::NewPlayer(info.clone()))
-                .await
-                .unwrap();
-            self.lobby_state.changed().await.unwrap();
-            let _ = self.to_network.send(LobbyStateAction::NewPlayer(info));
-
-            let lobby_state = { self.lobby_state.borrow().clone() };
-
-            util::write_to(&(remote_addr, lobby_state), join_response).await?;
+                ));
+
+                let info = PlayerInfo {
+                    addr: remote_addr,
+                    ..request.info
+                };
+
+                if let Some(identity) = identity {
+                    self.moderate(|moderation| {
+                        moderation.identities.insert(remote_addr, identity);
+                    });
+                }
+                self.to_local
+                    .send(LobbyStateAction::NewPlayer(info.clone(), identity))
+                    .await
+                    .unwrap();
+                self.lobby_state.changed().await.unwrap();
+                self.broadcast(LobbyStateAction::NewPlayer(info, identity));
+
+                let lobby_state = { self.lobby_state.borrow().clone() };
+                let moderation = { self.moderation.borrow().clone() };
+                util::write_to(
+                    &JoinResponse::Accepted(
+                        remote_addr,
+                        lobby_state,
+                        self.access.clone(),
+                        moderation,
+                    ),
+                    response,
+                )
+                .await?;
+            }
+            ConnectRequest::Rejoin(proof) => {
+                // only players this replica has no connection to are let back in, like everyone
+                // moving over to the new host after the last one left,
+                // and only if they prove they're who they joined as
+                let identity = proof.verify(quinn.identity.fingerprint());
+                let expected = {
+                    self.moderation
+                        .borrow()
+                        .identities
+                        .get(&remote_addr)
+                        .copied()
+                };
+                let connected = lobby_state
+                    .player_list
+                    .find_key(|info| info.addr == remote_addr)
+                    .map_or(false, |player| self.connection_list.contains_key(&player));
+                if identity.is_none() || identity != expected || connected {
+                    conn.connection.close(0u16.into(), b"unknown player");
+                    return Err(Disconnected);
+                }
+            }
+            // only the host lets anyone new in
+            ConnectRequest::Join(_) => return Err(Disconnected),
         }
 
         let lobby_state = { self.lobby_state.borrow().clone() };
@@ -151,92 +278,356 @@ impl LobbyBackend {
 
     async fn handle_incoming_packet(
         &mut self,
-        incoming: LobbyStateAction,
+        mut incoming: LobbyStateAction,
     ) -> Result<(), Disconnected> {
         let is_host = { self.lobby_state.borrow().is_user_host() };
 
+        match &mut incoming {
+            LobbyStateAction::Chat(message) if is_host => {
+                // everyone sees the same time, no matter whose clock it came from,
+                // and the text is cut off again in case the sender's build didn't
+                *message = ChatMessage::new(message.sender, std::mem::take(&mut message.text));
+            }
+            // every replica keeps track of bans, so they still hold if it takes over as host
+            LobbyStateAction::Ban(by, target) => self.ban(*by, *target),
+            LobbyStateAction::NewPlayer(info, Some(identity)) => {
+                let (addr, identity) = (info.addr, *identity);
+                self.moderate(|moderation| {
+                    moderation.identities.insert(addr, identity);
+                });
+            }
+            LobbyStateAction::Snapshot(_, moderation) => {
+                let _ = self.to_moderation.send(std::mem::take(moderation));
+            }
+            _ => (),
+        }
+
         println!("is_host: {}, handling: {:?}", is_host, incoming);
 
-        self.to_local.send(incoming.clone()).await.unwrap();
+        // a player that was removed has nothing left to apply actions to
+        self.to_local
+            .send(incoming.clone())
+            .await
+            .map_err(|_| Disconnected)?;
         if is_host {
-            let _ = self.to_network.send(incoming);
+            self.broadcast(incoming);
         }
 
         Ok(())
     }
 
-    async fn handle_action_in_game(&mut self, action: LobbyAction) {
+    /// Numbers an action the host applied, and passes it on to everyone else.
+    fn broadcast(&mut self, action: LobbyStateAction) {
+        self.version += 1;
+        let _ = self
+            .to_network
+            .send(StreamPacket::Host(HostPacket::Update(self.version, action)));
+    }
+
+    fn moderate(&mut self, change: impl FnOnce(&mut Moderation)) {
+        let mut moderation = { self.moderation.borrow().clone() };
+        change(&mut moderation);
+        let _ = self.to_moderation.send(moderation);
+    }
+
+    // only bans that will go through are kept, so nobody else can get a player banned
+    fn ban(&mut self, by: Player, target: Player) {
+        let addr = {
+            let lobby_state = self.lobby_state.borrow();
+            if lobby_state.can_remove(by, target).is_err() {
+                return;
+            }
+            lobby_state.player_list.get(target).map(|info| info.addr)
+        };
+        if let Some(addr) = addr {
+            self.moderate(|moderation| moderation.ban(addr));
+        }
+    }
+
+    async fn handle_network_packet(
+        &mut self,
+        incoming: LobbyStateAction,
+        quinn: &mut QuinnHandle,
+    ) -> Result<(), Disconnected> {
+        match incoming {
+            LobbyStateAction::HostLeft(host) => self.migrate_host(host, quinn).await,
+            incoming => self.handle_incoming_packet(incoming).await,
+        }
+    }
+
+    /// Hands the lobby over to the next player in `PlayerList` order once the host is gone.
+    /// Games carry on as they were, except for the one the old host was in.
+    async fn migrate_host(
+        &mut self,
+        old_host: Player,
+        quinn: &mut QuinnHandle,
+    ) -> Result<(), Disconnected> {
+        if { self.lobby_state.borrow().host_id() } != old_host {
+            return Ok(());
+        }
+
+        self.connection_list.remove(&old_host);
+        self.to_local
+            .send(LobbyStateAction::HostLeft(old_host))
+            .await
+            .map_err(|_| Disconnected)?;
+
+        let lobby_state = loop {
+            let lobby_state = { self.lobby_state.borrow().clone() };
+            if lobby_state.host_id() != old_host {
+                break lobby_state;
+            }
+            self.lobby_state.changed().await.map_err(|_| Disconnected)?;
+        };
+
+        let host = lobby_state.host_id();
+        // the new host numbers its updates from its own replica,
+        // which everyone else resyncs to as soon as they switch over to it
+        self.version = lobby_state.version;
+
+        // connections we already have, like the ones made for a match,
+        // switch over to or from the host on their own when the lobby state changes
+        if lobby_state.is_user_host() || self.connection_list.contains_key(&host) {
+            return Ok(());
+        }
+
+        let addr = lobby_state.player_list.get(host).ok_or(Disconnected)?.addr;
+        match connect(quinn, addr).await {
+            Ok(conn) => {
+                self.attach_peer(conn, host, ConnectionType::PeerToHost);
+                Ok(())
+            }
+            Err(err) => {
+                // everyone else elects the same host, so there's nobody else to go to
+                let _ = self.to_local.send(LobbyStateAction::Kill).await;
+                Err(err)
+            }
+        }
+    }
+
+    /// Sends an action to the host, who applies it and passes it on to everyone else.
+    async fn submit(&mut self, action: LobbyStateAction) -> Result<(), Disconnected> {
+        let is_host = { self.lobby_state.borrow().is_user_host() };
+
+        if is_host {
+            self.handle_incoming_packet(action).await
+        } else {
+            let _ = self
+                .to_network
+                .send(StreamPacket::Client(ClientPacket::Action(action)));
+            Ok(())
+        }
+    }
+
+    async fn handle_game_action(&mut self, action: GameAction) -> Result<(), Disconnected> {
+        let user = { self.lobby_state.borrow().user };
+
         match action {
-            LobbyAction::CreateGame => {}
-            LobbyAction::JoinGame(_) => {}
-            LobbyAction::UpdatePlayerInfo(_) => {}
+            GameAction::Ready => self.submit(LobbyStateAction::Ready(user, true)).await,
+            GameAction::Pass => self.submit(LobbyStateAction::Ready(user, false)).await,
+        }
+    }
+
+    async fn check_ready(&mut self, quinn: &mut QuinnHandle) -> Result<(), Disconnected> {
+        let lobby_state = { self.game_state.borrow().clone() };
+
+        if lobby_state.is_user_host() {
+            self.confirm_ready_games(&lobby_state).await?;
+        }
+
+        let game = lobby_state
+            .games
+            .iter()
+            .find(|game| game.contains(&lobby_state.user));
+
+        match game.and_then(|game| Some((game, game.start?))) {
+            Some((game, start)) => {
+                if self.active_match.is_none() && !self.played {
+                    self.start_match(game.clone(), start, &lobby_state, quinn)
+                        .await?;
+                }
+            }
+            _ => self.played = false,
+        }
+
+        Ok(())
+    }
+
+    // the host starts every game that's ready, whether or not it's in them
+    async fn confirm_ready_games(&mut self, lobby_state: &LobbyState) -> Result<(), Disconnected> {
+        for game in lobby_state.games.iter() {
+            if !game.all_ready() || game.start.is_some() {
+                continue;
+            }
+            let start = MatchStart {
+                characters: game
+                    .players()
+                    .iter()
+                    .map(|player| {
+                        lobby_state
+                            .player_list
+                            .get(*player)
+                            .map(|info| info.character)
+                            .unwrap_or_default()
+                    })
+                    .collect(),
+                first_to: game.first_to,
+                start_frame: MATCH_START_DELAY,
+            };
+            self.submit(LobbyStateAction::StartGame(game.id, start))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn start_match(
+        &mut self,
+        game: GameInfo,
+        mut start: MatchStart,
+        lobby_state: &LobbyState,
+        quinn: &mut QuinnHandle,
+    ) -> Result<(), Disconnected> {
+        let started_at = Instant::now();
+        // the start took about half a round trip to get here from the host
+        let travel_time = if lobby_state.is_user_host() {
+            Duration::from_secs(0)
+        } else {
+            self.connection_list
+                .get(&lobby_state.host_id())
+                .map(|host| host.connection.rtt() / 2)
+                .unwrap_or_default()
+        };
+
+        // members connect to everyone seated before them that they don't already have
+        // a connection to, and are connected to by everyone after
+        let members = game.members();
+        let seat = members
+            .iter()
+            .position(|player| *player == lobby_state.user)
+            .ok_or(Disconnected)?;
+
+        for peer in members[..seat].iter() {
+            if self.connection_list.contains_key(peer) {
+                continue;
+            }
+            let addr = lobby_state.player_list.get(*peer).ok_or(Disconnected)?.addr;
+            let conn = connect(quinn, addr).await?;
+            self.attach_peer(conn, *peer, ConnectionType::PeerToPeer);
+        }
+
+        let (outgoing_tx, outgoing) = mpsc::unbounded_channel();
+        let (messages, messages_rx) = crossbeam_channel::unbounded();
+
+        self.active_match = Some(MatchBackend {
+            players: members.clone(),
+            outgoing,
+            messages,
+        });
+        self.played = true;
+
+        let elapsed = travel_time + started_at.elapsed();
+        start.start_frame = start
+            .start_frame
+            .saturating_sub((elapsed.as_secs_f32() * FRAMES_PER_SECOND).round() as usize);
+
+        let _ = self.to_game.send(GameMessage::PlayersReady(
+            Match::new(members, lobby_state.user, outgoing_tx, messages_rx),
+            start,
+        ));
+
+        Ok(())
+    }
+
+    async fn handle_match_packet(
+        &mut self,
+        packet: Option<MatchPacket>,
+    ) -> Result<(), Disconnected> {
+        match packet {
+            Some(MatchPacket { to, data, reliable }) => {
+                if let Some(peer) = self.connection_list.get(&to) {
+                    if reliable {
+                        let _ = peer.control.send(data);
+                    } else {
+                        // too large or unsupported datagrams are dropped, the same as lost ones
+                        let _ = peer.connection.send_datagram(data);
+                    }
+                }
+                Ok(())
+            }
+            None => {
+                // the frontend dropped the match, so it's over
+                self.active_match = None;
+                self.handle_game_action(GameAction::Pass).await
+            }
+        }
+    }
+
+    fn handle_match_data(&mut self, player: Player, data: MatchData) {
+        if let MatchData::Disconnected = data {
+            self.connection_list.remove(&player);
+        }
+
+        if let Some(active_match) = &self.active_match {
+            if !active_match.players.contains(&player) {
+                return;
+            }
+            let who = WhoIs::from_seat(&active_match.players, player);
+            let message = match data {
+                MatchData::Datagram(data) => MatchMessage::Packet(who, data),
+                MatchData::Control(data) => MatchMessage::Control(who, data),
+                MatchData::Disconnected => MatchMessage::Disconnected(who),
+            };
+            let _ = active_match.messages.send(message);
         }
     }
 
     pub(crate) async fn main_loop(&mut self, quinn: &mut QuinnHandle) {
-        dbg!("main");
+        let active_match = &mut self.active_match;
         select! {
-            Some(incoming) = quinn.incoming.next() => self.handle_incoming(incoming).await.ok(),
-            // Some(action) = self.from_frontend.recv() => self.handle_action(action, None).await,
-            Some(incoming) = self.from_network.recv() => self.handle_incoming_packet(incoming).await.ok(),
+            Some(incoming) = quinn.incoming.next() => self.handle_incoming(incoming, quinn).await.ok(),
+            Some(action) = self.from_frontend.recv() => self.handle_action(action).await.ok(),
+            Some(incoming) = self.from_network.recv() => self.handle_network_packet(incoming, quinn).await.ok(),
+            Some(action) = self.from_game.recv() => self.handle_game_action(action).await.ok(),
+            Ok(()) = self.game_state.changed() => self.check_ready(quinn).await.ok(),
+            Some((player, data)) = self.from_peers.recv() => {
+                self.handle_match_data(player, data);
+                Some(())
+            }
+            packet = async { active_match.as_mut().unwrap().outgoing.recv().await }, if active_match.is_some() => {
+                self.handle_match_packet(packet).await.ok()
+            }
             else => None,
         };
     }
 
-    async fn handle_action(&mut self, action: LobbyAction, user: Option<Player>) -> Option<()> {
-        // let is_host = self.lobby_state.borrow().is_user_host();
-        // let user = user.unwrap_or_else(|| self.lobby_state.borrow().user);
+    async fn handle_action(&mut self, action: LobbyAction) -> Result<(), Disconnected> {
+        let user = { self.lobby_state.borrow().user };
+
+        let action = match action {
+            LobbyAction::CreateGame => LobbyStateAction::CreateGame(user),
+            LobbyAction::JoinGame(game) => LobbyStateAction::JoinGame(user, game),
+            LobbyAction::SpectateGame(game) => LobbyStateAction::SpectateGame(user, game),
+            LobbyAction::LeaveGame => LobbyStateAction::LeaveGame(user),
+            LobbyAction::UpdatePlayerInfo(info) => LobbyStateAction::UpdatePlayer(user, info),
+            LobbyAction::Chat(text) => LobbyStateAction::Chat(ChatMessage::new(user, text)),
+            LobbyAction::Kick(player) => LobbyStateAction::Kick(user, player),
+            LobbyAction::Ban(player) => LobbyStateAction::Ban(user, player),
+            LobbyAction::Lock(locked) => LobbyStateAction::Lock(user, locked),
+        };
 
-        match action {
-            LobbyAction::CreateGame => {
-                // let (send, recv) = oneshot::channel();
-
-                // self.lobby_state
-                //     .actions
-                //     .send(LobbyStateAction::CreateGame(user, send))
-                //     .await
-                //     .ok()?;
-
-                // if is_host {
-                //     self.messages
-                //         .send(LobbyMessage::CreateGame(recv.await.ok()?))
-                //         .ok()?;
-                // } else {
-                //     send to
-                // }
-            }
-            LobbyAction::JoinGame(game) => {
-                // let (send, recv) = oneshot::channel();
-
-                // self.lobby_state
-                //     .actions
-                //     .send(LobbyStateAction::JoinGame(game, user, send))
-                //     .await
-                //     .ok()?;
-
-                // if is_host {
-                //     self.messages
-                //         .send(LobbyMessage::JoinGame(recv.await.ok()?))
-                //         .ok()?;
-                // }
-            }
-            LobbyAction::UpdatePlayerInfo(info) => {
-                // self.lobby_state
-                //     .actions
-                //     .send(LobbyStateAction::UpdatePlayer(user, info.clone()))
-                //     .await
-                //     .ok()?;
-
-                // if !is_host {
-                //     self.host_peer()
-                //         .lobby_action
-                //         .send(LobbyAction::UpdatePlayerInfo(info))
-                //         .await
-                //         .unwrap();
-                // }
-            }
-        }
-
-        None
+        self.submit(action).await
     }
 }
+
+/// Connects to someone already in the lobby, proving this is the player they know.
+async fn connect(quinn: &mut QuinnHandle, addr: SocketAddr) -> Result<NewConnection, Disconnected> {
+    let (conn, fingerprint) = quinn.connect(addr).await.map_err(|_| Disconnected)?;
+    let (send, _) = conn.connection.open_bi().await?;
+    util::write_to(
+        &ConnectRequest::Rejoin(quinn.identity.prove(fingerprint)),
+        send,
+    )
+    .await?;
+    Ok(conn)
+}
@@ -1,33 +1,222 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    fs::File,
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-use quinn::{ClientConfig, ClientConfigBuilder};
+use quinn::{
+    Certificate, CertificateChain, ClientConfig, ClientConfigBuilder, PrivateKey, ServerConfig,
+    ServerConfigBuilder, TransportConfig,
+};
+use ring::{
+    rand::SystemRandom,
+    signature::{EcdsaKeyPair, ECDSA_P256_SHA256_ASN1_SIGNING},
+};
+use serde::{Deserialize, Serialize};
 
-struct SkipServerVerification;
+/// The certificate and key an installation uses for every session,
+/// so anyone that connected before can tell it's the same host.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Identity {
+    cert: Vec<u8>,
+    key: Vec<u8>,
+}
+
+impl Identity {
+    pub fn generate() -> Self {
+        let cert = rcgen::generate_simple_self_signed(vec!["lobby_server".into()]).unwrap();
+        Self {
+            cert: cert.serialize_der().unwrap(),
+            key: cert.serialize_private_key_der(),
+        }
+    }
+
+    /// Loads the identity at `path`, generating and saving a new one if there isn't one yet.
+    pub fn load_or_generate(path: &Path) -> io::Result<Self> {
+        match File::open(path) {
+            Ok(file) => bincode::deserialize_from(file)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let identity = Self::generate();
+                bincode::serialize_into(File::create(path)?, &identity)
+                    .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+                Ok(identity)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Stays the same across sessions, so it can stand in for who the player is.
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint::of(&self.cert)
+    }
+
+    /// Signs the host's fingerprint, so the proof is no good for joining anyone else.
+    pub(crate) fn prove(&self, host: Fingerprint) -> IdentityProof {
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &self.key).unwrap();
+        let signature = key.sign(&SystemRandom::new(), &host.0).unwrap();
+        IdentityProof {
+            cert: self.cert.clone(),
+            signature: signature.as_ref().to_vec(),
+        }
+    }
+
+    pub(crate) fn configure_server(&self) -> ServerConfig {
+        let mut server_config = ServerConfigBuilder::default();
+        let mut transport_config = TransportConfig::default();
+        transport_config.keep_alive_interval(Some(Duration::from_secs(2)));
+        transport_config
+            .max_idle_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        server_config
+            .certificate(
+                CertificateChain::from_certs(std::iter::once(
+                    Certificate::from_der(&self.cert).unwrap(),
+                )),
+                PrivateKey::from_der(&self.key).unwrap(),
+            )
+            .unwrap();
+
+        server_config.build()
+    }
+}
+
+/// Shows that a joining player holds the key to their identity.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct IdentityProof {
+    cert: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl IdentityProof {
+    /// The fingerprint of whoever signed for `host`, if the signature holds up.
+    pub(crate) fn verify(&self, host: Fingerprint) -> Option<Fingerprint> {
+        webpki::EndEntityCert::from(&self.cert)
+            .ok()?
+            .verify_signature(&webpki::ECDSA_P256_SHA256, &host.0, &self.signature)
+            .ok()?;
+        Some(Fingerprint::of(&self.cert))
+    }
+}
+
+/// The SHA-256 hash of a certificate.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint([u8; 32]);
 
-impl SkipServerVerification {
-    fn new() -> Arc<Self> {
-        Arc::new(Self)
+impl Fingerprint {
+    pub fn of(cert: &[u8]) -> Self {
+        let mut fingerprint = [0; 32];
+        fingerprint.copy_from_slice(ring::digest::digest(&ring::digest::SHA256, cert).as_ref());
+        Self(fingerprint)
     }
 }
 
-impl rustls::ServerCertVerifier for SkipServerVerification {
+impl Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// The fingerprint each host had the first time it was connected to.
+#[derive(Default)]
+pub struct KnownHosts {
+    path: Option<PathBuf>,
+    hosts: HashMap<SocketAddr, Fingerprint>,
+}
+
+impl KnownHosts {
+    /// Loads the hosts saved at `path`, which every newly pinned host is saved to.
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let hosts = match File::open(&path) {
+            Ok(file) => bincode::deserialize_from(file)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            path: Some(path),
+            hosts,
+        })
+    }
+
+    pub fn get(&self, addr: &SocketAddr) -> Option<Fingerprint> {
+        self.hosts.get(addr).copied()
+    }
+
+    pub fn pin(&mut self, addr: SocketAddr, fingerprint: Fingerprint) {
+        self.hosts.insert(addr, fingerprint);
+        if let Some(path) = &self.path {
+            // a pin that can't be saved still holds for the rest of the session
+            if let Ok(file) = File::create(path) {
+                let _ = bincode::serialize_into(file, &self.hosts);
+            }
+        }
+    }
+}
+
+/// Accepts whatever certificate the host has the first time, and only that one after.
+pub(crate) struct PinnedServerVerification {
+    pinned: Option<Fingerprint>,
+    presented: Mutex<Option<Fingerprint>>,
+}
+
+impl PinnedServerVerification {
+    fn new(pinned: Option<Fingerprint>) -> Arc<Self> {
+        Arc::new(Self {
+            pinned,
+            presented: Mutex::new(None),
+        })
+    }
+
+    /// The fingerprint the host presented, once the handshake has gotten that far.
+    pub(crate) fn presented(&self) -> Option<Fingerprint> {
+        *self.presented.lock().unwrap()
+    }
+}
+
+impl rustls::ServerCertVerifier for PinnedServerVerification {
     fn verify_server_cert(
         &self,
         _roots: &rustls::RootCertStore,
-        _presented_certs: &[rustls::Certificate],
+        presented_certs: &[rustls::Certificate],
         _dns_name: webpki::DNSNameRef,
         _ocsp_response: &[u8],
     ) -> Result<rustls::ServerCertVerified, rustls::TLSError> {
-        Ok(rustls::ServerCertVerified::assertion())
+        let fingerprint = Fingerprint::of(
+            &presented_certs
+                .first()
+                .ok_or(rustls::TLSError::NoCertificatesPresented)?
+                .0,
+        );
+        *self.presented.lock().unwrap() = Some(fingerprint);
+
+        match self.pinned {
+            Some(pinned) if pinned != fingerprint => Err(rustls::TLSError::General(
+                "the host's certificate changed since it was pinned".to_string(),
+            )),
+            _ => Ok(rustls::ServerCertVerified::assertion()),
+        }
     }
 }
 
-pub fn configure_client() -> ClientConfig {
+pub(crate) fn configure_client(
+    pinned: Option<Fingerprint>,
+) -> (ClientConfig, Arc<PinnedServerVerification>) {
+    let verifier = PinnedServerVerification::new(pinned);
     let mut cfg = ClientConfigBuilder::default().build();
     let tls_cfg: &mut rustls::ClientConfig = Arc::get_mut(&mut cfg.crypto).unwrap();
     // this is only available when compiled with "dangerous_configuration" feature
     tls_cfg
         .dangerous()
-        .set_certificate_verifier(SkipServerVerification::new());
-    cfg
+        .set_certificate_verifier(verifier.clone());
+    (cfg, verifier)
 }
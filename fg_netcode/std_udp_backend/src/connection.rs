@@ -1,11 +1,13 @@
 use crate::{
+    access::Moderation,
     lobby_state::LobbyStateAction,
-    request::{ClientPacket, Disconnected},
+    request::{ClientPacket, Disconnected, HostPacket, StreamPacket},
     util,
 };
 use bytes::Bytes;
 use fg_netcode::{
-    lobby::{lobby_state::LobbyState, LobbyAction},
+    game::MAX_RELIABLE_SIZE,
+    lobby::lobby_state::{LobbyState, MAX_LOBBY_STATE_SIZE},
     player_list::Player,
 };
 use futures_util::StreamExt;
@@ -19,30 +21,54 @@ use tokio::{
     task::JoinHandle,
 };
 
+// the most that is read from a single uni stream between peers, which only carry match data,
+// with room for the packet's variant tag and length prefix
+const MAX_CONTROL_SIZE: usize = MAX_RELIABLE_SIZE + 16;
+
 pub struct Peer {
     pub task: JoinHandle<()>,
+    pub connection: Connection,
+    /// Match data to be sent reliably, in the order it's queued.
+    pub control: mpsc::UnboundedSender<Bytes>,
+}
+
+/// Match data that arrived from a peer, or notice that they're gone.
+#[derive(Debug)]
+pub enum MatchData {
+    Datagram(Bytes),
+    Control(Bytes),
+    Disconnected,
 }
 
 pub fn handle_incoming(
     conn: NewConnection,
     peer_id: Player,
     lobby_state: watch::Receiver<LobbyState>,
+    moderation: watch::Receiver<Moderation>,
     connection_type: ConnectionType,
     incoming: mpsc::Sender<LobbyStateAction>,
-    outgoing: broadcast::Receiver<LobbyStateAction>,
+    outgoing: broadcast::Receiver<StreamPacket>,
+    match_data: mpsc::UnboundedSender<(Player, MatchData)>,
 ) -> Peer {
+    let (control, outgoing_control) = mpsc::unbounded_channel();
+    let connection = conn.connection.clone();
     let back_conn = BackendConnection::new(
         conn,
         peer_id,
         lobby_state,
+        moderation,
         incoming,
         outgoing,
+        match_data,
+        outgoing_control,
         connection_type,
     );
     Peer {
         task: tokio::spawn(async move {
             let _ = main_loop(back_conn).await;
         }),
+        connection,
+        control,
     }
 }
 
@@ -53,12 +79,21 @@ pub struct BackendConnection {
     datagrams: Datagrams,
 
     incoming: mpsc::Sender<LobbyStateAction>,
-    outgoing: broadcast::Receiver<LobbyStateAction>,
+    outgoing: broadcast::Receiver<StreamPacket>,
+
+    match_data: mpsc::UnboundedSender<(Player, MatchData)>,
+    outgoing_control: mpsc::UnboundedReceiver<Bytes>,
 
     lobby_state: watch::Receiver<LobbyState>,
+    moderation: watch::Receiver<Moderation>,
 
     peer_id: Player,
     connection_type: ConnectionType,
+
+    // the version of the last update from the host that was passed on
+    version: u64,
+    // set once a snapshot has been asked for, so it's only asked for once
+    resyncing: bool,
 }
 
 impl ConnectionType {
@@ -78,10 +113,14 @@ impl BackendConnection {
         conn: NewConnection,
         peer_id: Player,
         lobby_state: watch::Receiver<LobbyState>,
+        moderation: watch::Receiver<Moderation>,
         incoming: mpsc::Sender<LobbyStateAction>,
-        outgoing: broadcast::Receiver<LobbyStateAction>,
+        outgoing: broadcast::Receiver<StreamPacket>,
+        match_data: mpsc::UnboundedSender<(Player, MatchData)>,
+        outgoing_control: mpsc::UnboundedReceiver<Bytes>,
         connection_type: ConnectionType,
     ) -> Self {
+        let version = lobby_state.borrow().version;
         Self {
             connection: conn.connection,
             uni_streams: conn.uni_streams,
@@ -90,14 +129,52 @@ impl BackendConnection {
             peer_id,
             incoming,
             outgoing,
+            match_data,
+            outgoing_control,
             lobby_state,
+            moderation,
             connection_type,
+            version,
+            resyncing: false,
+        }
+    }
+
+    async fn update_connection_type(&mut self) -> Result<(), Disconnected> {
+        let connection_type = {
+            let lobby_state = self.lobby_state.borrow();
+            ConnectionType::from_peer(self.peer_id, &lobby_state)
+        };
+        if connection_type != self.connection_type {
+            self.connection_type = connection_type;
+            self.sync_with_host().await?;
+        }
+        Ok(())
+    }
+
+    /// Starts the peer off from the host's whole lobby, once either side starts treating this
+    /// as the connection to the host. A new host's replica can be ahead of or behind the peer's,
+    /// so its numbering only lines up after that. Both sides do it, since either can notice first.
+    async fn sync_with_host(&mut self) -> Result<(), Disconnected> {
+        match self.connection_type {
+            ConnectionType::PeerToHost => self.resync().await,
+            ConnectionType::HostToPeer => self.send_snapshot().await,
+            ConnectionType::PeerToPeer => Ok(()),
         }
     }
 
-    fn update_connection_type(&mut self) {
-        let lobby_state = self.lobby_state.borrow();
-        self.connection_type = ConnectionType::from_peer(self.peer_id, &lobby_state);
+    /// Skips any updates from the host until the whole lobby it asks for arrives.
+    async fn resync(&mut self) -> Result<(), Disconnected> {
+        self.resyncing = true;
+        self.send(&StreamPacket::Client(ClientPacket::Resync)).await
+    }
+
+    async fn send_snapshot(&mut self) -> Result<(), Disconnected> {
+        let snapshot = { self.lobby_state.borrow().clone() };
+        let moderation = { self.moderation.borrow().clone() };
+        self.send(&StreamPacket::Host(HostPacket::Snapshot(
+            snapshot, moderation,
+        )))
+        .await
     }
 
     async fn handle_uni(
@@ -106,17 +183,77 @@ impl BackendConnection {
     ) -> Result<(), Disconnected> {
         let stream = stream.ok_or(Disconnected)??;
 
-        match self.connection_type {
-            ConnectionType::HostToPeer | ConnectionType::PeerToHost => {
-                let value = util::read_from::<LobbyStateAction>(1000, stream).await?;
-                self.incoming.send(value).await.map_err(|_| Disconnected)?;
+        // the host can send the whole lobby, and anything to or from the host can carry chat
+        let size_limit = match self.connection_type {
+            ConnectionType::PeerToHost | ConnectionType::HostToPeer => MAX_LOBBY_STATE_SIZE,
+            ConnectionType::PeerToPeer => MAX_CONTROL_SIZE,
+        };
+
+        match util::read_from::<StreamPacket>(size_limit, stream).await? {
+            StreamPacket::Client(packet) => match self.connection_type {
+                ConnectionType::HostToPeer => self.handle_client_packet(packet).await?,
+                ConnectionType::PeerToHost | ConnectionType::PeerToPeer => {}
+            },
+            StreamPacket::Host(packet) => match self.connection_type {
+                ConnectionType::PeerToHost => self.handle_host_packet(packet).await?,
+                ConnectionType::HostToPeer | ConnectionType::PeerToPeer => {}
+            },
+            StreamPacket::Match(data) => {
+                let _ = self
+                    .match_data
+                    .send((self.peer_id, MatchData::Control(data.into())));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_client_packet(&mut self, packet: ClientPacket) -> Result<(), Disconnected> {
+        match packet {
+            // peers can only act for themselves, and never for the host
+            ClientPacket::Action(action) => {
+                if action.actor() == Some(self.peer_id) {
+                    self.incoming.send(action).await.map_err(|_| Disconnected)?;
+                }
+            }
+            // anything the snapshot already has is skipped by the peer when it arrives
+            ClientPacket::Resync => self.send_snapshot().await?,
+        }
+
+        Ok(())
+    }
+
+    async fn handle_host_packet(&mut self, packet: HostPacket) -> Result<(), Disconnected> {
+        match packet {
+            // anything already applied, or that the snapshot on its way will have, is skipped
+            HostPacket::Update(version, _) if self.resyncing || version <= self.version => {}
+            HostPacket::Update(version, action) if version == self.version + 1 => {
+                self.version = version;
+                self.incoming.send(action).await.map_err(|_| Disconnected)?;
+            }
+            HostPacket::Update(..) => {
+                // an update went missing, so nothing after it can be applied until it's caught up
+                self.resync().await?;
+            }
+            HostPacket::Snapshot(snapshot, moderation) => {
+                self.version = snapshot.version;
+                self.resyncing = false;
+                self.incoming
+                    .send(LobbyStateAction::Snapshot(Box::new(snapshot), moderation))
+                    .await
+                    .map_err(|_| Disconnected)?;
             }
-            ConnectionType::PeerToPeer => {}
         }
 
         Ok(())
     }
 
+    async fn send(&mut self, packet: &StreamPacket) -> Result<(), Disconnected> {
+        let send = self.connection.open_uni().await?;
+        util::write_to(packet, send).await?;
+        Ok(())
+    }
+
     async fn handle_bi(
         &mut self,
         (send, recv): (SendStream, RecvStream),
@@ -124,15 +261,40 @@ impl BackendConnection {
         Ok(())
     }
 
-    async fn handle_datagram(&mut self, _bytes: Bytes) -> Result<(), Disconnected> {
+    async fn handle_datagram(&mut self, bytes: Bytes) -> Result<(), Disconnected> {
+        let _ = self
+            .match_data
+            .send((self.peer_id, MatchData::Datagram(bytes)));
         Ok(())
     }
 
-    async fn handle_outgoing(&mut self, outgoing: LobbyStateAction) -> Result<(), Disconnected> {
+    async fn handle_outgoing_control(&mut self, data: Bytes) -> Result<(), Disconnected> {
+        // each message waits for the last to finish, so they're accepted in the same order
+        self.send(&StreamPacket::Match(data.to_vec())).await
+    }
+
+    async fn handle_outgoing(&mut self, outgoing: StreamPacket) -> Result<(), Disconnected> {
         match self.connection_type {
             ConnectionType::PeerToHost | ConnectionType::HostToPeer => {
-                let send = self.connection.open_uni().await?;
-                util::write_to(&outgoing, send).await?;
+                let removed = self.connection_type == ConnectionType::HostToPeer
+                    && match &outgoing {
+                        StreamPacket::Host(HostPacket::Update(_, action)) => match action {
+                            LobbyStateAction::Kick(by, target)
+                            | LobbyStateAction::Ban(by, target) => {
+                                *target == self.peer_id && self.lobby_state.borrow().is_host(*by)
+                            }
+                            _ => false,
+                        },
+                        _ => false,
+                    };
+
+                self.send(&outgoing).await?;
+
+                // the peer has the reason by now, since writing waits for it to be received
+                if removed {
+                    self.connection.close(0u16.into(), b"removed");
+                    return Err(Disconnected);
+                }
             }
             ConnectionType::PeerToPeer => {
                 // TODO warn
@@ -151,21 +313,25 @@ pub enum ConnectionType {
 }
 
 async fn main_loop(mut connection: BackendConnection) -> Result<(), Disconnected> {
+    // anything the host sent before it had this connection was missed,
+    // and if that can't be fixed the connection is gone, which is noticed below
+    let _ = connection.sync_with_host().await;
     loop {
         let status = select! {
-            Ok(()) = connection.lobby_state.changed() => {
-                connection.update_connection_type();
-                Ok(())
-            },
+            Ok(()) = connection.lobby_state.changed() => connection.update_connection_type().await,
             Ok(outgoing) = connection.outgoing.recv() => connection.handle_outgoing(outgoing).await,
             incoming = connection.uni_streams.next() => connection.handle_uni(incoming).await,
             // Some(Ok(incoming)) = connection.bi_streams.next() => connection.handle_bi(incoming).await,
-            // Some(Ok(incoming)) = connection.datagrams.next() => connection.handle_datagram(incoming).await,
+            Some(Ok(incoming)) = connection.datagrams.next() => connection.handle_datagram(incoming).await,
+            Some(data) = connection.outgoing_control.recv() => connection.handle_outgoing_control(data).await,
             else => Err(Disconnected),
         };
         match status {
             Ok(_) => {}
             Err(_) => {
+                let _ = connection
+                    .match_data
+                    .send((connection.peer_id, MatchData::Disconnected));
                 match connection.connection_type {
                     ConnectionType::HostToPeer => {
                         let _ = connection
@@ -174,7 +340,10 @@ async fn main_loop(mut connection: BackendConnection) -> Result<(), Disconnected
                             .await;
                     }
                     ConnectionType::PeerToHost => {
-                        let _ = connection.incoming.send(LobbyStateAction::Kill).await;
+                        let _ = connection
+                            .incoming
+                            .send(LobbyStateAction::HostLeft(connection.peer_id))
+                            .await;
                     }
                     ConnectionType::PeerToPeer => {}
                 }
@@ -1,3 +1,4 @@
+mod access;
 mod backend;
 mod cert;
 mod connection;
@@ -7,25 +8,73 @@ mod util;
 
 use backend::NetworkBackend;
 
+pub use access::LobbyAccess;
 use backend::State;
-use fg_netcode::{player_info::PlayerInfo, NetworkingMessage};
-
-use quinn::{
-    Certificate, CertificateChain, Endpoint, Incoming, PrivateKey, ServerConfigBuilder,
-    TransportConfig,
+use cert::configure_client;
+pub use cert::{Fingerprint, Identity, KnownHosts};
+use fg_netcode::{
+    compatibility::Compatibility, error::JoinLobbyError, player_info::PlayerInfo, Networking,
+    NetworkingBackend, NetworkingMessage,
 };
 
-use std::{net::SocketAddr, time::Duration};
-use tokio::{runtime::Handle, select, sync::mpsc, task::JoinHandle};
+use quinn::{Endpoint, Incoming, NewConnection};
+
+use std::{
+    fs, io,
+    net::{SocketAddr, UdpSocket},
+    path::Path,
+};
+use tokio::{
+    runtime::{Handle, Runtime},
+    select,
+    sync::mpsc,
+    task::JoinHandle,
+};
 
 #[derive(Debug)]
 pub enum NetworkingAction {
-    Host(PlayerInfo),
-    ConnectTo(PlayerInfo, SocketAddr),
+    /// Hosts a lobby, which only lets in players with the same `Compatibility`.
+    Host(PlayerInfo, LobbyAccess, Compatibility),
+    /// Joins the lobby at the address, with its password if it has one.
+    ConnectTo(PlayerInfo, SocketAddr, Option<String>, Compatibility),
 }
 struct QuinnHandle {
     pub(crate) endpoint: Endpoint,
     pub(crate) incoming: Incoming,
+    pub(crate) identity: Identity,
+    pub(crate) known_hosts: KnownHosts,
+}
+
+impl QuinnHandle {
+    /// Connects to `addr`, pinning its certificate the first time,
+    /// and refusing it from then on if it ever changes.
+    pub(crate) async fn connect(
+        &mut self,
+        addr: SocketAddr,
+    ) -> Result<(NewConnection, Fingerprint), JoinLobbyError> {
+        let pinned = self.known_hosts.get(&addr);
+        let (config, verifier) = configure_client(pinned);
+        let connecting = self
+            .endpoint
+            .connect_with(config, &addr, "lobby_server")
+            .map_err(|_| JoinLobbyError::NetworkError)?;
+
+        match connecting.await {
+            Ok(conn) => {
+                let fingerprint = verifier.presented().ok_or(JoinLobbyError::NetworkError)?;
+                if pinned.is_none() {
+                    self.known_hosts.pin(addr, fingerprint);
+                }
+                Ok((conn, fingerprint))
+            }
+            Err(_) => match (pinned, verifier.presented()) {
+                (Some(pinned), Some(presented)) if pinned != presented => {
+                    Err(JoinLobbyError::CertificateChanged)
+                }
+                _ => Err(JoinLobbyError::NetworkError),
+            },
+        }
+    }
 }
 
 pub struct BackendInterface {
@@ -35,7 +84,103 @@ pub struct BackendInterface {
     _task: JoinHandle<Option<()>>,
 }
 
-pub fn start(addr: SocketAddr, handle: Handle) -> BackendInterface {
+pub fn start(
+    addr: SocketAddr,
+    identity: Identity,
+    known_hosts: KnownHosts,
+    handle: Handle,
+) -> BackendInterface {
+    spawn(UdpSocket::bind(addr).ok(), identity, known_hosts, handle)
+}
+
+/// Starts on a socket that's already in use, like one that punched through a NAT
+/// with the `rendezvous` server, so the NAT's mappings carry over to the endpoint.
+pub fn start_with_socket(
+    socket: UdpSocket,
+    identity: Identity,
+    known_hosts: KnownHosts,
+    handle: Handle,
+) -> BackendInterface {
+    spawn(Some(socket), identity, known_hosts, handle)
+}
+
+/// Runs the backend on a runtime of its own, for a frontend that isn't async.
+/// The identity and every pinned host are kept in `config`, so they carry over between sessions.
+pub fn networking(config: &Path, addrs: &[SocketAddr]) -> io::Result<Networking> {
+    fs::create_dir_all(config)?;
+    let identity = Identity::load_or_generate(&config.join("identity"))?;
+    let known_hosts = KnownHosts::load(config.join("known_hosts"))?;
+    let socket = UdpSocket::bind(addrs)?;
+    let local_addr = socket.local_addr()?;
+    let runtime = Runtime::new()?;
+
+    let interface = start_with_socket(socket, identity, known_hosts, runtime.handle().clone());
+    let messages = interface.messages.clone();
+    Ok(Networking::with_backend(
+        messages,
+        Box::new(RuntimeBackend {
+            interface,
+            local_addr,
+            _runtime: runtime,
+        }),
+    ))
+}
+
+struct RuntimeBackend {
+    interface: BackendInterface,
+    // the host is known by this until someone joins and says what they reached it at,
+    // so the port it's announced on is the one that was actually bound
+    local_addr: SocketAddr,
+    // dropped after the interface, so the backend can see it's been shut down
+    _runtime: Runtime,
+}
+
+impl NetworkingBackend for RuntimeBackend {
+    fn request_host(
+        &mut self,
+        player: PlayerInfo,
+        password: Option<String>,
+        compatibility: Compatibility,
+    ) {
+        let access = LobbyAccess {
+            password,
+            allowed: None,
+        };
+        let player = PlayerInfo {
+            addr: self.local_addr,
+            ..player
+        };
+        let _ = self.interface.actions.blocking_send(NetworkingAction::Host(
+            player,
+            access,
+            compatibility,
+        ));
+    }
+    fn request_join(
+        &mut self,
+        id: SocketAddr,
+        player: PlayerInfo,
+        password: Option<String>,
+        compatibility: Compatibility,
+    ) {
+        let _ = self
+            .interface
+            .actions
+            .blocking_send(NetworkingAction::ConnectTo(
+                player,
+                id,
+                password,
+                compatibility,
+            ));
+    }
+}
+
+fn spawn(
+    socket: Option<UdpSocket>,
+    identity: Identity,
+    known_hosts: KnownHosts,
+    handle: Handle,
+) -> BackendInterface {
     let (message_tx, message_rx) = crossbeam_channel::bounded(4);
     let (action_tx, action_rx) = mpsc::channel(4);
 
@@ -49,39 +194,35 @@ pub fn start(addr: SocketAddr, handle: Handle) -> BackendInterface {
         messages: message_rx,
         actions: action_tx,
         shutdown: disconnect_rx,
-        _task: handle.spawn(main_loop(addr, state, disconnect_tx)),
+        _task: handle.spawn(main_loop(
+            socket,
+            identity,
+            known_hosts,
+            state,
+            disconnect_tx,
+        )),
     }
 }
 
-async fn main_loop(addr: SocketAddr, mut state: State, shutdown: mpsc::Sender<()>) -> Option<()> {
+async fn main_loop(
+    socket: Option<UdpSocket>,
+    identity: Identity,
+    known_hosts: KnownHosts,
+    mut state: State,
+    shutdown: mpsc::Sender<()>,
+) -> Option<()> {
     let mut builder = quinn::EndpointBuilder::default();
-    builder.default_client_config(cert::configure_client());
-    {
-        let mut server_config = ServerConfigBuilder::default();
-        let mut transport_config = TransportConfig::default();
-        transport_config.keep_alive_interval(Some(Duration::from_secs(2)));
-        transport_config
-            .max_idle_timeout(Some(Duration::from_secs(5)))
-            .unwrap();
-
-        let cert = rcgen::generate_simple_self_signed(vec!["lobby_server".into()]).unwrap();
-        let cert_der = cert.serialize_der().unwrap();
-        let priv_key = cert.serialize_private_key_der();
-        let priv_key = PrivateKey::from_der(&priv_key).unwrap();
-
-        server_config
-            .certificate(
-                CertificateChain::from_certs(std::iter::once(
-                    Certificate::from_der(&cert_der).unwrap(),
-                )),
-                priv_key,
-            )
-            .unwrap();
-
-        builder.listen(server_config.build());
-    }
-    let (endpoint, incoming) = builder.bind(&addr).ok()?;
-    let mut quinn = QuinnHandle { endpoint, incoming };
+    builder.listen(identity.configure_server());
+    let socket = socket?;
+    // punching leaves the socket blocking, which the endpoint can't use
+    socket.set_nonblocking(true).ok()?;
+    let (endpoint, incoming) = builder.with_socket(socket).ok()?;
+    let mut quinn = QuinnHandle {
+        endpoint,
+        incoming,
+        identity,
+        known_hosts,
+    };
     loop {
         state = select! {
             value = state.main_loop(&mut quinn) => value,
@@ -96,18 +237,25 @@ async fn main_loop(addr: SocketAddr, mut state: State, shutdown: mpsc::Sender<()
 
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
+    use std::{collections::BTreeMap, net::UdpSocket, thread, time::Duration};
 
     use fg_netcode::{
-        lobby::{InGame, LobbyMessage},
+        compatibility::{Compatibility, Incompatibility},
+        error::{CreateGameError, JoinGameError, JoinLobbyError, UpdateMetaError},
+        lobby::{GameId, LobbyMessage, Removal},
         player_info::PlayerInfo,
         NetworkingMessage,
     };
     use tokio::task::yield_now;
 
-    use crate::{start, NetworkingAction};
+    use crate::{start, start_with_socket, Identity, KnownHosts, LobbyAccess, NetworkingAction};
 
     const WAIT_TIME: u64 = 10;
+
+    fn compatibility() -> Compatibility {
+        Compatibility::new("test".to_string(), BTreeMap::new())
+    }
+
     #[test]
     fn integ() {
         let rt = tokio::runtime::Builder::new_current_thread()
@@ -119,13 +267,22 @@ mod tests {
         let handle = rt.handle().clone();
 
         let host_addr = "127.0.0.1:10800".parse().unwrap();
-        let host = start(host_addr, handle.clone());
+        let host = start(
+            host_addr,
+            Identity::generate(),
+            KnownHosts::default(),
+            handle.clone(),
+        );
         host.actions
-            .blocking_send(NetworkingAction::Host(PlayerInfo {
-                name: "Host".to_string(),
-                character: Default::default(),
-                addr: host_addr,
-            }))
+            .blocking_send(NetworkingAction::Host(
+                PlayerInfo {
+                    name: "Host".to_string(),
+                    character: Default::default(),
+                    addr: host_addr,
+                },
+                LobbyAccess::default(),
+                compatibility(),
+            ))
             .unwrap();
 
         rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
@@ -139,9 +296,19 @@ mod tests {
         rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
 
         let client_addr = "127.0.0.1:10801".parse().unwrap();
-        let mut client = start(client_addr, handle.clone());
+        let mut client = start(
+            client_addr,
+            Identity::generate(),
+            KnownHosts::default(),
+            handle.clone(),
+        );
         let client_addr2 = "127.0.0.1:10802".parse().unwrap();
-        let client2 = start(client_addr2, handle);
+        let client2 = start(
+            client_addr2,
+            Identity::generate(),
+            KnownHosts::default(),
+            handle,
+        );
 
         client2
             .actions
@@ -152,6 +319,8 @@ mod tests {
                     addr: client_addr2,
                 },
                 host_addr,
+                None,
+                compatibility(),
             ))
             .unwrap();
 
@@ -164,6 +333,8 @@ mod tests {
                     addr: client_addr,
                 },
                 host_addr,
+                None,
+                compatibility(),
             ))
             .unwrap();
 
@@ -210,35 +381,69 @@ mod tests {
 
         assert_ne!(host_lobby.state().user, client_lobby.state().user);
 
-        // test change player info
+        // test change player info and games
+
+        host_lobby.update_player_data(|data| data.name = "Host Update".to_string());
+        host_lobby.create_game();
+        host_lobby.create_game();
+        client_lobby.join_game(GameId(0));
+
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
 
-        // host_lobby.update_player_data(|data| data.name = "Host Update".to_string());
-        // host_lobby.create_game();
-        // host_lobby.create_game();
+        client_lobby2.join_game(GameId(0));
+        client_lobby2.join_game(GameId(1));
 
-        // std::thread::sleep(Duration::from_millis(1));
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
 
-        // assert_eq!(client_lobby.state().host().name, "Host Update".to_string());
-        // assert_eq!(client_lobby2.state().games().len(), 1);
+        client_lobby2.spectate_game(GameId(0));
 
-        // assert_eq!(host_lobby.poll(), Some(LobbyMessage::CreateGame(Ok(0))));
-        // assert_eq!(
-        //     host_lobby.poll(),
-        //     Some(LobbyMessage::CreateGame(Err(InGame)))
-        // );
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
 
-        // assert_eq!(
-        //     host_lobby.state().player_list,
-        //     client_lobby.state().player_list
-        // );
-        // assert_eq!(
-        //     host_lobby.state().player_list,
-        //     client_lobby2.state().player_list
-        // );
-        // assert_eq!(
-        //     client_lobby.state().player_list,
-        //     client_lobby2.state().player_list
-        // );
+        assert_eq!(client_lobby.state().host().name, "Host Update".to_string());
+        assert_eq!(client_lobby2.state().games().len(), 1);
+
+        assert_eq!(
+            host_lobby.poll(),
+            Some(LobbyMessage::CreateGame(Ok(GameId(0))))
+        );
+        assert_eq!(
+            host_lobby.poll(),
+            Some(LobbyMessage::CreateGame(Err(
+                CreateGameError::AlreadyInGame
+            )))
+        );
+        assert_eq!(
+            client_lobby.poll(),
+            Some(LobbyMessage::JoinGame(Ok(GameId(0))))
+        );
+        assert_eq!(
+            client_lobby2.poll(),
+            Some(LobbyMessage::JoinGame(Err(JoinGameError::GameFull)))
+        );
+        assert_eq!(
+            client_lobby2.poll(),
+            Some(LobbyMessage::JoinGame(Err(JoinGameError::NoSuchGame)))
+        );
+        assert_eq!(
+            client_lobby2.poll(),
+            Some(LobbyMessage::SpectateGame(Ok(GameId(0))))
+        );
+
+        assert_eq!(
+            host_lobby.state().player_list,
+            client_lobby.state().player_list
+        );
+        assert_eq!(
+            host_lobby.state().player_list,
+            client_lobby2.state().player_list
+        );
+        assert_eq!(host_lobby.state().games, client_lobby.state().games);
+        assert_eq!(host_lobby.state().games, client_lobby2.state().games);
+
+        let host_user = host_lobby.state().user;
+        let game = host_lobby.state().games()[0].clone();
+        assert_eq!(game.players(), &[host_user, client_lobby.state().user]);
+        assert_eq!(game.spectators(), &[client_lobby2.state().user]);
 
         // test disconnect
 
@@ -258,6 +463,863 @@ mod tests {
             client_lobby.state().player_list,
             client_lobby2.state().player_list
         );
+
+        // the disconnected player's slot is freed up
+        assert_eq!(host_lobby.state().games()[0].players(), &[host_user]);
+    }
+
+    #[test]
+    fn host_migration() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let _enterguard = rt.enter();
+        let handle = rt.handle().clone();
+
+        let host_addr = "127.0.0.1:10810".parse().unwrap();
+        let mut host = start(
+            host_addr,
+            Identity::generate(),
+            KnownHosts::default(),
+            handle.clone(),
+        );
+        host.actions
+            .blocking_send(NetworkingAction::Host(
+                PlayerInfo {
+                    name: "Host".to_string(),
+                    character: Default::default(),
+                    addr: host_addr,
+                },
+                LobbyAccess::default(),
+                compatibility(),
+            ))
+            .unwrap();
+
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
+
+        let host_lobby = loop {
+            if let Ok(NetworkingMessage::Host(Ok(lobby))) = host.messages.recv() {
+                break lobby;
+            }
+        };
+
+        let mut clients = Vec::new();
+        for (idx, port) in [10811, 10812].iter().enumerate() {
+            let addr = format!("127.0.0.1:{}", port).parse().unwrap();
+            let client = start(
+                addr,
+                Identity::generate(),
+                KnownHosts::default(),
+                handle.clone(),
+            );
+            client
+                .actions
+                .blocking_send(NetworkingAction::ConnectTo(
+                    PlayerInfo {
+                        name: format!("Client {}", idx + 1),
+                        character: Default::default(),
+                        addr,
+                    },
+                    host_addr,
+                    None,
+                    compatibility(),
+                ))
+                .unwrap();
+
+            let lobby = loop {
+                match client.messages.try_recv() {
+                    Ok(NetworkingMessage::Join(Ok(lobby))) => break lobby,
+                    Ok(NetworkingMessage::Join(Err(err))) => panic!("{:?}", err),
+                    _ => rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME))),
+                }
+            };
+            clients.push((client, lobby));
+        }
+
+        let client_lobby = &clients[0].1;
+        let client_lobby2 = &clients[1].1;
+
+        // one game with the host in it, and one without
+        host_lobby.create_game();
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
+        client_lobby.join_game(GameId(0));
+        client_lobby2.create_game();
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
+
+        assert_eq!(host_lobby.state().games, client_lobby2.state().games);
+        assert_eq!(host_lobby.state().games().len(), 2);
+
+        let client_user = client_lobby.state().user;
+        let client_user2 = client_lobby2.state().user;
+
+        host.shutdown.close();
+
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME * 10)));
+
+        // the next player to have joined takes over
+        assert!(client_lobby.state().is_user_host());
+        assert_eq!(client_lobby2.state().host_id(), client_user);
+        assert_eq!(
+            client_lobby.state().player_list,
+            client_lobby2.state().player_list
+        );
+        assert_eq!(client_lobby.state().players().len(), 2);
+        assert_eq!(client_lobby.state().version, client_lobby2.state().version);
+
+        // only the old host's game lost anyone
+        assert_eq!(client_lobby.state().games, client_lobby2.state().games);
+        assert_eq!(client_lobby.state().games()[0].players(), &[client_user]);
+        assert_eq!(client_lobby.state().games()[1].players(), &[client_user2]);
+
+        // and the new host passes on changes
+        client_lobby2.leave_game();
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
+
+        assert_eq!(
+            client_lobby2.poll(),
+            Some(LobbyMessage::CreateGame(Ok(GameId(1))))
+        );
+        assert_eq!(
+            client_lobby2.poll(),
+            Some(LobbyMessage::LeaveGame(Ok(GameId(1))))
+        );
+        assert_eq!(client_lobby.state().games, client_lobby2.state().games);
+        assert_eq!(client_lobby.state().games().len(), 1);
+        assert_eq!(client_lobby.state().games()[0].id, GameId(0));
+        assert_eq!(client_lobby.state().version, client_lobby2.state().version);
+    }
+
+    #[test]
+    fn changed_certificate() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let _enterguard = rt.enter();
+        let handle = rt.handle().clone();
+
+        let host_addr = "127.0.0.1:10820".parse().unwrap();
+        let host = start(
+            host_addr,
+            Identity::generate(),
+            KnownHosts::default(),
+            handle.clone(),
+        );
+        host.actions
+            .blocking_send(NetworkingAction::Host(
+                PlayerInfo {
+                    name: "Host".to_string(),
+                    character: Default::default(),
+                    addr: host_addr,
+                },
+                LobbyAccess::default(),
+                compatibility(),
+            ))
+            .unwrap();
+
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
+
+        let _host_lobby = loop {
+            if let Ok(NetworkingMessage::Host(Ok(lobby))) = host.messages.recv() {
+                break lobby;
+            }
+        };
+
+        // the last time this address was connected to, it was someone else
+        let mut known_hosts = KnownHosts::default();
+        known_hosts.pin(host_addr, Identity::generate().fingerprint());
+
+        let client_addr = "127.0.0.1:10821".parse().unwrap();
+        let client = start(client_addr, Identity::generate(), known_hosts, handle);
+        client
+            .actions
+            .blocking_send(NetworkingAction::ConnectTo(
+                PlayerInfo {
+                    name: "Client".to_string(),
+                    character: Default::default(),
+                    addr: client_addr,
+                },
+                host_addr,
+                None,
+                compatibility(),
+            ))
+            .unwrap();
+
+        let result = loop {
+            match client.messages.try_recv() {
+                Ok(NetworkingMessage::Join(result)) => break result,
+                _ => rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME))),
+            }
+        };
+
+        assert_eq!(result.err(), Some(JoinLobbyError::CertificateChanged));
+    }
+
+    #[test]
+    fn lobby_access() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let _enterguard = rt.enter();
+        let handle = rt.handle().clone();
+
+        let invited = Identity::generate();
+        let host_addr = "127.0.0.1:10830".parse().unwrap();
+        let host = start(
+            host_addr,
+            Identity::generate(),
+            KnownHosts::default(),
+            handle.clone(),
+        );
+        host.actions
+            .blocking_send(NetworkingAction::Host(
+                PlayerInfo {
+                    name: "Host".to_string(),
+                    character: Default::default(),
+                    addr: host_addr,
+                },
+                LobbyAccess {
+                    password: Some("hunter2".to_string()),
+                    allowed: Some(std::iter::once(invited.fingerprint()).collect()),
+                },
+                compatibility(),
+            ))
+            .unwrap();
+
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
+
+        let _host_lobby = loop {
+            if let Ok(NetworkingMessage::Host(Ok(lobby))) = host.messages.recv() {
+                break lobby;
+            }
+        };
+
+        let try_join = |port: u16, identity: Identity, password: &str| {
+            let addr = format!("127.0.0.1:{}", port).parse().unwrap();
+            let client = start(addr, identity, KnownHosts::default(), handle.clone());
+            client
+                .actions
+                .blocking_send(NetworkingAction::ConnectTo(
+                    PlayerInfo {
+                        name: "Client".to_string(),
+                        character: Default::default(),
+                        addr,
+                    },
+                    host_addr,
+                    Some(password.to_string()),
+                    compatibility(),
+                ))
+                .unwrap();
+
+            let result = loop {
+                match client.messages.try_recv() {
+                    Ok(NetworkingMessage::Join(result)) => break result,
+                    _ => rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME))),
+                }
+            };
+            (client, result)
+        };
+
+        let (_uninvited, result) = try_join(10831, Identity::generate(), "hunter2");
+        assert_eq!(result.err(), Some(JoinLobbyError::Denied));
+
+        let (_wrong_password, result) = try_join(10832, invited.clone(), "hunter3");
+        assert_eq!(result.err(), Some(JoinLobbyError::IncorrectPassword));
+
+        let (_client, result) = try_join(10833, invited, "hunter2");
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn moderation() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let _enterguard = rt.enter();
+        let handle = rt.handle().clone();
+
+        let host_addr = "127.0.0.1:10840".parse().unwrap();
+        let host = start(
+            host_addr,
+            Identity::generate(),
+            KnownHosts::default(),
+            handle.clone(),
+        );
+        host.actions
+            .blocking_send(NetworkingAction::Host(
+                PlayerInfo {
+                    name: "Host".to_string(),
+                    character: Default::default(),
+                    addr: host_addr,
+                },
+                LobbyAccess::default(),
+                compatibility(),
+            ))
+            .unwrap();
+
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
+
+        let host_lobby = loop {
+            if let Ok(NetworkingMessage::Host(Ok(lobby))) = host.messages.recv() {
+                break lobby;
+            }
+        };
+
+        let try_join = |port: u16| {
+            let addr = format!("127.0.0.1:{}", port).parse().unwrap();
+            let client = start(
+                addr,
+                Identity::generate(),
+                KnownHosts::default(),
+                handle.clone(),
+            );
+            client
+                .actions
+                .blocking_send(NetworkingAction::ConnectTo(
+                    PlayerInfo {
+                        name: format!("Client {}", port),
+                        character: Default::default(),
+                        addr,
+                    },
+                    host_addr,
+                    None,
+                    compatibility(),
+                ))
+                .unwrap();
+
+            let result = loop {
+                match client.messages.try_recv() {
+                    Ok(NetworkingMessage::Join(result)) => break result,
+                    _ => rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME))),
+                }
+            };
+            (client, result)
+        };
+
+        let (_client, result) = try_join(10841);
+        let client_lobby = result.unwrap();
+        let (_client2, result) = try_join(10842);
+        let client_lobby2 = result.unwrap();
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
+
+        let client_user = client_lobby.state().user;
+        let client_user2 = client_lobby2.state().user;
+
+        // only the host can remove anyone, or lock the lobby
+        client_lobby2.kick(client_user);
+        client_lobby2.lock(true);
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
+
+        assert_eq!(
+            client_lobby2.poll(),
+            Some(LobbyMessage::Kick(Err(UpdateMetaError::InvalidPermission)))
+        );
+        assert_eq!(
+            client_lobby2.poll(),
+            Some(LobbyMessage::Lock(Err(UpdateMetaError::InvalidPermission)))
+        );
+        assert_eq!(host_lobby.state().players().len(), 3);
+        assert!(!host_lobby.state().locked);
+
+        host_lobby.kick(client_user);
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME * 10)));
+
+        assert_eq!(host_lobby.poll(), Some(LobbyMessage::Kick(Ok(client_user))));
+        assert_eq!(
+            client_lobby.poll(),
+            Some(LobbyMessage::Removed(Removal::Kicked))
+        );
+        assert_eq!(host_lobby.state().players().len(), 2);
+        assert_eq!(
+            host_lobby.state().player_list,
+            client_lobby2.state().player_list
+        );
+
+        host_lobby.lock(true);
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
+        assert_eq!(host_lobby.poll(), Some(LobbyMessage::Lock(Ok(true))));
+        assert!(client_lobby2.state().locked);
+
+        let (_locked_out, result) = try_join(10843);
+        assert_eq!(result.err(), Some(JoinLobbyError::Locked));
+
+        host_lobby.lock(false);
+        host_lobby.ban(client_user2);
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME * 10)));
+
+        assert_eq!(host_lobby.poll(), Some(LobbyMessage::Lock(Ok(false))));
+        assert_eq!(host_lobby.poll(), Some(LobbyMessage::Ban(Ok(client_user2))));
+        assert_eq!(
+            client_lobby2.poll(),
+            Some(LobbyMessage::Removed(Removal::Banned))
+        );
+        assert_eq!(host_lobby.state().players().len(), 1);
+
+        // everyone here is on the same address as the banned player
+        let (_banned, result) = try_join(10844);
+        assert_eq!(result.err(), Some(JoinLobbyError::Banned));
+    }
+
+    #[test]
+    fn lagging_peer_resyncs() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let _enterguard = rt.enter();
+        let handle = rt.handle().clone();
+
+        let host_addr = "127.0.0.1:10850".parse().unwrap();
+        let host = start(
+            host_addr,
+            Identity::generate(),
+            KnownHosts::default(),
+            handle.clone(),
+        );
+        host.actions
+            .blocking_send(NetworkingAction::Host(
+                PlayerInfo {
+                    name: "Host".to_string(),
+                    character: Default::default(),
+                    addr: host_addr,
+                },
+                LobbyAccess::default(),
+                compatibility(),
+            ))
+            .unwrap();
+
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
+
+        let host_lobby = loop {
+            if let Ok(NetworkingMessage::Host(Ok(lobby))) = host.messages.recv() {
+                break lobby;
+            }
+        };
+
+        let client_addr = "127.0.0.1:10851".parse().unwrap();
+        let client = start(
+            client_addr,
+            Identity::generate(),
+            KnownHosts::default(),
+            handle,
+        );
+        client
+            .actions
+            .blocking_send(NetworkingAction::ConnectTo(
+                PlayerInfo {
+                    name: "Client".to_string(),
+                    character: Default::default(),
+                    addr: client_addr,
+                },
+                host_addr,
+                None,
+                compatibility(),
+            ))
+            .unwrap();
+
+        let client_lobby = loop {
+            match client.messages.try_recv() {
+                Ok(NetworkingMessage::Join(Ok(lobby))) => break lobby,
+                Ok(NetworkingMessage::Join(Err(err))) => panic!("{:?}", err),
+                _ => rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME))),
+            }
+        };
+
+        // both sides change at once, so the host can send updates faster than they're written,
+        // which drops the ones the client's connection falls too far behind on
+        for round in 0..10 {
+            for idx in 0..4 {
+                host_lobby
+                    .update_player_data(|info| info.name = format!("Host {}", round * 4 + idx));
+                client_lobby
+                    .update_player_data(|info| info.name = format!("Client {}", round * 4 + idx));
+            }
+            rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
+        }
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME * 10)));
+
+        assert_eq!(host_lobby.state().version, client_lobby.state().version);
+        assert_eq!(
+            host_lobby.state().player_list,
+            client_lobby.state().player_list
+        );
+        assert_eq!(client_lobby.state().host().name, "Host 39");
+        assert_eq!(client_lobby.state().user().name, "Client 39");
+    }
+
+    #[test]
+    fn bans_survive_migration() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let _enterguard = rt.enter();
+        let handle = rt.handle().clone();
+
+        let mut host_addr = "127.0.0.1:10870".parse().unwrap();
+        let mut host = start(
+            host_addr,
+            Identity::generate(),
+            KnownHosts::default(),
+            handle.clone(),
+        );
+        host.actions
+            .blocking_send(NetworkingAction::Host(
+                PlayerInfo {
+                    name: "Host".to_string(),
+                    character: Default::default(),
+                    addr: host_addr,
+                },
+                LobbyAccess::default(),
+                compatibility(),
+            ))
+            .unwrap();
+
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
+
+        let host_lobby = loop {
+            if let Ok(NetworkingMessage::Host(Ok(lobby))) = host.messages.recv() {
+                break lobby;
+            }
+        };
+
+        let try_join = |port: u16, target| {
+            let addr = format!("127.0.0.1:{}", port).parse().unwrap();
+            let client = start(
+                addr,
+                Identity::generate(),
+                KnownHosts::default(),
+                handle.clone(),
+            );
+            client
+                .actions
+                .blocking_send(NetworkingAction::ConnectTo(
+                    PlayerInfo {
+                        name: format!("Client {}", port),
+                        character: Default::default(),
+                        addr,
+                    },
+                    target,
+                    None,
+                    compatibility(),
+                ))
+                .unwrap();
+
+            let result = loop {
+                match client.messages.try_recv() {
+                    Ok(NetworkingMessage::Join(result)) => break result,
+                    _ => rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME))),
+                }
+            };
+            (client, result)
+        };
+
+        let client_addr = "127.0.0.1:10871".parse().unwrap();
+        let (_client, result) = try_join(10871, host_addr);
+        let client_lobby = result.unwrap();
+        let (_client2, result) = try_join(10872, host_addr);
+        let client_lobby2 = result.unwrap();
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
+
+        host_lobby.ban(client_lobby2.state().user);
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME * 10)));
+        assert_eq!(client_lobby.state().players().len(), 2);
+
+        host.shutdown.close();
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME * 10)));
+        assert!(client_lobby.state().is_user_host());
+
+        // everyone here is on the same address as the banned player
+        let (_banned, result) = try_join(10873, client_addr);
+        assert_eq!(result.err(), Some(JoinLobbyError::Banned));
+    }
+
+    #[test]
+    fn pinned_host_reconnects() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let _enterguard = rt.enter();
+        let handle = rt.handle().clone();
+
+        let config = std::env::temp_dir().join("std_udp_backend_pinned_host_reconnects");
+        let _ = std::fs::remove_dir_all(&config);
+        std::fs::create_dir_all(&config).unwrap();
+        let identity_path = config.join("identity");
+        let known_hosts_path = config.join("known_hosts");
+
+        let host_addr = "127.0.0.1:10880".parse().unwrap();
+        let start_host = || {
+            let host = start(
+                host_addr,
+                Identity::load_or_generate(&identity_path).unwrap(),
+                KnownHosts::default(),
+                handle.clone(),
+            );
+            host.actions
+                .blocking_send(NetworkingAction::Host(
+                    PlayerInfo {
+                        name: "Host".to_string(),
+                        character: Default::default(),
+                        addr: host_addr,
+                    },
+                    LobbyAccess::default(),
+                    compatibility(),
+                ))
+                .unwrap();
+
+            rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
+
+            let lobby = loop {
+                if let Ok(NetworkingMessage::Host(Ok(lobby))) = host.messages.recv() {
+                    break lobby;
+                }
+            };
+            (host, lobby)
+        };
+        let try_join = |port: u16| {
+            let addr = format!("127.0.0.1:{}", port).parse().unwrap();
+            let client = start(
+                addr,
+                Identity::generate(),
+                KnownHosts::load(known_hosts_path.clone()).unwrap(),
+                handle.clone(),
+            );
+            client
+                .actions
+                .blocking_send(NetworkingAction::ConnectTo(
+                    PlayerInfo {
+                        name: "Client".to_string(),
+                        character: Default::default(),
+                        addr,
+                    },
+                    host_addr,
+                    None,
+                    compatibility(),
+                ))
+                .unwrap();
+
+            let result = loop {
+                match client.messages.try_recv() {
+                    Ok(NetworkingMessage::Join(result)) => break result,
+                    _ => rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME))),
+                }
+            };
+            (client, result)
+        };
+
+        let (mut host, _host_lobby) = start_host();
+        let (mut client, result) = try_join(10881);
+        assert!(result.is_ok());
+
+        // both start over, with what they saved the first time
+        host.shutdown.close();
+        client.shutdown.close();
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME * 10)));
+
+        let fingerprint = Identity::load_or_generate(&identity_path)
+            .unwrap()
+            .fingerprint();
+        assert_eq!(
+            KnownHosts::load(known_hosts_path.clone())
+                .unwrap()
+                .get(&host_addr),
+            Some(fingerprint)
+        );
+
+        let (_host, _host_lobby) = start_host();
+        let (_client, result) = try_join(10882);
+        assert!(result.is_ok());
+
+        let _ = std::fs::remove_dir_all(&config);
+    }
+
+    #[test]
+    fn incompatible_client() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let _enterguard = rt.enter();
+        let handle = rt.handle().clone();
+
+        let host_addr = "127.0.0.1:10860".parse().unwrap();
+        let host = start(
+            host_addr,
+            Identity::generate(),
+            KnownHosts::default(),
+            handle.clone(),
+        );
+        host.actions
+            .blocking_send(NetworkingAction::Host(
+                PlayerInfo {
+                    name: "Host".to_string(),
+                    character: Default::default(),
+                    addr: host_addr,
+                },
+                LobbyAccess::default(),
+                Compatibility::new(
+                    "test".to_string(),
+                    std::iter::once((Default::default(), 1)).collect(),
+                ),
+            ))
+            .unwrap();
+
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
+
+        let _host_lobby = loop {
+            if let Ok(NetworkingMessage::Host(Ok(lobby))) = host.messages.recv() {
+                break lobby;
+            }
+        };
+
+        // a different build, with different data for the same character
+        let client_addr = "127.0.0.1:10861".parse().unwrap();
+        let client = start(
+            client_addr,
+            Identity::generate(),
+            KnownHosts::default(),
+            handle,
+        );
+        client
+            .actions
+            .blocking_send(NetworkingAction::ConnectTo(
+                PlayerInfo {
+                    name: "Client".to_string(),
+                    character: Default::default(),
+                    addr: client_addr,
+                },
+                host_addr,
+                None,
+                Compatibility::new(
+                    "other".to_string(),
+                    std::iter::once((Default::default(), 2)).collect(),
+                ),
+            ))
+            .unwrap();
+
+        let result = loop {
+            match client.messages.try_recv() {
+                Ok(NetworkingMessage::Join(result)) => break result,
+                _ => rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME))),
+            }
+        };
+
+        assert_eq!(
+            result.err(),
+            Some(JoinLobbyError::Incompatible(vec![
+                Incompatibility::Build {
+                    host: "test".to_string(),
+                    client: "other".to_string(),
+                },
+                Incompatibility::Character(Default::default()),
+            ]))
+        );
+    }
+
+    #[test]
+    fn punched_connection() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let _enterguard = rt.enter();
+        let handle = rt.handle().clone();
+
+        let mut server = rendezvous::Server::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let server_addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let host_addr = "127.0.0.1:10890".parse().unwrap();
+        let client_addr = "127.0.0.1:10891".parse().unwrap();
+        let host_socket = UdpSocket::bind(host_addr).unwrap();
+        let client_socket = UdpSocket::bind(client_addr).unwrap();
+
+        // both sides have to be punching at once for either to get through
+        let punching = thread::spawn(move || {
+            let peer = rendezvous::punch(&host_socket, server_addr, "lobby");
+            (host_socket, peer)
+        });
+        let host_peer = rendezvous::punch(&client_socket, server_addr, "lobby").unwrap();
+        let (host_socket, client_peer) = punching.join().unwrap();
+
+        assert_eq!(host_peer, host_addr);
+        assert_eq!(client_peer.unwrap(), client_addr);
+
+        let host = start_with_socket(
+            host_socket,
+            Identity::generate(),
+            KnownHosts::default(),
+            handle.clone(),
+        );
+        host.actions
+            .blocking_send(NetworkingAction::Host(
+                PlayerInfo {
+                    name: "Host".to_string(),
+                    character: Default::default(),
+                    addr: host_addr,
+                },
+                LobbyAccess::default(),
+                compatibility(),
+            ))
+            .unwrap();
+
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
+
+        let host_lobby = loop {
+            if let Ok(NetworkingMessage::Host(Ok(lobby))) = host.messages.recv() {
+                break lobby;
+            }
+        };
+
+        let client = start_with_socket(
+            client_socket,
+            Identity::generate(),
+            KnownHosts::default(),
+            handle,
+        );
+        client
+            .actions
+            .blocking_send(NetworkingAction::ConnectTo(
+                PlayerInfo {
+                    name: "Client".to_string(),
+                    character: Default::default(),
+                    addr: client_addr,
+                },
+                host_peer,
+                None,
+                compatibility(),
+            ))
+            .unwrap();
+
+        let client_lobby = loop {
+            match client.messages.try_recv() {
+                Ok(NetworkingMessage::Join(Ok(lobby))) => break lobby,
+                Ok(NetworkingMessage::Join(Err(err))) => panic!("{:?}", err),
+                _ => rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME))),
+            }
+        };
+
+        rt.block_on(tokio::time::sleep(Duration::from_millis(WAIT_TIME)));
+
+        assert_eq!(
+            host_lobby.state().player_list,
+            client_lobby.state().player_list
+        );
+        assert_eq!(client_lobby.state().players().len(), 2);
     }
 }
 
@@ -1,7 +1,8 @@
 use std::net::SocketAddr;
 
 use fg_netcode::{
-    lobby::{lobby_state::LobbyState, GameInfo, InGame, JoinGameError, LobbyMessage},
+    game::MatchStart,
+    lobby::{chat::ChatMessage, lobby_state::LobbyState, GameId, LobbyMessage, Removal},
     player_info::PlayerInfo,
     player_list::Player,
 };
@@ -12,18 +13,62 @@ use tokio::{
     task::JoinHandle,
 };
 
+use crate::{access::Moderation, cert::Fingerprint};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum LobbyStateAction {
-    NewPlayer(PlayerInfo),
+    /// The player, and the identity they proved when they joined.
+    NewPlayer(PlayerInfo, Option<Fingerprint>),
     UpdatePlayer(Player, PlayerInfo),
     Disconnect(Player),
     CreateGame(Player),
-    JoinGame(Player, usize),
+    JoinGame(Player, GameId),
+    SpectateGame(Player, GameId),
+    LeaveGame(Player),
     UpdateAddr(Player, SocketAddr),
+    Ready(Player, bool),
+    StartGame(GameId, MatchStart),
+    Chat(ChatMessage),
+    /// Who asked for it, and who is being removed.
+    Kick(Player, Player),
+    Ban(Player, Player),
+    Lock(Player, bool),
+    /// The connection to the host was lost, so the next player in line takes over.
+    /// Every replica notices on its own, so this is never sent.
+    #[serde(skip)]
+    HostLeft(Player),
+    /// The whole lobby from the host, after this replica missed an update.
+    #[serde(skip)]
+    Snapshot(Box<LobbyState>, Moderation),
     #[serde(skip)]
     Kill,
 }
 
+impl LobbyStateAction {
+    /// The player a peer has to be to send this, or `None` if only the host can.
+    pub fn actor(&self) -> Option<Player> {
+        match self {
+            LobbyStateAction::UpdatePlayer(player, _)
+            | LobbyStateAction::CreateGame(player)
+            | LobbyStateAction::JoinGame(player, _)
+            | LobbyStateAction::SpectateGame(player, _)
+            | LobbyStateAction::LeaveGame(player)
+            | LobbyStateAction::Ready(player, _)
+            | LobbyStateAction::Kick(player, _)
+            | LobbyStateAction::Ban(player, _)
+            | LobbyStateAction::Lock(player, _) => Some(*player),
+            LobbyStateAction::Chat(message) => Some(message.sender),
+            LobbyStateAction::NewPlayer(..)
+            | LobbyStateAction::Disconnect(_)
+            | LobbyStateAction::UpdateAddr(..)
+            | LobbyStateAction::StartGame(..)
+            | LobbyStateAction::HostLeft(_)
+            | LobbyStateAction::Snapshot(..)
+            | LobbyStateAction::Kill => None,
+        }
+    }
+}
+
 pub struct LobbyTaskResult {
     pub task: JoinHandle<()>,
     pub state: watch::Receiver<LobbyState>,
@@ -75,51 +120,100 @@ async fn lobby_state_loop(user: Player, mut actor: LobbyActor) {
             dbg!(user);
             dbg!(&action);
         }
+        // only the host's updates count towards the version
+        let update = !matches!(
+            action,
+            LobbyStateAction::HostLeft(_) | LobbyStateAction::Snapshot(..)
+        );
         match action {
             LobbyStateAction::Kill => break,
+            LobbyStateAction::Snapshot(snapshot, _) => {
+                lobby_state = LobbyState {
+                    user: lobby_state.user,
+                    ..*snapshot
+                };
+            }
             LobbyStateAction::UpdateAddr(player, addr) => {
                 lobby_state.player_list.get_mut(player).unwrap().addr = addr;
             }
-            LobbyStateAction::NewPlayer(info) => {
+            LobbyStateAction::NewPlayer(info, _) => {
                 lobby_state.player_list.insert(info);
             }
-            LobbyStateAction::Disconnect(id) => {
+            LobbyStateAction::Disconnect(id) | LobbyStateAction::HostLeft(id) => {
                 if id == user {
                     break;
                 }
                 lobby_state.remove(&id);
             }
+            // every replica applies the same actions in the order the host did,
+            // so the requester's own replica reports the same result the host got
             LobbyStateAction::CreateGame(player) => {
-                if lobby_state
-                    .games
-                    .iter()
-                    .any(|game| game.player_list.iter().any(|item| item == &player))
-                {
-                } else {
-                    lobby_state.games.push(GameInfo {
-                        player_list: vec![player],
-                        ready: [false, false].into(),
-                    });
+                let result = lobby_state.create_game(player);
+                if player == user {
+                    let _ = actor.messages.send(LobbyMessage::CreateGame(result));
                 }
             }
             LobbyStateAction::JoinGame(player, game) => {
-                if lobby_state
-                    .games
-                    .iter()
-                    .any(|game| game.player_list.iter().any(|item| item == &player))
-                {
-                } else if game >= lobby_state.games.len() {
-                    todo!()
-                } else {
-                    lobby_state.games[game].player_list.push(player);
+                let result = lobby_state.join_game(player, game);
+                if player == user {
+                    let _ = actor.messages.send(LobbyMessage::JoinGame(result));
                 }
             }
+            LobbyStateAction::SpectateGame(player, game) => {
+                let result = lobby_state.spectate_game(player, game);
+                if player == user {
+                    let _ = actor.messages.send(LobbyMessage::SpectateGame(result));
+                }
+            }
+            LobbyStateAction::LeaveGame(player) => {
+                let result = lobby_state.leave_game(player);
+                if player == user {
+                    let _ = actor.messages.send(LobbyMessage::LeaveGame(result));
+                }
+            }
+            LobbyStateAction::Kick(by, target) | LobbyStateAction::Ban(by, target) => {
+                let result = lobby_state.remove_player(by, target);
+                let banned = matches!(action, LobbyStateAction::Ban(..));
+                if by == user {
+                    let _ = actor.messages.send(if banned {
+                        LobbyMessage::Ban(result)
+                    } else {
+                        LobbyMessage::Kick(result)
+                    });
+                } else if target == user && result.is_ok() {
+                    // the host closes the connection next, so there's nothing left to replicate
+                    let _ = actor.messages.send(LobbyMessage::Removed(if banned {
+                        Removal::Banned
+                    } else {
+                        Removal::Kicked
+                    }));
+                    break;
+                }
+            }
+            LobbyStateAction::Lock(by, locked) => {
+                let result = lobby_state.lock(by, locked);
+                if by == user {
+                    let _ = actor.messages.send(LobbyMessage::Lock(result));
+                }
+            }
+            LobbyStateAction::Ready(player, ready) => {
+                lobby_state.set_ready(player, ready);
+            }
+            LobbyStateAction::StartGame(game, start) => {
+                lobby_state.start_game(game, start);
+            }
+            LobbyStateAction::Chat(message) => {
+                lobby_state.add_chat(message);
+            }
             LobbyStateAction::UpdatePlayer(player, info) => {
                 if let Some(player) = lobby_state.player_list.get_mut(player) {
                     *player = info;
                 }
             }
         }
+        if update {
+            lobby_state.version += 1;
+        }
         match actor.state.send(lobby_state.clone()) {
             Ok(_) => {
                 if lobby_state.user != lobby_state.host_id() {
@@ -1,31 +1,68 @@
 use fg_netcode::{
-    lobby::{lobby_state::LobbyState, LobbyAction},
+    compatibility::Compatibility, error::JoinLobbyError, lobby::lobby_state::LobbyState,
     player_info::PlayerInfo,
 };
 use quinn::{ConnectError, ConnectionError, ReadError, ReadToEndError, WriteError};
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
 
-use crate::util::{RequestRecvError, RequestSendError};
+use crate::{
+    access::{Admission, Denial, Moderation},
+    cert::IdentityProof,
+    lobby_state::LobbyStateAction,
+    util::{RequestRecvError, RequestSendError},
+};
+
+/// The first thing sent over a new connection.
+#[derive(Serialize, Deserialize)]
+pub(crate) enum ConnectRequest {
+    /// A new player asking the host to let them in.
+    Join(JoinRequest),
+    /// A player already in the lobby connecting to someone they weren't connected to,
+    /// like the new host after a migration, or someone else in their game.
+    Rejoin(IdentityProof),
+}
 
 #[derive(Serialize, Deserialize)]
 pub(crate) struct JoinRequest {
     pub(crate) target: SocketAddr,
     pub(crate) info: PlayerInfo,
+    pub(crate) password: Option<String>,
+    pub(crate) identity: IdentityProof,
+    pub(crate) compatibility: Compatibility,
 }
 #[derive(Serialize, Deserialize)]
-pub(crate) struct JoinResponse {
-    pub(crate) target: SocketAddr,
+pub(crate) enum JoinResponse {
+    /// The address the host sees the player at, and the lobby they've joined.
+    /// The admission and moderation are passed on so whoever takes over as host keeps them.
+    Accepted(SocketAddr, LobbyState, Admission, Moderation),
+    Denied(Denial),
 }
 
-#[derive(Serialize, Deserialize)]
+/// Everything sent over a uni stream.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum StreamPacket {
+    Client(ClientPacket),
+    Host(HostPacket),
+    Match(Vec<u8>),
+}
+
+/// What a peer sends the host.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ClientPacket {
-    LobbyAction(LobbyAction),
+    /// Something the peer wants to do, which the host applies and passes on.
+    Action(LobbyStateAction),
+    /// The peer missed an update, and needs the whole lobby to catch up.
+    Resync,
 }
 
-#[derive(Serialize, Deserialize)]
+/// What the host sends its peers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum HostPacket {
-    LobbyUpdate(LobbyState),
+    /// An action the host applied, and the version of the lobby once it's applied.
+    Update(u64, LobbyStateAction),
+    /// The whole lobby, for a peer that missed an update.
+    Snapshot(LobbyState, Moderation),
 }
 
 #[derive(Debug)]
@@ -69,3 +106,9 @@ impl From<ConnectError> for Disconnected {
         Self
     }
 }
+
+impl From<Disconnected> for JoinLobbyError {
+    fn from(_: Disconnected) -> Self {
+        JoinLobbyError::NetworkError
+    }
+}
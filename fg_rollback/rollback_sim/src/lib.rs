@@ -0,0 +1,11 @@
+//! A deterministic, in memory network for testing `fg_rollback` clients against each other.
+//!
+//! Any `RollbackableGameState` can be simulated, as long as it can be created without a
+//! window, so `Match` can be plugged in once it no longer needs a `ggez::Context`.
+
+pub mod network;
+pub mod simulation;
+pub mod test_game;
+
+pub use network::{Network, NetworkConfig, NetworkStats};
+pub use simulation::{Peer, Recorder, Simulation, SimulationError};
@@ -0,0 +1,192 @@
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use std::collections::BTreeMap;
+
+// the most extra ticks a reordered packet is held back for
+const MAX_REORDER_DELAY: usize = 4;
+
+/// How badly the simulated network behaves. All times are in ticks, which are one frame long.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NetworkConfig {
+    /// Ticks every packet takes to arrive.
+    pub latency: usize,
+    /// Up to this many ticks are randomly added to each packet's latency.
+    pub jitter: usize,
+    /// Chance from 0 to 1 that a packet never arrives.
+    pub loss: f64,
+    /// Chance from 0 to 1 that a packet arrives twice.
+    pub duplication: f64,
+    /// Chance from 0 to 1 that a packet is held back, so packets sent after it arrive first.
+    pub reordering: f64,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            latency: 0,
+            jitter: 0,
+            loss: 0.0,
+            duplication: 0.0,
+            reordering: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetworkStats {
+    pub sent: usize,
+    pub lost: usize,
+    pub duplicated: usize,
+    pub reordered: usize,
+    pub delivered: usize,
+}
+
+struct InFlight {
+    from: usize,
+    to: usize,
+    data: Vec<u8>,
+}
+
+/// An in memory network between numbered peers.
+/// Given the same seed and the same packets, packets are always delivered in the same order
+/// on the same tick.
+pub struct Network {
+    config: NetworkConfig,
+    rng: ChaCha8Rng,
+    current_tick: usize,
+    // keyed by delivery tick, then the order they were queued in
+    in_flight: BTreeMap<(usize, usize), InFlight>,
+    next_id: usize,
+    stats: NetworkStats,
+}
+
+impl Network {
+    pub fn new(config: NetworkConfig, rng: ChaCha8Rng) -> Self {
+        Self {
+            config,
+            rng,
+            current_tick: 0,
+            in_flight: BTreeMap::new(),
+            next_id: 0,
+            stats: NetworkStats::default(),
+        }
+    }
+
+    pub fn config(&self) -> NetworkConfig {
+        self.config
+    }
+
+    pub fn stats(&self) -> NetworkStats {
+        self.stats
+    }
+
+    pub fn current_tick(&self) -> usize {
+        self.current_tick
+    }
+
+    pub fn send(&mut self, from: usize, to: usize, data: Vec<u8>) {
+        self.stats.sent += 1;
+
+        if self.rng.gen_bool(self.config.loss) {
+            self.stats.lost += 1;
+            return;
+        }
+
+        let copies = if self.rng.gen_bool(self.config.duplication) {
+            self.stats.duplicated += 1;
+            2
+        } else {
+            1
+        };
+
+        for _ in 0..copies {
+            let mut delay = self.config.latency + self.rng.gen_range(0..=self.config.jitter);
+            if self.rng.gen_bool(self.config.reordering) {
+                self.stats.reordered += 1;
+                delay += self.rng.gen_range(1..=MAX_REORDER_DELAY);
+            }
+
+            self.in_flight.insert(
+                (self.current_tick + delay, self.next_id),
+                InFlight {
+                    from,
+                    to,
+                    data: data.clone(),
+                },
+            );
+            self.next_id += 1;
+        }
+    }
+
+    /// Takes every packet for `to` that has arrived by the current tick, along with who sent it.
+    pub fn receive(&mut self, to: usize) -> Vec<(usize, Vec<u8>)> {
+        let arrived: Vec<_> = self
+            .in_flight
+            .range(..(self.current_tick + 1, 0))
+            .filter(|(_, packet)| packet.to == to)
+            .map(|(key, _)| *key)
+            .collect();
+
+        self.stats.delivered += arrived.len();
+        arrived
+            .into_iter()
+            .filter_map(|key| self.in_flight.remove(&key))
+            .map(|packet| (packet.from, packet.data))
+            .collect()
+    }
+
+    pub fn tick(&mut self) {
+        self.current_tick += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Network, NetworkConfig};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn deliveries(config: NetworkConfig, seed: u64) -> Vec<(usize, Vec<u8>)> {
+        let mut network = Network::new(config, ChaCha8Rng::seed_from_u64(seed));
+        let mut received = Vec::new();
+        for tick in 0..100 {
+            network.send(0, 1, vec![tick as u8]);
+            received.extend(
+                network
+                    .receive(1)
+                    .into_iter()
+                    .map(|(_, data)| (network.current_tick(), data)),
+            );
+            network.tick();
+        }
+        received
+    }
+
+    #[test]
+    fn latency() {
+        let received = deliveries(
+            NetworkConfig {
+                latency: 3,
+                ..NetworkConfig::default()
+            },
+            0,
+        );
+        assert_eq!(received.len(), 97);
+        assert!(received
+            .iter()
+            .all(|(tick, data)| *tick == data[0] as usize + 3));
+    }
+
+    #[test]
+    fn same_seed_same_deliveries() {
+        let config = NetworkConfig {
+            latency: 2,
+            jitter: 3,
+            loss: 0.1,
+            duplication: 0.1,
+            reordering: 0.1,
+        };
+        assert_eq!(deliveries(config, 7), deliveries(config, 7));
+        assert_ne!(deliveries(config, 7), deliveries(config, 8));
+    }
+}
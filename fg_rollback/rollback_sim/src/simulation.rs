@@ -0,0 +1,459 @@
+use crate::network::{Network, NetworkConfig, NetworkStats};
+use fg_rollback::{
+    InputSet, NetcodeClient, NetcodeError, Packet, PlayerHandle, Predictor, RepeatLast,
+    RollbackableGameState,
+};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt::Debug;
+
+/// Wraps a game to keep the checksum after every frame it simulates,
+/// so peers can be compared once they've all confirmed a frame.
+pub struct Recorder<Game> {
+    game: Game,
+    frame: usize,
+    // the checksum of the state after each frame, re-simulated frames overwrite their old checksum
+    checksums: Vec<u64>,
+}
+
+impl<Game: RollbackableGameState> Recorder<Game> {
+    pub fn new(game: Game) -> Self {
+        let checksum = game.checksum();
+        Self {
+            game,
+            frame: 0,
+            checksums: vec![checksum],
+        }
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn checksum_after(&self, frame: usize) -> Option<u64> {
+        self.checksums.get(frame).copied()
+    }
+}
+
+impl<Game: RollbackableGameState> RollbackableGameState for Recorder<Game> {
+    type Input = Game::Input;
+    type SavedState = (usize, Game::SavedState);
+
+    fn player_count(&self) -> usize {
+        self.game.player_count()
+    }
+
+    fn advance_frame(&mut self, input: InputSet<'_, Self::Input>) {
+        self.game.advance_frame(input);
+        self.frame += 1;
+        self.checksums.truncate(self.frame);
+        self.checksums.push(self.game.checksum());
+    }
+    fn save_state(&self) -> Self::SavedState {
+        (self.frame, self.game.save_state())
+    }
+    fn save_state_into(&self, (frame, state): &mut Self::SavedState) {
+        *frame = self.frame;
+        self.game.save_state_into(state);
+    }
+    fn load_state(&mut self, (frame, load): &Self::SavedState) {
+        self.frame = *frame;
+        self.game.load_state(load);
+    }
+    fn checksum(&self) -> u64 {
+        self.game.checksum()
+    }
+}
+
+pub struct Peer<Game: RollbackableGameState, P = RepeatLast> {
+    pub client: NetcodeClient<Game::Input, (usize, Game::SavedState), P>,
+    pub game: Recorder<Game>,
+    handle: PlayerHandle,
+    next_input_frame: usize,
+    confirmed_inputs: Vec<Vec<Game::Input>>,
+}
+
+impl<Game: RollbackableGameState, P> Peer<Game, P> {
+    pub fn handle(&self) -> PlayerHandle {
+        self.handle
+    }
+
+    pub fn confirmed_frames(&self) -> usize {
+        self.confirmed_inputs.len()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimulationError {
+    /// A peer's client hit an error it couldn't recover from.
+    Netcode { peer: usize, error: NetcodeError },
+    /// Not every peer confirmed the target frame before the tick limit.
+    TimedOut { tick: usize },
+    /// Peers confirmed different inputs for a frame.
+    InputMismatch { frame: usize },
+    /// Peers ended a confirmed frame with different states.
+    Desync { frame: usize },
+    /// A peer confirmed a frame twice, or skipped one.
+    ConfirmedOutOfOrder { peer: usize, frame: usize },
+}
+
+/// Runs one client per player, each owning a single local player,
+/// and passes their packets to each other over a simulated network.
+pub struct Simulation<Game: RollbackableGameState, P = RepeatLast> {
+    network: Network,
+    peers: Vec<Peer<Game, P>>,
+    inputs: Box<dyn FnMut(PlayerHandle, usize) -> Game::Input>,
+}
+
+impl<Game, P> Simulation<Game, P>
+where
+    Game: RollbackableGameState,
+    Game::Input: Clone + Default + PartialEq + Debug + Serialize + DeserializeOwned,
+    P: Predictor<Game::Input> + Default,
+{
+    /// Creates a peer for each game, where peer `n` plays as handle `n`.
+    /// `inputs` is asked for each player's input for a frame exactly once, in frame order.
+    pub fn new<Inputs>(games: Vec<Game>, config: NetworkConfig, seed: u64, inputs: Inputs) -> Self
+    where
+        Inputs: FnMut(PlayerHandle, usize) -> Game::Input + 'static,
+    {
+        let player_count = games.len();
+        let peers = games
+            .into_iter()
+            .enumerate()
+            .map(|(handle, game)| {
+                let mut client = NetcodeClient::new(60);
+                for player in 0..player_count {
+                    if player == handle {
+                        client.add_local_player(player);
+                    } else {
+                        client.add_network_player(player);
+                    }
+                }
+                Peer {
+                    client,
+                    game: Recorder::new(game),
+                    handle,
+                    next_input_frame: 0,
+                    confirmed_inputs: Vec::new(),
+                }
+            })
+            .collect();
+
+        Self {
+            network: Network::new(config, ChaCha8Rng::seed_from_u64(seed)),
+            peers,
+            inputs: Box::new(inputs),
+        }
+    }
+
+    pub fn peers(&self) -> &[Peer<Game, P>] {
+        &self.peers
+    }
+
+    pub fn peers_mut(&mut self) -> &mut [Peer<Game, P>] {
+        &mut self.peers
+    }
+
+    pub fn network_stats(&self) -> NetworkStats {
+        self.network.stats()
+    }
+
+    fn broadcast(&mut self, from: usize, packet: &Packet<Game::Input>) {
+        let data = bincode::serialize(packet).unwrap();
+        for to in (0..self.peers.len()).filter(|to| *to != from) {
+            self.network.send(from, to, data.clone());
+        }
+    }
+
+    /// Has every peer propose an input delay for `round`, from the pings they've been given.
+    pub fn propose_input_delay(&mut self, round: usize) {
+        for idx in 0..self.peers.len() {
+            if let Some(packet) = self.peers[idx].client.propose_input_delay(round) {
+                self.broadcast(idx, &packet);
+            }
+        }
+    }
+
+    /// Runs every peer for a single frame, then advances the network.
+    pub fn tick(&mut self) -> Result<(), SimulationError> {
+        for idx in 0..self.peers.len() {
+            for (from, data) in self.network.receive(idx) {
+                let packet = bincode::deserialize(&data).unwrap();
+                let sender = self.peers[from].handle;
+                match self.peers[idx].client.handle_packet_from(&[sender], packet) {
+                    Ok(Some(response)) => {
+                        self.network
+                            .send(idx, from, bincode::serialize(&response).unwrap());
+                    }
+                    Ok(None) => (),
+                    Err(error) if error.is_recoverable() => (),
+                    Err(error) => return Err(SimulationError::Netcode { peer: idx, error }),
+                }
+            }
+
+            let peer = &mut self.peers[idx];
+            let input = (self.inputs)(peer.handle, peer.next_input_frame);
+            let input_packet = peer
+                .client
+                .handle_local_input(input, peer.handle)
+                .map_err(|error| SimulationError::Netcode { peer: idx, error })?;
+            if input_packet.is_some() {
+                peer.next_input_frame += 1;
+            }
+
+            let update_packet = peer
+                .client
+                .update(&mut peer.game)
+                .map_err(|error| SimulationError::Netcode { peer: idx, error })?;
+            // replays are written straight from these, so they have to arrive exactly once, in order
+            for (frame, inputs) in peer.client.drain_confirmed_inputs() {
+                if frame != peer.confirmed_inputs.len() {
+                    return Err(SimulationError::ConfirmedOutOfOrder { peer: idx, frame });
+                }
+                peer.confirmed_inputs.push(inputs);
+            }
+
+            for packet in input_packet.iter().chain(update_packet.iter()) {
+                self.broadcast(idx, packet);
+            }
+        }
+
+        self.network.tick();
+        Ok(())
+    }
+
+    /// Ticks until every peer has confirmed `frames` frames, then checks that they all agree.
+    pub fn run(&mut self, frames: usize, max_ticks: usize) -> Result<(), SimulationError> {
+        while self
+            .peers
+            .iter()
+            .any(|peer| peer.confirmed_frames() < frames)
+        {
+            if self.network.current_tick() >= max_ticks {
+                return Err(SimulationError::TimedOut {
+                    tick: self.network.current_tick(),
+                });
+            }
+            self.tick()?;
+        }
+
+        self.verify(frames)
+    }
+
+    /// Checks that every peer confirmed the same inputs, and ended up in the same state,
+    /// for the first `frames` frames.
+    pub fn verify(&self, frames: usize) -> Result<(), SimulationError> {
+        let (first, rest) = self.peers.split_first().unwrap();
+
+        for frame in 0..frames {
+            if rest
+                .iter()
+                .any(|peer| peer.confirmed_inputs.get(frame) != first.confirmed_inputs.get(frame))
+            {
+                return Err(SimulationError::InputMismatch { frame });
+            }
+        }
+
+        for frame in 0..=frames {
+            let checksum = first.game.checksum_after(frame);
+            if checksum.is_none()
+                || rest
+                    .iter()
+                    .any(|peer| peer.game.checksum_after(frame) != checksum)
+            {
+                return Err(SimulationError::Desync { frame });
+            }
+        }
+
+        if let Some(desync) = self
+            .peers
+            .iter()
+            .filter_map(|peer| peer.client.desync())
+            .min_by_key(|desync| desync.frame)
+        {
+            return Err(SimulationError::Desync {
+                frame: desync.frame,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Simulation, SimulationError};
+    use crate::network::NetworkConfig;
+    use crate::test_game::{random_inputs, HashGame};
+    use fg_input::InputState;
+    use fg_rollback::{Neutral, PredictionStats, Predictor, RepeatLast, RepeatLastReleaseButtons};
+
+    const FRAMES: usize = 600;
+    const MAX_TICKS: usize = 6000;
+
+    fn run_with<P: Predictor<InputState> + Default>(
+        players: usize,
+        config: NetworkConfig,
+        seed: u64,
+    ) -> Result<PredictionStats, SimulationError> {
+        let mut simulation = Simulation::<_, P>::new(
+            vec![HashGame::new(players); players],
+            config,
+            seed,
+            random_inputs(seed),
+        );
+        for peer in simulation.peers_mut() {
+            peer.client.set_input_delay(2);
+            peer.client.set_allowed_rollback(8);
+        }
+        simulation.run(FRAMES, MAX_TICKS)?;
+
+        Ok(simulation
+            .peers()
+            .iter()
+            .map(|peer| peer.client.prediction_stats())
+            .fold(PredictionStats::default(), |acc, stats| PredictionStats {
+                correct: acc.correct + stats.correct,
+                wrong: acc.wrong + stats.wrong,
+            }))
+    }
+
+    fn run(players: usize, config: NetworkConfig, seed: u64) -> Result<(), SimulationError> {
+        run_with::<RepeatLast>(players, config, seed).map(|_| ())
+    }
+
+    fn bad_network() -> NetworkConfig {
+        NetworkConfig {
+            latency: 3,
+            jitter: 3,
+            loss: 0.05,
+            duplication: 0.05,
+            reordering: 0.05,
+        }
+    }
+
+    #[test]
+    fn perfect_network() {
+        assert_eq!(run(2, NetworkConfig::default(), 0), Ok(()));
+    }
+
+    #[test]
+    fn latency() {
+        let config = NetworkConfig {
+            latency: 5,
+            ..NetworkConfig::default()
+        };
+        assert_eq!(run(2, config, 0), Ok(()));
+    }
+
+    #[test]
+    fn bad_network_stays_in_sync() {
+        for seed in 0..8 {
+            assert_eq!(run(2, bad_network(), seed), Ok(()), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn more_players_stay_in_sync() {
+        for seed in 0..4 {
+            assert_eq!(run(4, bad_network(), seed), Ok(()), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn dummy_players_stay_in_sync() {
+        // two players and two empty slots, like a 2v2 with only one player on each team
+        let mut simulation = Simulation::<_, RepeatLast>::new(
+            vec![HashGame::new(4); 2],
+            bad_network(),
+            0,
+            random_inputs(0),
+        );
+        for peer in simulation.peers_mut() {
+            peer.client.set_input_delay(2);
+            peer.client.add_dummy_player(2);
+            peer.client.add_dummy_player(3);
+        }
+
+        assert_eq!(simulation.run(FRAMES, MAX_TICKS), Ok(()));
+    }
+
+    #[test]
+    fn input_delay_changes_stay_in_sync() {
+        let mut simulation = Simulation::<_, RepeatLast>::new(
+            vec![HashGame::new(2); 2],
+            bad_network(),
+            0,
+            random_inputs(0),
+        );
+        for peer in simulation.peers_mut() {
+            let other = 1 - peer.handle();
+            peer.client.set_input_delay(2);
+            peer.client.set_allowed_rollback(8);
+            peer.client.set_input_delay_bounds(1, 5);
+            peer.client.add_rtt_sample(other, 200.0).unwrap();
+        }
+
+        assert_eq!(simulation.run(FRAMES / 3, MAX_TICKS), Ok(()));
+        simulation.propose_input_delay(1);
+        assert_eq!(simulation.run(FRAMES * 2 / 3, MAX_TICKS), Ok(()));
+        assert!(simulation
+            .peers()
+            .iter()
+            .all(|peer| peer.client.input_delay() == 5));
+
+        for peer in simulation.peers_mut() {
+            let other = 1 - peer.handle();
+            for _ in 0..100 {
+                peer.client.add_rtt_sample(other, 0.0).unwrap();
+            }
+        }
+        simulation.propose_input_delay(2);
+        assert_eq!(simulation.run(FRAMES, MAX_TICKS), Ok(()));
+        assert!(simulation
+            .peers()
+            .iter()
+            .all(|peer| peer.client.input_delay() == 1));
+    }
+
+    #[test]
+    fn compared_predictors_see_the_same_inputs() {
+        let mut simulation = Simulation::<_, RepeatLast>::new(
+            vec![HashGame::new(2); 2],
+            bad_network(),
+            0,
+            random_inputs(0),
+        );
+        for peer in simulation.peers_mut() {
+            peer.client.set_input_delay(2);
+            peer.client.compare_predictor(Neutral);
+            peer.client.compare_predictor(RepeatLastReleaseButtons);
+        }
+        assert_eq!(simulation.run(FRAMES, MAX_TICKS), Ok(()));
+
+        for peer in simulation.peers() {
+            let stats = peer.client.prediction_stats_by_strategy();
+            assert_eq!(stats.len(), 3);
+            assert_eq!(stats[0].1, peer.client.prediction_stats());
+            assert!(stats[0].1.total() > 0);
+            assert!(stats
+                .iter()
+                .all(|(_, strategy)| strategy.total() == stats[0].1.total()));
+        }
+    }
+
+    #[test]
+    fn every_predictor_stays_in_sync() {
+        let stats = [
+            run_with::<RepeatLast>(2, bad_network(), 0),
+            run_with::<RepeatLastReleaseButtons>(2, bad_network(), 0),
+            run_with::<Neutral>(2, bad_network(), 0),
+        ];
+        for stats in stats.iter() {
+            let stats = stats.unwrap();
+            assert!(stats.total() > 0);
+        }
+    }
+}
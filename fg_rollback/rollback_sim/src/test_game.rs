@@ -0,0 +1,92 @@
+use fg_input::axis::Axis;
+use fg_input::button::ButtonState;
+use fg_input::InputState;
+use fg_rollback::{InputSet, PlayerHandle, RollbackableGameState};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// A tiny game that folds every input it's given into a hash, so any input that's
+/// mispredicted and never corrected changes its checksum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashGame {
+    players: usize,
+    state: u64,
+}
+
+impl HashGame {
+    pub fn new(players: usize) -> Self {
+        Self { players, state: 0 }
+    }
+}
+
+impl RollbackableGameState for HashGame {
+    type Input = InputState;
+    type SavedState = u64;
+
+    fn player_count(&self) -> usize {
+        self.players
+    }
+
+    fn advance_frame(&mut self, input: InputSet<'_, Self::Input>) {
+        let mut hasher = DefaultHasher::new();
+        self.state.hash(&mut hasher);
+        for inputs in input.inputs {
+            inputs.last().hash(&mut hasher);
+        }
+        self.state = hasher.finish();
+    }
+    fn save_state(&self) -> Self::SavedState {
+        self.state
+    }
+    fn load_state(&mut self, load: &Self::SavedState) {
+        self.state = *load;
+    }
+    fn checksum(&self) -> u64 {
+        self.state
+    }
+}
+
+const AXES: [Axis; 9] = [
+    Axis::Neutral,
+    Axis::Up,
+    Axis::Down,
+    Axis::Right,
+    Axis::Left,
+    Axis::UpRight,
+    Axis::UpLeft,
+    Axis::DownRight,
+    Axis::DownLeft,
+];
+
+// chance an input changes from one frame to the next, players hold inputs for a while
+const CHANGE_CHANCE: f64 = 0.2;
+
+/// Seeded random inputs for each player, that change every few frames like a real player's.
+pub fn random_inputs(seed: u64) -> impl FnMut(PlayerHandle, usize) -> InputState {
+    let mut players: HashMap<PlayerHandle, (ChaCha8Rng, InputState)> = HashMap::new();
+
+    move |handle, _| {
+        let (rng, input) = players.entry(handle).or_insert_with(|| {
+            (
+                ChaCha8Rng::seed_from_u64(seed ^ handle as u64),
+                InputState::default(),
+            )
+        });
+
+        if rng.gen_bool(CHANGE_CHANCE) {
+            input.axis = AXES[rng.gen_range(0..AXES.len())];
+            for button in input.buttons.iter_mut() {
+                *button = if rng.gen_bool(0.5) {
+                    ButtonState::Pressed
+                } else {
+                    ButtonState::Released
+                };
+            }
+        }
+
+        *input
+    }
+}
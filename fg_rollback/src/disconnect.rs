@@ -0,0 +1,140 @@
+use super::{NetcodeClient, NetcodeError, PlayerHandle, Predictor};
+use std::collections::HashMap;
+
+/// Tracks how long it's been since each networked player was last heard from.
+pub(super) struct DisconnectTimer {
+    // updates since each player's input last arrived
+    frames_since_input: HashMap<PlayerHandle, usize>,
+    // updates without input before a player is shown as possibly disconnected
+    notify_start: usize,
+    // updates without input before a player is considered disconnected
+    timeout: usize,
+}
+
+impl DisconnectTimer {
+    pub fn new() -> Self {
+        Self {
+            frames_since_input: HashMap::new(),
+            notify_start: 60,
+            timeout: 300,
+        }
+    }
+
+    pub fn add_player(&mut self, player: PlayerHandle) {
+        self.frames_since_input.insert(player, 0);
+    }
+
+    pub fn reset(&mut self, player: PlayerHandle) {
+        if let Some(frames) = self.frames_since_input.get_mut(&player) {
+            *frames = 0;
+        }
+    }
+
+    // the player that hasn't been heard from the longest
+    fn longest_waiting(&self) -> Option<(PlayerHandle, usize)> {
+        self.frames_since_input
+            .iter()
+            .map(|(player, frames)| (*player, *frames))
+            .max_by_key(|(player, frames)| (*frames, std::cmp::Reverse(*player)))
+    }
+}
+
+impl<Input: Clone + Default + PartialEq + std::fmt::Debug, GameState, P: Predictor<Input>>
+    NetcodeClient<Input, GameState, P>
+{
+    /// After `notify_start` updates without input from a player, `disconnect_countdown` starts
+    /// counting down, and after `timeout` updates `update` returns `NetcodeError::Disconnected`.
+    pub fn set_disconnect_timeout(&mut self, notify_start: usize, timeout: usize) {
+        assert!(
+            notify_start <= timeout,
+            "The disconnect notification must start before the timeout."
+        );
+        self.disconnect_timer.notify_start = notify_start;
+        self.disconnect_timer.timeout = timeout;
+    }
+
+    /// The player that's been silent the longest and how many more updates until they're
+    /// considered disconnected, once they've been silent long enough to let the user know.
+    pub fn disconnect_countdown(&self) -> Option<(PlayerHandle, usize)> {
+        self.disconnect_timer
+            .longest_waiting()
+            .filter(|(_, frames)| *frames >= self.disconnect_timer.notify_start)
+            .map(|(player, frames)| (player, self.disconnect_timer.timeout.saturating_sub(frames)))
+    }
+
+    pub(super) fn check_disconnects(&mut self) -> Result<(), NetcodeError> {
+        for frames in self.disconnect_timer.frames_since_input.values_mut() {
+            *frames += 1;
+        }
+
+        match self.disconnect_timer.longest_waiting() {
+            Some((player, frames)) if frames >= self.disconnect_timer.timeout => {
+                Err(NetcodeError::Disconnected(player))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{InputSet, NetcodeClient, NetcodeError, Packet, RollbackableGameState};
+
+    struct Game;
+
+    impl RollbackableGameState for Game {
+        type Input = i32;
+        type SavedState = ();
+
+        fn player_count(&self) -> usize {
+            2
+        }
+        fn advance_frame(&mut self, _: InputSet<'_, Self::Input>) {}
+        fn save_state(&self) -> Self::SavedState {}
+        fn load_state(&mut self, _: &Self::SavedState) {}
+        fn checksum(&self) -> u64 {
+            0
+        }
+    }
+
+    fn client() -> NetcodeClient<i32, ()> {
+        let mut client = NetcodeClient::new(1);
+        client.add_local_player(0);
+        client.add_network_player(1);
+        client.set_disconnect_timeout(2, 5);
+        client
+    }
+
+    #[test]
+    fn counts_down_without_input() {
+        let mut client = client();
+        let mut game = Game;
+
+        assert_eq!(client.disconnect_countdown(), None);
+        for _ in 0..2 {
+            client.update(&mut game).unwrap();
+        }
+        assert_eq!(client.disconnect_countdown(), Some((1, 3)));
+        for _ in 0..2 {
+            client.update(&mut game).unwrap();
+        }
+        assert_eq!(
+            client.update(&mut game).err(),
+            Some(NetcodeError::Disconnected(1))
+        );
+    }
+
+    #[test]
+    fn input_resets_countdown() {
+        let mut client = client();
+        let mut game = Game;
+
+        for frame in 0..10 {
+            client.update(&mut game).unwrap();
+            client
+                .handle_packet(Packet::Inputs(1, frame, 0, vec![0], 0.0))
+                .unwrap();
+        }
+        assert_eq!(client.disconnect_countdown(), None);
+    }
+}
@@ -0,0 +1,148 @@
+use super::PlayerHandle;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetcodeError {
+    /// The handle isn't a player of the expected type, e.g. a packet claiming to be input
+    /// for one of our local players.
+    InvalidHandle(PlayerHandle),
+    /// The frame is older than any input that's still held.
+    FrameTooOld(usize),
+    /// The frame is further ahead than a remote client could have gotten.
+    FrameTooNew(usize),
+    /// A misprediction happened on a frame that's no longer in the rollback window.
+    RollbackTooFar(usize),
+    /// A rollback needed to simulate a frame that has no input, predicted or otherwise.
+    MissingInput(usize),
+    /// The game expects input for the first count of players, but the client has the second.
+    PlayerCountMismatch(usize, usize),
+    /// No input has arrived from the player for longer than the disconnect timeout.
+    Disconnected(PlayerHandle),
+}
+
+impl NetcodeError {
+    /// Recoverable errors only mean a packet was dropped, and the match can keep going.
+    /// Anything else means the clients can no longer agree, and the match should end.
+    pub fn is_recoverable(&self) -> bool {
+        match self {
+            NetcodeError::InvalidHandle(_)
+            | NetcodeError::FrameTooOld(_)
+            | NetcodeError::FrameTooNew(_) => true,
+            NetcodeError::RollbackTooFar(_)
+            | NetcodeError::MissingInput(_)
+            | NetcodeError::PlayerCountMismatch(..)
+            | NetcodeError::Disconnected(_) => false,
+        }
+    }
+}
+
+impl Display for NetcodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetcodeError::InvalidHandle(handle) => write!(f, "invalid player handle {}", handle),
+            NetcodeError::FrameTooOld(frame) => write!(f, "frame {} is no longer held", frame),
+            NetcodeError::FrameTooNew(frame) => write!(f, "frame {} is too far ahead", frame),
+            NetcodeError::RollbackTooFar(frame) => {
+                write!(f, "can't rollback to frame {}, it's too far back", frame)
+            }
+            NetcodeError::MissingInput(frame) => write!(f, "no input for frame {}", frame),
+            NetcodeError::PlayerCountMismatch(expected, actual) => write!(
+                f,
+                "the game expects {} players, but the client has {}",
+                expected, actual
+            ),
+            NetcodeError::Disconnected(handle) => write!(f, "player {} disconnected", handle),
+        }
+    }
+}
+
+impl std::error::Error for NetcodeError {}
+
+#[cfg(test)]
+mod test {
+    use super::super::{NetcodeClient, Packet};
+    use super::NetcodeError;
+
+    fn client() -> NetcodeClient<i32, i32> {
+        let mut client = NetcodeClient::new(1);
+        client.add_local_player(0);
+        client.add_network_player(1);
+        client
+    }
+
+    #[test]
+    fn invalid_handles() {
+        let mut client = client();
+
+        assert_eq!(
+            client
+                .handle_packet(Packet::Inputs(0, 0, 0, vec![1], 0.0))
+                .err(),
+            Some(NetcodeError::InvalidHandle(0))
+        );
+        assert_eq!(
+            client
+                .handle_packet(Packet::Inputs(2, 0, 0, vec![1], 0.0))
+                .err(),
+            Some(NetcodeError::InvalidHandle(2))
+        );
+        assert_eq!(
+            client.handle_local_input(1, 1).err(),
+            Some(NetcodeError::InvalidHandle(1))
+        );
+        assert_eq!(
+            client.get_network_delay(0).err(),
+            Some(NetcodeError::InvalidHandle(0))
+        );
+    }
+
+    #[test]
+    fn valid_packets() {
+        let mut client = client();
+
+        assert!(client
+            .handle_packet(Packet::Inputs(1, 0, 0, vec![1, 2], 0.0))
+            .is_ok());
+        assert!(client.handle_local_input(1, 0).is_ok());
+    }
+
+    #[test]
+    fn far_future_frames() {
+        let mut client = client();
+
+        assert_eq!(
+            client
+                .handle_packet(Packet::Inputs(1, 0, 1 << 40, vec![1], 0.0))
+                .err(),
+            Some(NetcodeError::FrameTooNew(1 << 40))
+        );
+        assert_eq!(
+            client
+                .handle_packet(Packet::Inputs(1, 0, usize::MAX, vec![1, 2], 0.0))
+                .err(),
+            Some(NetcodeError::FrameTooNew(usize::MAX))
+        );
+        assert_eq!(
+            client
+                .handle_packet(Packet::Inputs(1, usize::MAX, 0, vec![1], 0.0))
+                .err(),
+            Some(NetcodeError::FrameTooNew(usize::MAX))
+        );
+        assert_eq!(
+            client.handle_packet(Packet::Request(usize::MAX)).err(),
+            Some(NetcodeError::FrameTooNew(usize::MAX))
+        );
+        assert_eq!(
+            client
+                .handle_packet(Packet::Provide(vec![(1, usize::MAX, vec![1])]))
+                .err(),
+            Some(NetcodeError::FrameTooNew(usize::MAX))
+        );
+        assert_eq!(
+            client
+                .handle_packet(Packet::Checksums(vec![(0, 0), (usize::MAX, 0)]))
+                .err(),
+            Some(NetcodeError::FrameTooNew(usize::MAX))
+        );
+    }
+}
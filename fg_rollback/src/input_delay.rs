@@ -0,0 +1,371 @@
+use super::{NetcodeClient, NetcodeError, Packet, PlayerHandle, PlayerType, Predictor};
+use std::collections::HashMap;
+
+const FRAME_MS: f32 = 1000.0 / 60.0;
+// frames between resending a proposal that hasn't been agreed to yet
+const RESEND_INTERVAL: usize = 10;
+// how far after a proposal the new delay takes effect, so everyone has agreed before then
+const SWITCH_LEAD: usize = 30;
+// a remote client may have started the next round before we have, but not any further
+const MAX_ROUNDS_AHEAD: usize = 1;
+
+/// A running estimate of the round trip time to a player, and how much it varies.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RttEstimate {
+    /// Smoothed round trip time in milliseconds.
+    pub rtt: f32,
+    /// Smoothed deviation of the round trip time in milliseconds.
+    pub jitter: f32,
+}
+
+impl RttEstimate {
+    fn add_sample(estimate: Option<Self>, sample: f32) -> Self {
+        match estimate {
+            None => Self {
+                rtt: sample,
+                jitter: sample / 2.0,
+            },
+            Some(estimate) => Self {
+                rtt: estimate.rtt * 0.875 + sample * 0.125,
+                jitter: estimate.jitter * 0.75 + (estimate.rtt - sample).abs() * 0.25,
+            },
+        }
+    }
+
+    /// How many frames it takes an input to reach this player, with some room for jitter.
+    fn delay_frames(&self) -> usize {
+        ((self.rtt / 2.0 + self.jitter * 2.0) / FRAME_MS).ceil() as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Proposal {
+    delay: usize,
+    // the frame the delay would start on
+    frame: usize,
+}
+
+pub(super) struct Negotiation {
+    round: usize,
+    local: Option<Proposal>,
+    remote: HashMap<PlayerHandle, Proposal>,
+    agreed: bool,
+    // the agreed delay, until the frame it starts on
+    pending: Option<Proposal>,
+}
+
+impl Negotiation {
+    fn new(round: usize) -> Self {
+        Self {
+            round,
+            local: None,
+            remote: HashMap::new(),
+            agreed: false,
+            pending: None,
+        }
+    }
+}
+
+impl<Input: Clone + Default + PartialEq + std::fmt::Debug, GameState, P: Predictor<Input>>
+    NetcodeClient<Input, GameState, P>
+{
+    /// The input delay picked automatically is always kept between `min` and `max`.
+    pub fn set_input_delay_bounds(&mut self, min: usize, max: usize) {
+        assert!(
+            min <= max,
+            "Minimum input delay must not exceed the maximum."
+        );
+        self.input_delay_bounds = (min, max);
+    }
+
+    pub fn add_rtt_sample(&mut self, player: PlayerHandle, rtt: f32) -> Result<(), NetcodeError> {
+        self.check_player_type(player, PlayerType::Net)?;
+
+        let estimate = RttEstimate::add_sample(self.rtt.get(&player).copied(), rtt);
+        self.rtt.insert(player, estimate);
+        Ok(())
+    }
+
+    pub fn rtt(&self, player: PlayerHandle) -> Option<RttEstimate> {
+        self.rtt.get(&player).copied()
+    }
+
+    /// The input delay that would hide the latency to the slowest player, or `None` if there
+    /// isn't a ping for every networked player yet.
+    pub fn recommended_input_delay(&self) -> Option<usize> {
+        let (min, max) = self.input_delay_bounds;
+        self.net_players
+            .keys()
+            .map(|player| self.rtt.get(player).map(RttEstimate::delay_frames))
+            .try_fold(min, |delay, player_delay| {
+                player_delay.map(|player_delay| delay.max(player_delay))
+            })
+            .map(|delay| delay.min(max))
+    }
+
+    /// Proposes the recommended input delay to every other client. Call this at the start of
+    /// every round, while inputs are ignored, so the delay can change without anyone noticing.
+    /// Once everyone has proposed a delay for the round, the largest one is used by everyone,
+    /// starting on the latest frame anyone proposed it for.
+    /// Returns `None` if a delay was already proposed for this round, or there's no ping yet.
+    pub fn propose_input_delay(&mut self, round: usize) -> Option<Packet<Input>> {
+        self.local_round = self.local_round.max(round);
+        let delay = self.recommended_input_delay()?;
+
+        match &self.negotiation {
+            Some(negotiation)
+                if negotiation.round > round
+                    || (negotiation.round == round && negotiation.local.is_some()) =>
+            {
+                return None
+            }
+            Some(negotiation) if negotiation.round == round => (),
+            _ => self.negotiation = Some(Negotiation::new(round)),
+        }
+
+        self.negotiation.as_mut().unwrap().local = Some(Proposal {
+            delay,
+            frame: self.current_frame + SWITCH_LEAD,
+        });
+        self.check_negotiation();
+
+        let packet = self.negotiation_packet();
+        if packet.is_some() {
+            self.stats.packets_sent += 1;
+        }
+        packet
+    }
+
+    pub(super) fn handle_input_delay(
+        &mut self,
+        round: usize,
+        players: Vec<PlayerHandle>,
+        delay: usize,
+        frame: usize,
+        agreed: bool,
+    ) -> Result<Option<Packet<Input>>, NetcodeError> {
+        for player in players.iter() {
+            self.check_player_type(*player, PlayerType::Net)?;
+        }
+        if frame > self.latest_remote_frame() + SWITCH_LEAD {
+            return Err(NetcodeError::FrameTooNew(frame));
+        }
+        // otherwise a made up round would hold off every proposal until we got to it
+        if round > self.local_round + MAX_ROUNDS_AHEAD {
+            return Ok(None);
+        }
+        // the delay is used by everyone, so no one gets to go past what we allow
+        let delay = delay.min(self.input_delay_bounds.1);
+
+        match &self.negotiation {
+            Some(negotiation) if negotiation.round > round => return Ok(None),
+            Some(negotiation) if negotiation.round == round => (),
+            _ => self.negotiation = Some(Negotiation::new(round)),
+        }
+
+        let negotiation = self.negotiation.as_mut().unwrap();
+        for player in players {
+            negotiation.remote.insert(player, Proposal { delay, frame });
+        }
+        self.check_negotiation();
+
+        // they're still waiting on us, but only answer if we've got something to answer with
+        if agreed {
+            Ok(None)
+        } else {
+            Ok(self.negotiation_packet())
+        }
+    }
+
+    // a proposal that hasn't been agreed to yet gets sent again every so often
+    pub(super) fn resend_negotiation(&self) -> Option<Packet<Input>> {
+        if self.current_frame % RESEND_INTERVAL == 0 {
+            self.negotiation
+                .as_ref()
+                .filter(|negotiation| !negotiation.agreed)
+                .and_then(|_| self.negotiation_packet())
+        } else {
+            None
+        }
+    }
+
+    fn negotiation_packet(&self) -> Option<Packet<Input>> {
+        let negotiation = self.negotiation.as_ref()?;
+        negotiation.local.map(|local| {
+            Packet::InputDelay(
+                negotiation.round,
+                self.local_players.keys().copied().collect(),
+                local.delay,
+                local.frame,
+                negotiation.agreed,
+            )
+        })
+    }
+
+    fn check_negotiation(&mut self) {
+        let negotiation = self.negotiation.as_mut().unwrap();
+        if negotiation.agreed {
+            return;
+        }
+
+        if let Some(local) = negotiation.local {
+            if self
+                .net_players
+                .keys()
+                .all(|player| negotiation.remote.contains_key(player))
+            {
+                negotiation.agreed = true;
+                negotiation.pending = Some(negotiation.remote.values().fold(
+                    local,
+                    |agreed, proposal| Proposal {
+                        delay: agreed.delay.max(proposal.delay),
+                        frame: agreed.frame.max(proposal.frame),
+                    },
+                ));
+            }
+        }
+    }
+
+    // everyone starts the agreed delay on the same frame, or as soon as they can if
+    // agreeing took so long they're already past it
+    pub(super) fn apply_agreed_input_delay(&mut self) {
+        let current_frame = self.current_frame;
+        if let Some(agreed) = self.negotiation.as_mut().and_then(|negotiation| {
+            negotiation
+                .pending
+                .filter(|pending| pending.frame <= current_frame)
+                .and_then(|_| negotiation.pending.take())
+        }) {
+            self.change_input_delay(agreed.delay);
+        }
+    }
+
+    // when the delay goes up, the frames in between are filled with the last input so
+    // there's no gap in the local input, and when it goes down inputs are dropped until
+    // the current frame catches up
+    fn change_input_delay(&mut self, delay: usize) {
+        for local_player in self.local_players.values_mut() {
+            for _ in self.input_delay..delay {
+                let last_input = local_player.last_input().cloned().unwrap_or_default();
+                local_player.add_input(last_input);
+            }
+        }
+        self.input_delay = delay;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{InputSet, NetcodeClient, NetcodeError, Packet, RollbackableGameState};
+    use super::SWITCH_LEAD;
+
+    struct Game;
+
+    impl RollbackableGameState for Game {
+        type Input = i32;
+        type SavedState = ();
+
+        fn player_count(&self) -> usize {
+            2
+        }
+        fn advance_frame(&mut self, _: InputSet<'_, Self::Input>) {}
+        fn save_state(&self) -> Self::SavedState {}
+        fn load_state(&mut self, _: &Self::SavedState) {}
+        fn checksum(&self) -> u64 {
+            0
+        }
+    }
+
+    fn client(local: usize, net: usize) -> NetcodeClient<i32, ()> {
+        let mut client = NetcodeClient::new(1);
+        client.add_local_player(local);
+        client.add_network_player(net);
+        client.set_input_delay(2);
+        client.set_input_delay_bounds(1, 6);
+        client
+    }
+
+    #[test]
+    fn recommends_within_bounds() {
+        let mut client = client(0, 1);
+        assert_eq!(client.recommended_input_delay(), None);
+
+        client.add_rtt_sample(1, 0.0).unwrap();
+        assert_eq!(client.recommended_input_delay(), Some(1));
+
+        for _ in 0..10 {
+            client.add_rtt_sample(1, 1000.0).unwrap();
+        }
+        assert_eq!(client.recommended_input_delay(), Some(6));
+    }
+
+    #[test]
+    fn agree_on_largest() {
+        let mut lhs = client(0, 1);
+        let mut rhs = client(1, 0);
+        lhs.add_rtt_sample(1, 100.0).unwrap();
+        rhs.add_rtt_sample(0, 30.0).unwrap();
+        let lhs_delay = lhs.recommended_input_delay().unwrap();
+
+        let proposal = lhs.propose_input_delay(1).unwrap();
+        assert!(lhs.propose_input_delay(1).is_none());
+
+        // rhs hasn't proposed yet, so it has nothing to answer with
+        assert!(rhs.handle_packet(proposal).unwrap().is_none());
+
+        let proposal = rhs.propose_input_delay(1).unwrap();
+        // lhs answers the proposal that was already agreed to, but doesn't need to
+        assert!(matches!(proposal, Packet::InputDelay(1, _, _, _, true)));
+        assert!(lhs.handle_packet(proposal).unwrap().is_none());
+
+        // both start the new delay on the same frame, which hasn't come yet
+        for client in [&lhs, &rhs].iter() {
+            let pending = client.negotiation.as_ref().unwrap().pending.unwrap();
+            assert_eq!((pending.delay, pending.frame), (lhs_delay, SWITCH_LEAD));
+            assert_eq!(client.input_delay(), 2);
+        }
+
+        let mut game = Game;
+        while lhs.current_frame() <= SWITCH_LEAD {
+            assert_eq!(lhs.input_delay(), 2);
+            let frame = lhs.current_frame();
+            lhs.handle_local_input(0, 0).unwrap();
+            lhs.handle_net_input(frame, 0, 1).unwrap();
+            lhs.update(&mut game).unwrap();
+        }
+        assert_eq!(lhs.input_delay(), lhs_delay);
+    }
+
+    #[test]
+    fn ignores_rounds_far_ahead() {
+        let mut client = client(0, 1);
+        client.add_rtt_sample(1, 0.0).unwrap();
+
+        assert!(client
+            .handle_packet(Packet::InputDelay(1000, vec![1], 6, 0, false))
+            .unwrap()
+            .is_none());
+        assert!(client.negotiation.is_none());
+        assert!(client.propose_input_delay(1).is_some());
+    }
+    #[test]
+    fn only_sender_players() {
+        let mut client = client(0, 1);
+        client.add_network_player(2);
+
+        assert_eq!(
+            client
+                .handle_packet_from(&[1], Packet::InputDelay(1, vec![1, 2], 6, 0, false))
+                .err(),
+            Some(NetcodeError::InvalidHandle(2))
+        );
+        assert!(client.negotiation.is_none());
+
+        assert!(client
+            .handle_packet_from(&[1], Packet::InputDelay(1, vec![1], 6, 0, false))
+            .is_ok());
+        let negotiation = client.negotiation.as_ref().unwrap();
+        assert!(negotiation.remote.contains_key(&1));
+        assert!(!negotiation.remote.contains_key(&2));
+    }
+}
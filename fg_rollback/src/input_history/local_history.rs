@@ -14,6 +14,11 @@ impl<T: Default + Clone> LocalHistory<T> {
         }
     }
 
+    /// The oldest frame that's still held.
+    pub fn front_frame(&self) -> usize {
+        self.front_frame
+    }
+
     fn adjust_frame(&self, frame: usize) -> Option<usize> {
         frame.checked_sub(self.front_frame)
     }
@@ -26,6 +31,10 @@ impl<T: Default + Clone> LocalHistory<T> {
         self.front_frame + self.data.len() - 1
     }
 
+    pub fn last_input(&self) -> Option<&T> {
+        self.data.last()
+    }
+
     pub fn has_input(&self, frame: usize) -> bool {
         self.adjust_frame(frame)
             .and_then(|frame| self.data.get(frame))
@@ -34,7 +43,7 @@ impl<T: Default + Clone> LocalHistory<T> {
 
     pub fn get_inputs(&self, frame: usize, amt: usize) -> (InputRange, &[T]) {
         let frame = self.adjust_frame(frame).unwrap();
-        let end_idx = self.data.len().min(frame + 1);
+        let end_idx = self.data.len().min(frame.saturating_add(1));
         let start_idx = end_idx.saturating_sub(amt);
 
         (
@@ -1,6 +1,7 @@
 use std::cmp::Ordering;
 
 use super::InputRange;
+use crate::Predictor;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum Canon {
@@ -31,6 +32,11 @@ impl<T: Default + Clone + PartialEq> NetworkedHistory<T> {
         }
     }
 
+    /// The oldest frame that's still held.
+    pub fn front_frame(&self) -> usize {
+        self.front_frame
+    }
+
     fn adjust_frame(&self, frame: usize) -> Option<usize> {
         frame.checked_sub(self.front_frame)
     }
@@ -93,6 +99,12 @@ impl<T: Default + Clone + PartialEq> NetworkedHistory<T> {
             }
         }
     }
+    /// The input held for the frame, whether it's canon or predicted.
+    pub fn get_input(&self, frame: usize) -> Option<&T> {
+        self.adjust_frame(frame)
+            .and_then(|frame| self.data.get(frame))
+    }
+
     pub fn has_input(&self, frame: usize) -> bool {
         self.adjust_frame(frame)
             .and_then(|frame| self.canon.get(frame))
@@ -121,7 +133,7 @@ impl<T: Default + Clone + PartialEq> NetworkedHistory<T> {
     // TODO: deal with what happens if you can't get any inputs
     pub fn get_inputs(&self, frame: usize, amt: usize) -> (InputRange, &[T]) {
         let frame = self.adjust_frame(frame).unwrap();
-        let end_idx = self.data.len().min(frame + 1);
+        let end_idx = self.data.len().min(frame.saturating_add(1));
         let start_idx = end_idx.saturating_sub(amt);
 
         (
@@ -132,13 +144,9 @@ impl<T: Default + Clone + PartialEq> NetworkedHistory<T> {
             &self.data[start_idx..end_idx],
         )
     }
-    pub fn predict(&mut self, frame: usize) {
+    pub fn predict<P: Predictor<T>>(&mut self, frame: usize, predictor: &P) {
         let frame = self.adjust_frame(frame).unwrap();
-        let data = frame
-            .checked_sub(1)
-            .and_then(|frame| self.data.get(frame))
-            .cloned()
-            .unwrap_or_default();
+        let data = predictor.predict(frame.checked_sub(1).and_then(|frame| self.data.get(frame)));
 
         if frame == self.data.len() {
             self.canon.push(Canon::Predicted);
@@ -155,13 +163,9 @@ impl<T: Default + Clone + PartialEq> NetworkedHistory<T> {
         }
     }
 
-    pub fn repredict(&mut self, frame: usize) {
+    pub fn repredict<P: Predictor<T>>(&mut self, frame: usize, predictor: &P) {
         let frame = self.adjust_frame(frame).unwrap();
-        let data = frame
-            .checked_sub(1)
-            .and_then(|frame| self.data.get(frame))
-            .cloned()
-            .unwrap_or_default();
+        let data = predictor.predict(frame.checked_sub(1).and_then(|frame| self.data.get(frame)));
 
         assert_eq!(self.canon[frame], Canon::Predicted);
         self.data[frame] = data;
@@ -0,0 +1,868 @@
+mod disconnect;
+mod error;
+mod input_delay;
+mod input_history;
+mod predictor;
+mod saved_states;
+mod stats;
+mod sync_test;
+mod time_sync;
+use disconnect::DisconnectTimer;
+pub use error::NetcodeError;
+use input_delay::Negotiation;
+pub use input_delay::RttEstimate;
+use input_history::{LocalHistory, NetworkedHistory, PredictionResult};
+pub use predictor::{Neutral, PredictionStats, Predictor, RepeatLast, RepeatLastReleaseButtons};
+use saved_states::SavedStates;
+use serde::{Deserialize, Serialize};
+use stats::StatsTracker;
+pub use stats::{NetcodeStats, PlayerStats};
+use std::collections::{BTreeMap, HashMap};
+use sync_test::SyncTest;
+pub use sync_test::SyncTestFailure;
+use time_sync::TimeSync;
+
+// TODO, consider parameterizing the size of current_frame to not waste bytes on the fact that its
+// at least 4 bytes when 18 minutes of 60 FPS gameplay only needs a u16 (2 bytes)
+// TODO, add a bunch of functions to perform syncing of the clients, but not pass input back and forth
+
+// TODO, create getters/setters for all the public properties
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+enum PlayerType {
+    Local,
+    Net,
+    // a slot the game expects input for, that no one controls
+    Dummy,
+}
+
+pub type PlayerHandle = usize;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+struct PlayerInfo {
+    player_type: PlayerType,
+    id: PlayerHandle,
+}
+
+/// The inputs held for every player on a frame, ending with that frame's input.
+pub struct InputSet<'a, Input> {
+    /// Ordered by player handle, so the index is the player's slot in the game.
+    pub inputs: Vec<&'a [Input]>,
+    /// The handle each slot's inputs belong to.
+    pub handles: Vec<PlayerHandle>,
+}
+
+impl<'a, Input> InputSet<'a, Input> {
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    pub fn get(&self, handle: PlayerHandle) -> Option<&'a [Input]> {
+        self.handles
+            .iter()
+            .position(|slot_handle| *slot_handle == handle)
+            .map(|slot| self.inputs[slot])
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Packet<Input> {
+    // the player, the frame it was sent on, the first frame of input, the inputs, and the
+    // sender's frame advantage over whoever is furthest behind them
+    Inputs(PlayerHandle, usize, usize, Vec<Input>, f32),
+    Request(usize),
+    Provide(Vec<(PlayerHandle, usize, Vec<Input>)>),
+    Checksums(Vec<(usize, u64)>),
+    // the round, the sender's local players, their proposed input delay, the frame it would
+    // start on, and whether they've agreed
+    InputDelay(usize, Vec<PlayerHandle>, usize, usize, bool),
+}
+
+/// Reported when a remote client's checksum for a confirmed frame doesn't match ours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Desync {
+    pub frame: usize,
+    pub local: u64,
+    pub remote: u64,
+}
+
+pub struct NetcodeClient<Input, GameState, P = RepeatLast> {
+    predictor: P,
+    prediction_stats: PredictionStats,
+    // scored against the same inputs as `predictor`, without being used
+    compared_predictors: Vec<(Box<dyn Predictor<Input>>, PredictionStats)>,
+    stats: StatsTracker,
+    local_players: HashMap<PlayerHandle, LocalHistory<Input>>,
+    net_players: HashMap<PlayerHandle, NetworkedHistory<Input>>,
+    current_frame: usize,
+    held_input_count: usize,
+    // a dummy player always holds neutral input
+    dummy_inputs: Vec<Input>,
+    time_sync: HashMap<PlayerHandle, TimeSync>,
+    // the last frame the client waited on to let remote players catch up
+    last_wait_frame: Option<usize>,
+    disconnect_timer: DisconnectTimer,
+    saved_rollback_states: SavedStates<GameState>,
+    rollback_to: Option<usize>,
+    players: Vec<PlayerInfo>,
+    network_delay: HashMap<PlayerHandle, usize>,
+    input_delay: usize,
+    input_delay_bounds: (usize, usize),
+    rtt: HashMap<PlayerHandle, RttEstimate>,
+    negotiation: Option<Negotiation>,
+    // the latest round an input delay was proposed for
+    local_round: usize,
+    allowed_rollback: usize,
+    packet_buffer_size: usize,
+    // checksums of the state at the start of a frame, keyed by that frame
+    pending_checksums: HashMap<usize, u64>,
+    confirmed_checksums: BTreeMap<usize, u64>,
+    remote_checksums: BTreeMap<usize, u64>,
+    new_confirmed_checksum: bool,
+    desync: Option<Desync>,
+    sync_test: Option<SyncTest<GameState>>,
+    next_confirmed_frame: usize,
+    confirmed_inputs: Vec<(usize, Vec<Input>)>,
+}
+
+impl<Input, GameState, P> NetcodeClient<Input, GameState, P>
+where
+    Input: Clone + Default + PartialEq + std::fmt::Debug,
+    P: Predictor<Input> + Default,
+{
+    pub fn new(held_input_count: usize) -> Self {
+        Self::with_predictor(held_input_count, P::default())
+    }
+}
+
+impl<Input: Clone + Default + PartialEq + std::fmt::Debug, GameState, P: Predictor<Input>>
+    NetcodeClient<Input, GameState, P>
+{
+    pub fn with_predictor(held_input_count: usize, predictor: P) -> Self {
+        Self {
+            predictor,
+            prediction_stats: PredictionStats::default(),
+            compared_predictors: Vec::new(),
+            stats: StatsTracker::new(),
+            local_players: HashMap::new(),
+            net_players: HashMap::new(),
+            current_frame: 0,
+            held_input_count,
+            dummy_inputs: vec![Input::default(); held_input_count],
+            time_sync: HashMap::new(),
+            last_wait_frame: None,
+            disconnect_timer: DisconnectTimer::new(),
+            packet_buffer_size: 10,
+            input_delay: 1,
+            input_delay_bounds: (1, 8),
+            rtt: HashMap::new(),
+            negotiation: None,
+            local_round: 0,
+            network_delay: HashMap::new(),
+            saved_rollback_states: SavedStates::new(9 + 1),
+            allowed_rollback: 9,
+            rollback_to: None,
+            players: Vec::new(),
+            pending_checksums: HashMap::new(),
+            confirmed_checksums: BTreeMap::new(),
+            remote_checksums: BTreeMap::new(),
+            new_confirmed_checksum: false,
+            desync: None,
+            sync_test: None,
+            next_confirmed_frame: 0,
+            confirmed_inputs: Vec::new(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn input_delay(&self) -> usize {
+        self.input_delay
+    }
+    pub fn set_input_delay(&mut self, value: usize) {
+        self.input_delay = value;
+    }
+
+    #[allow(dead_code)]
+    pub fn allowed_rollback(&self) -> usize {
+        self.allowed_rollback
+    }
+    /// Any states saved for rolling back are dropped, so only change this before the match starts.
+    pub fn set_allowed_rollback(&mut self, value: usize) {
+        self.allowed_rollback = value;
+        self.saved_rollback_states = SavedStates::new(value + 1);
+    }
+
+    #[allow(dead_code)]
+    pub fn packet_buffer_size(&self) -> usize {
+        self.packet_buffer_size
+    }
+    pub fn set_packet_buffer_size(&mut self, value: usize) {
+        self.packet_buffer_size = value;
+    }
+
+    pub fn get_network_delay(&self, player: PlayerHandle) -> Result<usize, NetcodeError> {
+        self.check_player_type(player, PlayerType::Net)?;
+
+        Ok(self.network_delay[&player])
+    }
+    pub fn set_network_delay(
+        &mut self,
+        value: usize,
+        player: PlayerHandle,
+    ) -> Result<(), NetcodeError> {
+        self.check_player_type(player, PlayerType::Net)?;
+
+        self.network_delay.insert(player, value);
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn current_frame(&self) -> usize {
+        self.current_frame
+    }
+
+    /// The first confirmed frame whose checksum didn't match a remote client's, if any.
+    pub fn desync(&self) -> Option<Desync> {
+        self.desync
+    }
+
+    /// How often predictions for networked players have been right or wrong so far.
+    pub fn prediction_stats(&self) -> PredictionStats {
+        self.prediction_stats
+    }
+
+    /// Scores another strategy against every input the client had to predict, without
+    /// using its predictions, so strategies can be compared on the same match.
+    pub fn compare_predictor<C: Predictor<Input> + 'static>(&mut self, predictor: C) {
+        self.compared_predictors
+            .push((Box::new(predictor), PredictionStats::default()));
+    }
+
+    /// The stats of the strategy in use, followed by each compared strategy.
+    pub fn prediction_stats_by_strategy(&self) -> Vec<(&'static str, PredictionStats)> {
+        std::iter::once((self.predictor.name(), self.prediction_stats))
+            .chain(
+                self.compared_predictors
+                    .iter()
+                    .map(|(predictor, stats)| (predictor.name(), *stats)),
+            )
+            .collect()
+    }
+
+    fn score_compared_predictors(&mut self, player: PlayerHandle, frame: usize, actual: &Input) {
+        let net_player = &self.net_players[&player];
+        let previous = frame
+            .checked_sub(1)
+            .and_then(|frame| net_player.get_input(frame));
+        for (predictor, stats) in self.compared_predictors.iter_mut() {
+            if predictor.predict(previous) == *actual {
+                stats.correct += 1;
+            } else {
+                stats.wrong += 1;
+            }
+        }
+    }
+
+    /// Drains every input frame that has become confirmed since the last call, in frame order.
+    /// Each frame holds one input per player, ordered by player handle.
+    /// Confirmed inputs are never predicted, and will never be rolled back.
+    pub fn drain_confirmed_inputs(&mut self) -> std::vec::Drain<'_, (usize, Vec<Input>)> {
+        self.confirmed_inputs.drain(..)
+    }
+
+    fn check_player_type(
+        &self,
+        player: PlayerHandle,
+        player_type: PlayerType,
+    ) -> Result<(), NetcodeError> {
+        if self
+            .players
+            .iter()
+            .any(|info| info.id == player && info.player_type == player_type)
+        {
+            Ok(())
+        } else {
+            Err(NetcodeError::InvalidHandle(player))
+        }
+    }
+
+    fn delayed_current_frame(&self) -> usize {
+        self.current_frame + self.input_delay
+    }
+
+    // a remote client stops once it's predicted `allowed_rollback` frames of our input,
+    // and both our input and theirs can be delayed on top of that
+    fn latest_remote_frame(&self) -> usize {
+        let max_delay = self.input_delay.max(self.input_delay_bounds.1);
+        self.current_frame + self.allowed_rollback + max_delay * 2
+    }
+
+    fn check_remote_frame(&self, frame: usize) -> Result<(), NetcodeError> {
+        if frame > self.latest_remote_frame() {
+            Err(NetcodeError::FrameTooNew(frame))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn add_local_player(&mut self, handle: PlayerHandle) {
+        let info: PlayerInfo = PlayerInfo {
+            id: handle,
+            player_type: PlayerType::Local,
+        };
+        self.local_players.insert(handle, LocalHistory::new());
+        self.players.push(info);
+        self.players.sort_by(|lhs, rhs| lhs.id.cmp(&rhs.id));
+    }
+    pub fn add_network_player(&mut self, handle: PlayerHandle) {
+        let info: PlayerInfo = PlayerInfo {
+            id: handle,
+            player_type: PlayerType::Net,
+        };
+        self.net_players.insert(handle, NetworkedHistory::new());
+        self.players.push(info);
+        self.players.sort_by(|lhs, rhs| lhs.id.cmp(&rhs.id));
+        self.network_delay.insert(handle, 0);
+        self.time_sync.insert(handle, TimeSync::new());
+        self.disconnect_timer.add_player(handle);
+    }
+    /// Adds a player that the game expects input for, but no one controls, like an empty
+    /// slot in a team match. Every client has to add the same dummy players.
+    pub fn add_dummy_player(&mut self, handle: PlayerHandle) {
+        let info: PlayerInfo = PlayerInfo {
+            id: handle,
+            player_type: PlayerType::Dummy,
+        };
+        self.players.push(info);
+        self.players.sort_by(|lhs, rhs| lhs.id.cmp(&rhs.id));
+    }
+
+    /// Every player added to the client, of any kind.
+    pub fn player_count(&self) -> usize {
+        self.players.len()
+    }
+
+    /// The handle of every player in the order their inputs are given to the game.
+    pub fn player_handles(&self) -> impl Iterator<Item = PlayerHandle> + '_ {
+        self.players.iter().map(|info| info.id)
+    }
+
+    pub fn handle_local_input(
+        &mut self,
+        data: Input,
+        player: PlayerHandle,
+    ) -> Result<Option<Packet<Input>>, NetcodeError> {
+        self.check_player_type(player, PlayerType::Local)?;
+        let delayed_current_frame = self.delayed_current_frame();
+        let local_player = self.local_players.get_mut(&player).unwrap();
+        if !local_player.has_input(delayed_current_frame) {
+            let input_frame = local_player.add_input(data);
+            let buffer_size = self.packet_buffer_size;
+
+            let (range, data) = local_player.get_inputs(input_frame, buffer_size);
+
+            let packet = Packet::Inputs(
+                player,
+                self.current_frame,
+                range.first,
+                data.to_vec(),
+                self.worst_frame_advantage(),
+            );
+            self.stats.packets_sent += 1;
+            Ok(Some(packet))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn handle_net_input(
+        &mut self,
+        frame: usize,
+        input: Input,
+        player: PlayerHandle,
+    ) -> Result<(), NetcodeError> {
+        self.check_player_type(player, PlayerType::Net)?;
+        self.check_remote_frame(frame)?;
+
+        let net_player = self.net_players.get_mut(&player).unwrap();
+        if frame < net_player.front_frame() {
+            return Err(NetcodeError::FrameTooOld(frame));
+        }
+        let actual = if self.compared_predictors.is_empty() {
+            None
+        } else {
+            Some(input.clone())
+        };
+        let result = net_player.add_input(frame, input);
+        if let Some(actual) = actual {
+            if !matches!(result, PredictionResult::Unpredicted) {
+                self.score_compared_predictors(player, frame, &actual);
+            }
+        }
+        match result {
+            PredictionResult::Unpredicted => (),
+            PredictionResult::Correct => {
+                self.prediction_stats.correct += 1;
+                if self
+                    .net_players
+                    .iter()
+                    .all(|(_, net_player)| !net_player.is_predicted_input(frame))
+                {
+                    // the state is still needed if another player mispredicted this frame
+                    if self.rollback_to != Some(frame) {
+                        self.saved_rollback_states.remove(frame);
+                    }
+                }
+            }
+            PredictionResult::Wrong => {
+                self.prediction_stats.wrong += 1;
+
+                // prefer to rollback to an older frame if one's available
+                if self.saved_rollback_states.contains(frame) {
+                    if self.rollback_to.map_or(true, |old_frame| old_frame > frame) {
+                        // the newer frame's state stays saved, the rollback will save over it
+                        // or drop it if it's no longer needed
+                        self.rollback_to = Some(frame);
+                    }
+                } else if self.rollback_to.is_none() {
+                    // we've got predicted input for a frame, and no currently rollbacking frame
+                    // AND no frame to rollback to waiting, so the frame must have left the window
+                    return Err(NetcodeError::RollbackTooFar(frame));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // inputs older than the history were confirmed long ago, so they can be skipped
+    // unless that's the entire packet
+    fn handle_net_inputs(
+        &mut self,
+        player: PlayerHandle,
+        start_frame: usize,
+        inputs: Vec<Input>,
+    ) -> Result<(), NetcodeError> {
+        self.check_player_type(player, PlayerType::Net)?;
+        // even stale input means they're still there
+        self.disconnect_timer.reset(player);
+
+        if inputs.is_empty() {
+            return Ok(());
+        }
+        let last_frame = start_frame
+            .checked_add(inputs.len() - 1)
+            .ok_or(NetcodeError::FrameTooNew(start_frame))?;
+        self.check_remote_frame(last_frame)?;
+
+        let front_frame = self.net_players[&player].front_frame();
+        if last_frame < front_frame {
+            return Err(NetcodeError::FrameTooOld(start_frame));
+        }
+
+        for (frame, input) in (start_frame..)
+            .zip(inputs)
+            .skip_while(|(frame, _)| *frame < front_frame)
+        {
+            self.handle_net_input(frame, input, player)?;
+        }
+        Ok(())
+    }
+
+    // must return to sender
+    pub fn handle_packet(
+        &mut self,
+        packet: Packet<Input>,
+    ) -> Result<Option<Packet<Input>>, NetcodeError> {
+        self.stats.packets_received += 1;
+        let response = match packet {
+            Packet::Inputs(player_handle, sent_on_frame, start_frame, inputs, frame_advantage) => {
+                let network_delay = self.get_network_delay(player_handle)?;
+                self.check_remote_frame(sent_on_frame)?;
+                self.time_sync.get_mut(&player_handle).unwrap().add_sample(
+                    self.current_frame,
+                    sent_on_frame,
+                    network_delay,
+                );
+                self.stats
+                    .remote_frame_advantage
+                    .insert(player_handle, frame_advantage);
+
+                self.handle_net_inputs(player_handle, start_frame, inputs)?;
+                Ok(None)
+            }
+            Packet::Request(frame) => {
+                self.check_remote_frame(frame)?;
+                if self
+                    .local_players
+                    .values()
+                    .any(|local_player| frame < local_player.front_frame())
+                {
+                    return Err(NetcodeError::FrameTooOld(frame));
+                }
+                let requested_data: Vec<_> = self
+                    .local_players
+                    .iter()
+                    .map(|(handle, player)| (handle, player.get_inputs(frame, 1)))
+                    .map(|(handle, (range, player))| (*handle, range.first, player.to_vec()))
+                    .collect();
+                if requested_data.is_empty() {
+                    // we don't have any local players or anything to send back.
+                    Ok(None)
+                } else {
+                    Ok(Some(Packet::Provide(requested_data)))
+                }
+            }
+            Packet::Provide(inputs_list) => {
+                for (player_handle, frame, inputs) in inputs_list {
+                    self.handle_net_inputs(player_handle, frame, inputs)?;
+                }
+                Ok(None)
+            }
+            Packet::Checksums(checksums) => {
+                for (frame, _) in checksums.iter() {
+                    self.check_remote_frame(*frame)?;
+                }
+                let last_confirmed = self.confirmed_checksums.keys().next_back().copied();
+                for (frame, checksum) in checksums {
+                    match last_confirmed {
+                        Some(last_confirmed) if frame <= last_confirmed => {
+                            if let Some(local) = self.confirmed_checksums.get(&frame) {
+                                self.compare_checksum(frame, *local, checksum);
+                            }
+                        }
+                        _ => {
+                            self.remote_checksums.insert(frame, checksum);
+                        }
+                    }
+                }
+                Ok(None)
+            }
+            Packet::InputDelay(round, players, delay, frame, agreed) => {
+                self.handle_input_delay(round, players, delay, frame, agreed)
+            }
+        };
+        if let Ok(Some(_)) = response {
+            self.stats.packets_sent += 1;
+        }
+        response
+    }
+
+    /// Handles a packet from the peer that controls the `sender` players, which can't speak
+    /// for anyone else's input delay.
+    pub fn handle_packet_from(
+        &mut self,
+        sender: &[PlayerHandle],
+        packet: Packet<Input>,
+    ) -> Result<Option<Packet<Input>>, NetcodeError> {
+        if let Packet::InputDelay(_, players, ..) = &packet {
+            if let Some(player) = players.iter().find(|player| !sender.contains(player)) {
+                self.stats.packets_received += 1;
+                return Err(NetcodeError::InvalidHandle(*player));
+            }
+        }
+        self.handle_packet(packet)
+    }
+
+    /// The average number of frames the local client is ahead of a networked player,
+    /// negative if it's behind.
+    pub fn frame_advantage(&self, player: PlayerHandle) -> Result<f32, NetcodeError> {
+        self.check_player_type(player, PlayerType::Net)?;
+
+        Ok(self.time_sync[&player].frame_advantage())
+    }
+
+    // only the furthest behind player matters, because waiting for them lets everyone else catch up too
+    fn should_wait(&self) -> bool {
+        time_sync::wait_interval(self.worst_frame_advantage()).map_or(false, |interval| {
+            self.last_wait_frame
+                .map_or(true, |last| self.current_frame >= last + interval)
+        })
+    }
+
+    fn compare_checksum(&mut self, frame: usize, local: u64, remote: u64) {
+        if local != remote && self.desync.map_or(true, |desync| frame < desync.frame) {
+            self.desync = Some(Desync {
+                frame,
+                local,
+                remote,
+            });
+        }
+    }
+
+    fn record_checksum<Game: RollbackableGameState>(&mut self, frame: usize, game: &Game) {
+        self.pending_checksums.insert(frame, game.checksum());
+    }
+
+    // the first frame that was simulated with predicted input, or hasn't been simulated yet
+    fn first_unconfirmed_frame(&self) -> usize {
+        self.saved_rollback_states
+            .oldest_frame()
+            .into_iter()
+            .chain(self.rollback_to)
+            .min()
+            .unwrap_or(self.current_frame)
+            .min(self.current_frame)
+    }
+
+    fn input_set(&self, frame: usize) -> InputSet<'_, Input> {
+        InputSet {
+            inputs: self
+                .players
+                .iter()
+                .map(|info| {
+                    let (range, inputs) = match info.player_type {
+                        PlayerType::Local => {
+                            self.local_players[&info.id].get_inputs(frame, self.held_input_count)
+                        }
+                        PlayerType::Net => {
+                            self.net_players[&info.id].get_inputs(frame, self.held_input_count)
+                        }
+                        PlayerType::Dummy => return self.dummy_inputs(frame),
+                    };
+                    assert!(
+                        range.last == frame,
+                        "The last frame of input in the queue, should match the simulated frame."
+                    );
+                    inputs
+                })
+                .collect(),
+            handles: self.player_handles().collect(),
+        }
+    }
+
+    // as many inputs as a real player would have held by this frame
+    fn dummy_inputs(&self, frame: usize) -> &[Input] {
+        &self.dummy_inputs[..self.held_input_count.min(frame + 1)]
+    }
+
+    fn collect_confirmed_inputs(&mut self) {
+        let first_unconfirmed = self.first_unconfirmed_frame();
+        for frame in self.next_confirmed_frame..first_unconfirmed {
+            let inputs = self
+                .players
+                .iter()
+                .map(|info| {
+                    let (_, inputs) = match info.player_type {
+                        PlayerType::Local => self.local_players[&info.id].get_inputs(frame, 1),
+                        PlayerType::Net => self.net_players[&info.id].get_inputs(frame, 1),
+                        PlayerType::Dummy => return Input::default(),
+                    };
+                    inputs[0].clone()
+                })
+                .collect();
+            self.confirmed_inputs.push((frame, inputs));
+        }
+        self.next_confirmed_frame = self.next_confirmed_frame.max(first_unconfirmed);
+    }
+
+    // the state at the start of a frame is confirmed once every frame before it
+    // was simulated without any predicted input
+    fn confirm_checksums(&mut self) {
+        let first_unconfirmed = self.first_unconfirmed_frame();
+
+        let mut confirmed: Vec<_> = self
+            .pending_checksums
+            .iter()
+            .filter(|(frame, _)| **frame <= first_unconfirmed)
+            .map(|(frame, checksum)| (*frame, *checksum))
+            .collect();
+        confirmed.sort_unstable();
+
+        for (frame, checksum) in confirmed {
+            self.pending_checksums.remove(&frame);
+            if let Some(remote) = self.remote_checksums.remove(&frame) {
+                self.compare_checksum(frame, checksum, remote);
+            }
+            self.confirmed_checksums.insert(frame, checksum);
+            self.new_confirmed_checksum = true;
+        }
+
+        while self.confirmed_checksums.len() > self.packet_buffer_size {
+            let oldest = *self.confirmed_checksums.keys().next().unwrap();
+            self.confirmed_checksums.remove(&oldest);
+        }
+        if let Some(oldest) = self.confirmed_checksums.keys().next().copied() {
+            self.remote_checksums = self.remote_checksums.split_off(&oldest);
+        }
+    }
+
+    fn checksum_packet(&mut self) -> Option<Packet<Input>> {
+        if std::mem::replace(&mut self.new_confirmed_checksum, false) {
+            Some(Packet::Checksums(
+                self.confirmed_checksums
+                    .iter()
+                    .map(|(frame, checksum)| (*frame, *checksum))
+                    .collect(),
+            ))
+        } else {
+            None
+        }
+    }
+
+    pub fn update<Game: RollbackableGameState<SavedState = GameState, Input = Input>>(
+        &mut self,
+        game: &mut Game,
+    ) -> Result<Option<Packet<Input>>, NetcodeError> {
+        let output = self.update_frame(game);
+        if let Ok(Some(_)) = output {
+            self.stats.packets_sent += 1;
+        }
+        output
+    }
+
+    fn update_frame<Game: RollbackableGameState<SavedState = GameState, Input = Input>>(
+        &mut self,
+        game: &mut Game,
+    ) -> Result<Option<Packet<Input>>, NetcodeError> {
+        if game.player_count() != self.player_count() {
+            return Err(NetcodeError::PlayerCountMismatch(
+                game.player_count(),
+                self.player_count(),
+            ));
+        }
+
+        self.check_disconnects()?;
+        self.apply_agreed_input_delay();
+
+        // collect before anything is rolled back or cleaned, so all the inputs are still held
+        self.collect_confirmed_inputs();
+
+        if let Some(rollback_frame) = self.rollback_to.take() {
+            if let Some(empty_frame) = (rollback_frame..self.current_frame).find(|frame| {
+                self.net_players
+                    .values()
+                    .any(|net_player| net_player.is_empty_input(*frame))
+            }) {
+                return Err(NetcodeError::MissingInput(empty_frame));
+            }
+
+            // the state stays in its slot, so saving this frame again reuses it
+            let state = self
+                .saved_rollback_states
+                .get(rollback_frame)
+                .ok_or(NetcodeError::RollbackTooFar(rollback_frame))?;
+            game.load_state(state);
+            self.stats
+                .add_rollback(self.current_frame, self.current_frame - rollback_frame);
+
+            for rollback_current_frame in rollback_frame..self.current_frame {
+                if self
+                    .net_players
+                    .iter()
+                    .any(|(_, net_player)| net_player.is_predicted_input(rollback_current_frame))
+                {
+                    self.saved_rollback_states
+                        .save(rollback_current_frame, game)?;
+                } else {
+                    self.saved_rollback_states.remove(rollback_current_frame);
+                }
+                for (_, net_player) in self
+                    .net_players
+                    .iter_mut()
+                    .filter(|(_, net_player)| net_player.is_predicted_input(rollback_current_frame))
+                {
+                    net_player.repredict(rollback_current_frame, &self.predictor);
+                }
+
+                self.record_checksum(rollback_current_frame, game);
+                game.advance_frame(self.input_set(rollback_current_frame));
+            }
+        }
+
+        if self.current_frame % self.held_input_count == 0 {
+            let clear_target = self
+                .current_frame
+                .saturating_sub(self.held_input_count + self.allowed_rollback);
+            for (_, local_player) in self.local_players.iter_mut() {
+                local_player.clean(clear_target);
+            }
+            for (_, net_player) in self.net_players.iter_mut() {
+                net_player.clean(clear_target);
+            }
+        }
+
+        if self.sync_test.is_some() {
+            return self.update_sync_test(game);
+        }
+
+        let earliest_predicted_input_diff = self
+            .saved_rollback_states
+            .oldest_frame()
+            .and_then(|frame| self.current_frame.checked_sub(frame))
+            .unwrap_or(0);
+
+        if self.should_wait() {
+            self.last_wait_frame = Some(self.current_frame);
+            self.stats.frames_skipped += 1;
+            Ok(None)
+        } else if self
+            .local_players
+            .iter()
+            .all(|(_, local_player)| local_player.has_input(self.current_frame))
+            && self
+                .net_players
+                .iter()
+                .all(|(_, net_players)| net_players.has_input(self.current_frame))
+            && earliest_predicted_input_diff < self.allowed_rollback
+        {
+            self.record_checksum(self.current_frame, game);
+            game.advance_frame(self.input_set(self.current_frame));
+
+            self.current_frame += 1;
+
+            self.confirm_checksums();
+            Ok(self.resend_negotiation().or_else(|| self.checksum_packet()))
+        } else if earliest_predicted_input_diff < self.allowed_rollback
+            && self.current_frame > self.allowed_rollback
+        {
+            self.saved_rollback_states.save(self.current_frame, game)?;
+
+            let current_frame = self.current_frame;
+
+            for net_player in self
+                .net_players
+                .values_mut()
+                .filter(|net_player| net_player.is_empty_input(current_frame))
+            {
+                net_player.predict(self.current_frame, &self.predictor);
+            }
+            self.record_checksum(self.current_frame, game);
+            game.advance_frame(self.input_set(self.current_frame));
+            self.current_frame += 1;
+
+            self.confirm_checksums();
+            Ok(self.resend_negotiation().or_else(|| self.checksum_packet()))
+        } else {
+            self.stats.frames_skipped += 1;
+            Ok(Some(Packet::Request(
+                self.current_frame - earliest_predicted_input_diff,
+            )))
+        }
+    }
+}
+
+pub trait RollbackableGameState {
+    type Input;
+    type SavedState;
+
+    /// How many players the game expects input for, each frame's `InputSet` holds one
+    /// set of inputs for each of them.
+    fn player_count(&self) -> usize;
+    fn advance_frame(&mut self, input: InputSet<'_, Self::Input>);
+    fn save_state(&self) -> Self::SavedState;
+    /// Saves over a state that was saved earlier, which lets implementations reuse its
+    /// allocations instead of making new ones every frame.
+    fn save_state_into(&self, state: &mut Self::SavedState) {
+        *state = self.save_state();
+    }
+    /// Loads a state without taking it, so the saved state keeps its allocations for
+    /// `save_state_into` to reuse.
+    fn load_state(&mut self, load: &Self::SavedState);
+    /// A checksum of the simulation state, which must be identical across clients
+    /// for identical inputs.
+    fn checksum(&self) -> u64;
+}
@@ -0,0 +1,121 @@
+use fg_input::{button::ButtonState, InputState};
+
+/// Guesses a networked player's input for a frame that hasn't arrived yet.
+pub trait Predictor<Input> {
+    /// `previous` is the input for the frame before, which may itself be a prediction.
+    fn predict(&self, previous: Option<&Input>) -> Input;
+    /// Labels the strategy's stats.
+    fn name(&self) -> &'static str;
+}
+
+/// Assumes the player keeps doing whatever they did last.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RepeatLast;
+
+impl<Input: Clone + Default> Predictor<Input> for RepeatLast {
+    fn predict(&self, previous: Option<&Input>) -> Input {
+        previous.cloned().unwrap_or_default()
+    }
+    fn name(&self) -> &'static str {
+        "repeat last"
+    }
+}
+
+/// Assumes the player lets go of everything.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Neutral;
+
+impl<Input: Default> Predictor<Input> for Neutral {
+    fn predict(&self, _: Option<&Input>) -> Input {
+        Input::default()
+    }
+    fn name(&self) -> &'static str {
+        "neutral"
+    }
+}
+
+/// Assumes the player keeps holding the same direction and buttons, but lets go of a button
+/// that was just pressed, so a tap isn't pressed again.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RepeatLastReleaseButtons;
+
+impl Predictor<InputState> for RepeatLastReleaseButtons {
+    fn predict(&self, previous: Option<&InputState>) -> InputState {
+        let mut input = previous.copied().unwrap_or_default();
+        for button in input.buttons.iter_mut() {
+            *button = button.next_with(*button == ButtonState::Pressed);
+        }
+        input
+    }
+    fn name(&self) -> &'static str {
+        "repeat last, release buttons"
+    }
+}
+
+/// How often a client's predictions turned out to be wrong once the real input arrived.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PredictionStats {
+    pub correct: usize,
+    pub wrong: usize,
+}
+
+impl PredictionStats {
+    pub fn total(&self) -> usize {
+        self.correct + self.wrong
+    }
+
+    /// The fraction of predictions that caused a rollback, from 0 to 1.
+    pub fn misprediction_rate(&self) -> f32 {
+        if self.total() == 0 {
+            0.0
+        } else {
+            self.wrong as f32 / self.total() as f32
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Neutral, Predictor, RepeatLast, RepeatLastReleaseButtons};
+    use fg_input::axis::Axis;
+    use fg_input::button::ButtonState;
+    use fg_input::InputState;
+
+    fn held() -> InputState {
+        let mut input = InputState {
+            axis: Axis::DownRight,
+            ..InputState::default()
+        };
+        input.buttons[0] = ButtonState::JustPressed;
+        input.buttons[1] = ButtonState::Pressed;
+        input
+    }
+
+    #[test]
+    fn repeat_last() {
+        assert_eq!(RepeatLast.predict(Some(&held())), held());
+        assert_eq!(
+            RepeatLast.predict(None::<&InputState>),
+            InputState::default()
+        );
+    }
+
+    #[test]
+    fn neutral() {
+        assert_eq!(Neutral.predict(Some(&held())), InputState::default());
+    }
+
+    #[test]
+    fn release_buttons() {
+        let predicted = RepeatLastReleaseButtons.predict(Some(&held()));
+        assert_eq!(predicted.axis, Axis::DownRight);
+        assert_eq!(predicted.buttons[0], ButtonState::JustReleased);
+        // a button that's been held for a while is more likely to stay held
+        assert_eq!(predicted.buttons[1], ButtonState::Pressed);
+        assert_eq!(predicted.buttons[2], ButtonState::Released);
+
+        let predicted = RepeatLastReleaseButtons.predict(Some(&predicted));
+        assert_eq!(predicted.buttons[0], ButtonState::Released);
+        assert_eq!(predicted.buttons[1], ButtonState::Pressed);
+    }
+}
@@ -0,0 +1,145 @@
+use super::{NetcodeError, RollbackableGameState};
+
+struct Slot<T> {
+    frame: Option<usize>,
+    // kept around after the slot is invalidated, so the next save can reuse its allocations
+    state: Option<T>,
+}
+
+/// Saved states for the frames that were simulated with predicted input.
+/// Only `capacity` frames in a row can be held, and each frame always uses the same slot,
+/// so saving over an old frame can reuse whatever that state had allocated.
+pub(super) struct SavedStates<T> {
+    slots: Vec<Slot<T>>,
+}
+
+impl<T> SavedStates<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            slots: (0..capacity)
+                .map(|_| Slot {
+                    frame: None,
+                    state: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn slot(&self, frame: usize) -> &Slot<T> {
+        &self.slots[frame % self.slots.len()]
+    }
+
+    fn slot_mut(&mut self, frame: usize) -> &mut Slot<T> {
+        let len = self.slots.len();
+        &mut self.slots[frame % len]
+    }
+
+    /// Fails if the frame's slot still holds an older frame that's waiting on input,
+    /// since that frame could no longer be rolled back to.
+    pub fn save<Game: RollbackableGameState<SavedState = T>>(
+        &mut self,
+        frame: usize,
+        game: &Game,
+    ) -> Result<(), NetcodeError> {
+        let slot = self.slot_mut(frame);
+        if let Some(old_frame) = slot.frame.filter(|old_frame| *old_frame != frame) {
+            return Err(NetcodeError::RollbackTooFar(old_frame));
+        }
+
+        match &mut slot.state {
+            Some(state) => game.save_state_into(state),
+            None => slot.state = Some(game.save_state()),
+        }
+        slot.frame = Some(frame);
+        Ok(())
+    }
+
+    pub fn contains(&self, frame: usize) -> bool {
+        self.slot(frame).frame == Some(frame)
+    }
+
+    /// Marks the frame as no longer needed, but holds on to the state so it can be reused.
+    pub fn remove(&mut self, frame: usize) {
+        let slot = self.slot_mut(frame);
+        if slot.frame == Some(frame) {
+            slot.frame = None;
+        }
+    }
+
+    pub fn get(&self, frame: usize) -> Option<&T> {
+        let slot = self.slot(frame);
+        if slot.frame == Some(frame) {
+            slot.state.as_ref()
+        } else {
+            None
+        }
+    }
+
+    pub fn oldest_frame(&self) -> Option<usize> {
+        self.slots.iter().filter_map(|slot| slot.frame).min()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SavedStates;
+    use crate::{InputSet, RollbackableGameState};
+
+    struct Game {
+        state: Vec<u8>,
+    }
+
+    impl RollbackableGameState for Game {
+        type Input = ();
+        type SavedState = Vec<u8>;
+
+        fn player_count(&self) -> usize {
+            0
+        }
+        fn advance_frame(&mut self, _: InputSet<'_, Self::Input>) {}
+        fn save_state(&self) -> Self::SavedState {
+            self.state.clone()
+        }
+        fn save_state_into(&self, state: &mut Self::SavedState) {
+            state.clone_from(&self.state);
+        }
+        fn load_state(&mut self, load: &Self::SavedState) {
+            self.state.clone_from(load);
+        }
+        fn checksum(&self) -> u64 {
+            0
+        }
+    }
+
+    #[test]
+    fn reuses_slots() {
+        let mut states = SavedStates::new(2);
+        let mut game = Game { state: vec![0; 64] };
+
+        states.save(0, &game).unwrap();
+        states.save(1, &game).unwrap();
+        assert_eq!(states.oldest_frame(), Some(0));
+        // frame 0 is still waiting on input, so its slot can't be saved over
+        assert_eq!(
+            states.save(2, &game),
+            Err(crate::NetcodeError::RollbackTooFar(0))
+        );
+        states.remove(0);
+        assert_eq!(states.oldest_frame(), Some(1));
+
+        game.state[0] = 2;
+        states.save(2, &game).unwrap();
+        assert!(states.contains(2));
+        assert!(!states.contains(0));
+        assert_eq!(states.get(2).map(|state| state[0]), Some(2));
+
+        // loading a state leaves it in its slot, so saving the frame again reuses it
+        let allocation = states.get(2).unwrap().as_ptr();
+        game.load_state(states.get(2).unwrap());
+        states.save(2, &game).unwrap();
+        assert_eq!(states.get(2).unwrap().as_ptr(), allocation);
+
+        states.remove(2);
+        assert_eq!(states.get(2), None);
+    }
+}
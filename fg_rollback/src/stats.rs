@@ -0,0 +1,153 @@
+use super::{NetcodeClient, PlayerHandle, Predictor, RttEstimate, TimeSync};
+use std::collections::{HashMap, VecDeque};
+
+const FRAMES_PER_SECOND: usize = 60;
+
+/// Live diagnostics for a client, meant to be shown while playing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NetcodeStats {
+    /// How many rollbacks happened in the last second of frames.
+    pub rollbacks_per_second: usize,
+    /// The most frames resimulated by a single rollback.
+    pub max_rollback_depth: usize,
+    pub average_rollback_depth: f32,
+    /// Frames that weren't simulated, either to let remote players catch up or while waiting on their input.
+    pub frames_skipped: usize,
+    /// The fraction of predictions that caused a rollback, from 0 to 1.
+    pub prediction_error_rate: f32,
+    /// Packets returned by the client to be sent.
+    pub packets_sent: usize,
+    /// Packets given to the client to handle.
+    pub packets_received: usize,
+    pub players: HashMap<PlayerHandle, PlayerStats>,
+}
+
+/// Diagnostics for a single networked player.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PlayerStats {
+    /// `None` until the first ping has been added.
+    pub ping: Option<RttEstimate>,
+    /// How many frames the local client is ahead of this player, negative if behind.
+    pub local_frame_advantage: f32,
+    /// How many frames this player says they're ahead of the client furthest behind them.
+    pub remote_frame_advantage: f32,
+}
+
+pub(super) struct StatsTracker {
+    // the frame each recent rollback started on
+    recent_rollbacks: VecDeque<usize>,
+    rollback_count: usize,
+    total_rollback_depth: usize,
+    max_rollback_depth: usize,
+    pub frames_skipped: usize,
+    pub packets_sent: usize,
+    pub packets_received: usize,
+    pub remote_frame_advantage: HashMap<PlayerHandle, f32>,
+}
+
+impl StatsTracker {
+    pub fn new() -> Self {
+        Self {
+            recent_rollbacks: VecDeque::new(),
+            rollback_count: 0,
+            total_rollback_depth: 0,
+            max_rollback_depth: 0,
+            frames_skipped: 0,
+            packets_sent: 0,
+            packets_received: 0,
+            remote_frame_advantage: HashMap::new(),
+        }
+    }
+
+    pub fn add_rollback(&mut self, current_frame: usize, depth: usize) {
+        while self
+            .recent_rollbacks
+            .front()
+            .map_or(false, |frame| frame + FRAMES_PER_SECOND <= current_frame)
+        {
+            self.recent_rollbacks.pop_front();
+        }
+        self.recent_rollbacks.push_back(current_frame);
+
+        self.rollback_count += 1;
+        self.total_rollback_depth += depth;
+        self.max_rollback_depth = self.max_rollback_depth.max(depth);
+    }
+
+    fn rollbacks_per_second(&self, current_frame: usize) -> usize {
+        self.recent_rollbacks
+            .iter()
+            .filter(|frame| *frame + FRAMES_PER_SECOND > current_frame)
+            .count()
+    }
+
+    fn average_rollback_depth(&self) -> f32 {
+        if self.rollback_count == 0 {
+            0.0
+        } else {
+            self.total_rollback_depth as f32 / self.rollback_count as f32
+        }
+    }
+}
+
+impl<Input: Clone + Default + PartialEq + std::fmt::Debug, GameState, P: Predictor<Input>>
+    NetcodeClient<Input, GameState, P>
+{
+    pub fn stats(&self) -> NetcodeStats {
+        NetcodeStats {
+            rollbacks_per_second: self.stats.rollbacks_per_second(self.current_frame),
+            max_rollback_depth: self.stats.max_rollback_depth,
+            average_rollback_depth: self.stats.average_rollback_depth(),
+            frames_skipped: self.stats.frames_skipped,
+            prediction_error_rate: self.prediction_stats.misprediction_rate(),
+            packets_sent: self.stats.packets_sent,
+            packets_received: self.stats.packets_received,
+            players: self
+                .net_players
+                .keys()
+                .map(|player| {
+                    (
+                        *player,
+                        PlayerStats {
+                            ping: self.rtt(*player),
+                            local_frame_advantage: self.time_sync[player].frame_advantage(),
+                            remote_frame_advantage: self
+                                .stats
+                                .remote_frame_advantage
+                                .get(player)
+                                .copied()
+                                .unwrap_or(0.0),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    // the advantage over whoever is furthest behind, which is what decides if the client waits
+    pub(super) fn worst_frame_advantage(&self) -> f32 {
+        self.time_sync
+            .values()
+            .map(TimeSync::frame_advantage)
+            .fold(None, |worst: Option<f32>, advantage| {
+                Some(worst.map_or(advantage, |worst| worst.max(advantage)))
+            })
+            .unwrap_or(0.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::StatsTracker;
+
+    #[test]
+    fn rollbacks_per_second() {
+        let mut stats = StatsTracker::new();
+        stats.add_rollback(10, 2);
+        stats.add_rollback(30, 4);
+        assert_eq!(stats.rollbacks_per_second(30), 2);
+        assert_eq!(stats.rollbacks_per_second(80), 1);
+        assert_eq!(stats.max_rollback_depth, 4);
+        assert_eq!(stats.average_rollback_depth(), 3.0);
+    }
+}
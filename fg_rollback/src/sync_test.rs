@@ -0,0 +1,304 @@
+use super::{
+    InputSet, NetcodeClient, NetcodeError, Packet, PlayerType, Predictor, RollbackableGameState,
+};
+use std::collections::HashMap;
+
+/// Reported when re-simulating a frame produced a different checksum than the first run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncTestFailure {
+    pub frame: usize,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+pub(super) struct SyncTest<GameState> {
+    check_distance: usize,
+    saved_states: HashMap<usize, GameState>,
+    // checksums of the state at the start of a frame, keyed by that frame
+    checksums: HashMap<usize, u64>,
+    failure: Option<SyncTestFailure>,
+}
+
+impl<GameState> SyncTest<GameState> {
+    fn new(check_distance: usize) -> Self {
+        Self {
+            check_distance,
+            saved_states: HashMap::new(),
+            checksums: HashMap::new(),
+            failure: None,
+        }
+    }
+
+    fn check(&mut self, frame: usize, actual: u64) {
+        if let Some(expected) = self.checksums.get(&frame).copied() {
+            if expected != actual && self.failure.is_none() {
+                self.failure = Some(SyncTestFailure {
+                    frame,
+                    expected,
+                    actual,
+                });
+            }
+        }
+    }
+}
+
+impl<Input: Clone + Default + PartialEq + std::fmt::Debug, GameState, P: Predictor<Input>>
+    NetcodeClient<Input, GameState, P>
+{
+    /// Puts the client into a local only mode that rolls back `check_distance` frames every
+    /// update, and re-simulates them to make sure the game state is deterministic.
+    /// Only local players are supported while sync testing.
+    pub fn set_sync_test(&mut self, check_distance: Option<usize>) {
+        self.sync_test = check_distance.map(SyncTest::new);
+    }
+
+    pub fn is_sync_testing(&self) -> bool {
+        self.sync_test.is_some()
+    }
+
+    /// The first frame that simulated differently after being rolled back, if any.
+    pub fn sync_test_failure(&self) -> Option<SyncTestFailure> {
+        self.sync_test
+            .as_ref()
+            .and_then(|sync_test| sync_test.failure)
+    }
+
+    // sync testing only supports local players
+    fn sync_test_inputs(&self, frame: usize) -> Result<InputSet<'_, Input>, NetcodeError> {
+        if let Some(info) = self
+            .players
+            .iter()
+            .find(|info| info.player_type == PlayerType::Net)
+        {
+            return Err(NetcodeError::InvalidHandle(info.id));
+        }
+        Ok(self.input_set(frame))
+    }
+
+    pub(super) fn update_sync_test<
+        Game: RollbackableGameState<SavedState = GameState, Input = Input>,
+    >(
+        &mut self,
+        game: &mut Game,
+    ) -> Result<Option<Packet<Input>>, NetcodeError> {
+        let frame = self.current_frame;
+        if !self
+            .local_players
+            .values()
+            .all(|local_player| local_player.has_input(frame))
+        {
+            return Ok(None);
+        }
+
+        let saved_state = game.save_state();
+        let sync_test = self.sync_test.as_mut().unwrap();
+        sync_test.saved_states.insert(frame, saved_state);
+
+        game.advance_frame(self.sync_test_inputs(frame)?);
+        self.current_frame += 1;
+
+        let checksum = game.checksum();
+        let sync_test = self.sync_test.as_mut().unwrap();
+        sync_test.checksums.insert(self.current_frame, checksum);
+
+        let rollback_frame = self.current_frame.saturating_sub(sync_test.check_distance);
+        if let Some(state) = sync_test.saved_states.remove(&rollback_frame) {
+            game.load_state(&state);
+
+            for frame in rollback_frame..self.current_frame {
+                let checksum = game.checksum();
+                let saved_state = game.save_state();
+                let sync_test = self.sync_test.as_mut().unwrap();
+                sync_test.check(frame, checksum);
+                sync_test.saved_states.insert(frame, saved_state);
+
+                game.advance_frame(self.sync_test_inputs(frame)?);
+            }
+
+            let checksum = game.checksum();
+            self.sync_test
+                .as_mut()
+                .unwrap()
+                .check(self.current_frame, checksum);
+        }
+
+        let sync_test = self.sync_test.as_mut().unwrap();
+        sync_test
+            .saved_states
+            .retain(|frame, _| *frame >= rollback_frame);
+        sync_test
+            .checksums
+            .retain(|frame, _| *frame >= rollback_frame);
+
+        // nothing is ever predicted, so a frame is confirmed as soon as it's simulated, and
+        // turning the sync test off doesn't leave the last one undrained
+        self.collect_confirmed_inputs();
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{InputSet, NetcodeClient, NetcodeError, RollbackableGameState};
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    #[derive(Default)]
+    struct Counter {
+        total: i32,
+        // simulates state that doesn't get restored on load
+        unsaved_frames: i32,
+    }
+
+    impl RollbackableGameState for Counter {
+        type Input = i32;
+        type SavedState = i32;
+
+        fn player_count(&self) -> usize {
+            2
+        }
+        fn advance_frame(&mut self, input: InputSet<'_, Self::Input>) {
+            self.total += input
+                .inputs
+                .iter()
+                .map(|inputs| inputs.last().unwrap())
+                .sum::<i32>();
+            self.unsaved_frames += 1;
+        }
+        fn save_state(&self) -> Self::SavedState {
+            self.total
+        }
+        fn load_state(&mut self, load: &Self::SavedState) {
+            self.total = *load;
+        }
+        fn checksum(&self) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            self.total.hash(&mut hasher);
+            hasher.finish()
+        }
+    }
+
+    struct Desyncing(Counter);
+
+    impl RollbackableGameState for Desyncing {
+        type Input = i32;
+        type SavedState = i32;
+
+        fn player_count(&self) -> usize {
+            2
+        }
+        fn advance_frame(&mut self, input: InputSet<'_, Self::Input>) {
+            self.0.advance_frame(input)
+        }
+        fn save_state(&self) -> Self::SavedState {
+            self.0.save_state()
+        }
+        fn load_state(&mut self, load: &Self::SavedState) {
+            self.0.load_state(load)
+        }
+        fn checksum(&self) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            self.0.total.hash(&mut hasher);
+            self.0.unsaved_frames.hash(&mut hasher);
+            hasher.finish()
+        }
+    }
+
+    fn run<Game: RollbackableGameState<Input = i32, SavedState = i32>>(
+        game: &mut Game,
+    ) -> NetcodeClient<i32, i32> {
+        let mut client = NetcodeClient::new(1);
+        client.add_local_player(0);
+        client.add_local_player(1);
+        client.set_input_delay(0);
+        client.set_sync_test(Some(4));
+
+        for frame in 0..60 {
+            client.handle_local_input(frame, 0).unwrap();
+            client.handle_local_input(-frame / 2, 1).unwrap();
+            client.update(game).unwrap();
+        }
+
+        client
+    }
+
+    #[test]
+    fn deterministic() {
+        let mut game = Counter::default();
+        let client = run(&mut game);
+
+        assert_eq!(client.current_frame(), 60);
+        assert_eq!(client.sync_test_failure(), None);
+        assert_eq!(game.total, (0..60).map(|frame| frame - frame / 2).sum());
+    }
+
+    #[test]
+    fn confirms_every_frame() {
+        let mut game = Counter::default();
+        let mut client = run(&mut game);
+
+        let frames: Vec<_> = client
+            .drain_confirmed_inputs()
+            .map(|(frame, _)| frame)
+            .collect();
+        assert_eq!(frames, (0..60).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn dummy_players() {
+        let mut game = Counter::default();
+        let mut client = NetcodeClient::<i32, i32>::new(1);
+        client.add_local_player(0);
+        client.add_dummy_player(1);
+        client.set_input_delay(0);
+        client.set_sync_test(Some(4));
+
+        for frame in 0..60 {
+            client.handle_local_input(frame, 0).unwrap();
+            client.update(&mut game).unwrap();
+        }
+
+        assert_eq!(client.sync_test_failure(), None);
+        assert_eq!(game.total, (0..60).sum());
+    }
+
+    #[test]
+    fn player_count_mismatch() {
+        let mut game = Counter::default();
+        let mut client = NetcodeClient::<i32, i32>::new(1);
+        client.add_local_player(0);
+
+        assert_eq!(
+            client.update(&mut game).err(),
+            Some(NetcodeError::PlayerCountMismatch(2, 1))
+        );
+    }
+
+    #[test]
+    fn network_players() {
+        let mut game = Counter::default();
+        let mut client = NetcodeClient::<i32, i32>::new(1);
+        client.add_local_player(0);
+        client.add_network_player(1);
+        client.set_input_delay(0);
+        client.set_sync_test(Some(4));
+
+        client.handle_local_input(0, 0).unwrap();
+        assert_eq!(
+            client.update(&mut game).err(),
+            Some(NetcodeError::InvalidHandle(1))
+        );
+    }
+
+    #[test]
+    fn non_deterministic() {
+        let mut game = Desyncing(Counter::default());
+        let client = run(&mut game);
+
+        assert_eq!(
+            client.sync_test_failure().map(|failure| failure.frame),
+            Some(1)
+        );
+    }
+}
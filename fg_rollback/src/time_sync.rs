@@ -0,0 +1,106 @@
+use std::collections::VecDeque;
+
+// how many samples of frame advantage are averaged for each remote player
+const WINDOW_SIZE: usize = 30;
+// the average advantage over a remote player before the local client starts slowing down
+const MAX_ADVANTAGE: f32 = 1.0;
+// the fewest frames allowed between two waited frames, so slowing down is spread out
+const MIN_WAIT_INTERVAL: usize = 6;
+// the most frames between two waited frames, used when only barely ahead
+const MAX_WAIT_INTERVAL: usize = 30;
+
+/// Tracks how far ahead of a single remote player the local client is running.
+pub(super) struct TimeSync {
+    samples: VecDeque<isize>,
+    last_sent_on_frame: Option<usize>,
+}
+
+impl TimeSync {
+    pub(super) fn new() -> Self {
+        Self {
+            samples: VecDeque::with_capacity(WINDOW_SIZE),
+            last_sent_on_frame: None,
+        }
+    }
+
+    /// Records a sample from a packet the remote player sent on `sent_on_frame`.
+    /// Packets that arrive out of order don't say anything new, so they're ignored.
+    pub(super) fn add_sample(&mut self, current_frame: usize, sent_on_frame: usize, delay: usize) {
+        if self
+            .last_sent_on_frame
+            .map_or(false, |last| sent_on_frame <= last)
+        {
+            return;
+        }
+        self.last_sent_on_frame = Some(sent_on_frame);
+
+        if self.samples.len() == WINDOW_SIZE {
+            self.samples.pop_front();
+        }
+        self.samples
+            .push_back(current_frame as isize - (sent_on_frame + delay) as isize);
+    }
+
+    /// The average number of frames the local client is ahead of this player, negative if behind.
+    pub(super) fn frame_advantage(&self) -> f32 {
+        if self.samples.is_empty() {
+            0.0
+        } else {
+            self.samples.iter().sum::<isize>() as f32 / self.samples.len() as f32
+        }
+    }
+}
+
+/// How many frames to wait between waited frames for a given advantage, or `None` if the
+/// client shouldn't slow down at all.
+/// The further ahead the client is, the more often it waits, but it never waits on
+/// consecutive frames so the slow down isn't noticeable.
+pub(super) fn wait_interval(frame_advantage: f32) -> Option<usize> {
+    if frame_advantage > MAX_ADVANTAGE {
+        let interval = (MAX_WAIT_INTERVAL as f32 / frame_advantage) as usize;
+        Some(interval.clamp(MIN_WAIT_INTERVAL, MAX_WAIT_INTERVAL))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{wait_interval, TimeSync, MIN_WAIT_INTERVAL, WINDOW_SIZE};
+
+    #[test]
+    fn averages_over_window() {
+        let mut time_sync = TimeSync::new();
+        for frame in 0..WINDOW_SIZE {
+            // 4 frames ahead
+            time_sync.add_sample(frame + 4, frame, 0);
+        }
+        assert_eq!(time_sync.frame_advantage(), 4.0);
+
+        for frame in WINDOW_SIZE..WINDOW_SIZE * 2 {
+            // even
+            time_sync.add_sample(frame + 2, frame, 2);
+        }
+        assert_eq!(time_sync.frame_advantage(), 0.0);
+    }
+
+    #[test]
+    fn ignores_stale_packets() {
+        let mut time_sync = TimeSync::new();
+        time_sync.add_sample(10, 8, 0);
+        time_sync.add_sample(20, 4, 0);
+        assert_eq!(time_sync.frame_advantage(), 2.0);
+    }
+
+    #[test]
+    fn waits_more_often_when_further_ahead() {
+        assert_eq!(wait_interval(0.0), None);
+        assert_eq!(wait_interval(-5.0), None);
+        assert_eq!(wait_interval(1.0), None);
+
+        let slightly_ahead = wait_interval(1.5).unwrap();
+        let far_ahead = wait_interval(20.0).unwrap();
+        assert!(far_ahead < slightly_ahead);
+        assert_eq!(far_ahead, MIN_WAIT_INTERVAL);
+    }
+}
@@ -129,6 +129,10 @@ impl<T> Menu<T> {
         self.state.confirmed
     }
 
+    pub fn deselect(&mut self) {
+        self.state.confirmed = false;
+    }
+
     pub fn modify_items<F>(&mut self, f: F)
     where
         F: FnOnce(&mut Vec<T>),
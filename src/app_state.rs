@@ -9,11 +9,9 @@ use ggez::event::{EventHandler, KeyCode, KeyMods};
 use ggez::input::mouse::MouseButton;
 use ggez::{Context, GameResult};
 use imgui::NavInput;
-use laminar::{Config, Socket};
 use sdl_controller_backend::{ControllerId, SdlController};
 use std::collections::HashMap;
-use std::net::{SocketAddr, ToSocketAddrs};
-use std::time::Duration;
+use std::net::{Ipv4Addr, SocketAddr};
 
 pub enum Transition {
     Push(Box<dyn AppState>),
@@ -27,10 +25,17 @@ pub struct AppContext {
     pub imgui: ImGuiWrapper,
     pub control_schemes: HashMap<ControllerId, ControlMapping>,
     pub audio: rodio::Device,
-    pub socket: Option<Socket>,
     pub networking: Networking,
 }
 
+// listens on every interface, so a LAN without a route to the internet can still reach it,
+// and the first of these that's free is used
+fn local_addrs() -> Vec<SocketAddr> {
+    (10800..10810)
+        .map(|port| (Ipv4Addr::UNSPECIFIED, port).into())
+        .collect()
+}
+
 pub trait AppState {
     fn update(&mut self, ctx: &mut Context, app_ctx: &mut AppContext) -> GameResult<Transition>;
     fn on_enter(&mut self, ctx: &mut Context, app_ctx: &mut AppContext) -> GameResult<()>;
@@ -47,48 +52,21 @@ impl AppStateRunner {
     pub fn new(ctx: &mut Context, mut start: Box<dyn AppState>) -> GameResult<Self> {
         let audio = rodio::default_output_device().unwrap();
 
-        let adapter = ipconfig::get_adapters().ok().and_then(|adapters| {
-            adapters
-                .into_iter()
-                .find(|x| x.friendly_name() == "Ethernet")
-        });
-
         let mut app_ctx = AppContext {
             controllers: SdlController::new().unwrap(),
             imgui: ImGuiWrapper::new(ctx),
             control_schemes: HashMap::new(),
             audio,
-            networking: Networking::new(),
-            socket: Socket::bind_with_config(
-                adapter
-                    .and_then(|adapter| {
-                        adapter
-                            .ip_addresses()
-                            .iter()
-                            .find(|item| item.is_ipv4())
-                            .cloned()
-                    })
-                    .map(|ip| {
-                        (0..10)
-                            .into_iter()
-                            .map(|i| format!("{}:1080{}", ip, i))
-                            .collect()
-                    })
-                    .unwrap_or_else(|| vec!["127.0.0.1:10800".to_owned()])
-                    .into_iter()
-                    .filter_map(|item| item.to_socket_addrs().ok())
-                    .flatten()
-                    .collect::<Vec<SocketAddr>>()
-                    .as_slice(),
-                Config {
-                    blocking_mode: false,
-                    rtt_max_value: 2000,
-                    idle_connection_timeout: Duration::from_secs(5),
-                    heartbeat_interval: Some(Duration::from_millis(200)),
-                    ..Config::default()
-                },
-            )
-            .ok(),
+            networking: match std_udp_backend::networking(
+                &ggez::filesystem::user_config_dir(ctx).join("network"),
+                &local_addrs(),
+            ) {
+                Ok(networking) => networking,
+                Err(err) => {
+                    println!("Couldn't start networking: {}", err);
+                    Networking::new()
+                }
+            },
         };
         start.on_enter(ctx, &mut app_ctx)?;
         Ok(AppStateRunner {
@@ -3,7 +3,6 @@ mod match_settings;
 mod noop_writer;
 pub mod sounds;
 
-use crate::netcode::{InputSet, RollbackableGameState};
 use crate::roster::generic_character::GenericCharacterBehaviour;
 use crate::roster::generic_character::OpaqueStateData;
 use crate::roster::hit_info::Source;
@@ -18,6 +17,7 @@ use fg_datastructures::math::collision::IntoGraphical;
 use fg_datastructures::math::graphics::{Matrix4, Vec3};
 use fg_datastructures::player_data::PlayerData;
 use fg_input::{InputState, Facing};
+use fg_rollback::{InputSet, RollbackableGameState};
 use flash::FlashOverlay;
 pub use flash::FlashType;
 use ggez::graphics::Image;
@@ -28,6 +28,8 @@ pub use match_settings::{
 };
 use noop_writer::NoopWriter;
 use sounds::{GlobalSound, SoundList};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::io::Write;
 use std::rc::Rc;
 
@@ -98,6 +100,7 @@ pub struct Match<Writer> {
     game_state: GameState,
 
     game_over: Option<PlayerData<bool>>,
+    disconnected: Option<usize>,
 
     runtime_data: Rc<RuntimeData>,
 
@@ -114,7 +117,7 @@ struct GameText {
     pub timer: ggez::graphics::Text,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum UpdateMode {
     Normal,
     RoundStart { duration: i32 },
@@ -181,6 +184,7 @@ impl<Writer: Write> Match<Writer> {
             sound_renderer: sounds::SoundRenderer::new(),
 
             game_over: None,
+            disconnected: None,
             writer,
             scale_factor: 3.6,
             text: GameText { timer },
@@ -203,6 +207,10 @@ impl<Writer: Write> Match<Writer> {
         self.game_state.current_frame
     }
 
+    pub fn round(&self) -> usize {
+        self.game_state.round
+    }
+
     fn update_normal(&mut self, input: PlayerData<&[InputState]>) {
         self.game_state.timer = self.game_state.timer.saturating_sub(1);
 
@@ -473,14 +481,45 @@ impl<Writer: Write> Match<Writer> {
         p2.handle_refacing(p1.position().x);
     }
 
+    /// Ends the match early because `player` stopped responding, and records it in the replay.
+    pub fn disconnect(&mut self, player: usize) {
+        if self.disconnected.is_none() {
+            let _ = bincode::serialize_into(&mut self.writer, &crate::replay::DISCONNECT_FRAME);
+            let _ = bincode::serialize_into(&mut self.writer, &player);
+            let _ = self.writer.flush();
+            self.disconnected = Some(player);
+        }
+    }
+
+    /// Advances a frame, and records its input in the replay.
     pub fn update(&mut self, input: PlayerData<&[InputState]>) {
-        if input.iter().any(|input| input.is_empty()) {
+        if input.iter().any(|input| input.is_empty()) || self.disconnected.is_some() {
+            return;
+        }
+
+        self.record_frame(
+            self.game_state.current_frame as usize,
+            input.map(|input| *input.last().unwrap()),
+        );
+        self.advance(input);
+    }
+
+    /// Writes a frame of input to the replay, for matches that advance without `update`.
+    /// Frames have to be recorded in order, once each, for the replay to play back.
+    pub fn record_frame(&mut self, frame: usize, input: PlayerData<InputState>) {
+        if self.disconnected.is_some() {
             return;
         }
 
-        let _ = bincode::serialize_into(&mut self.writer, &self.game_state.current_frame);
+        let _ = bincode::serialize_into(&mut self.writer, &(frame as u32));
         for input in input.iter() {
-            let _ = bincode::serialize_into(&mut self.writer, &input.last().unwrap());
+            let _ = bincode::serialize_into(&mut self.writer, input);
+        }
+    }
+
+    fn advance(&mut self, input: PlayerData<&[InputState]>) {
+        if input.iter().any(|input| input.is_empty()) || self.disconnected.is_some() {
+            return;
         }
 
         self.game_state.mode = match self.game_state.mode {
@@ -992,8 +1031,14 @@ impl<Writer: Write> RollbackableGameState for Match<Writer> {
     type Input = InputState;
     type SavedState = (PlayerData<OpaqueStateData>, GameState);
 
+    fn player_count(&self) -> usize {
+        self.players.len()
+    }
+
+    // rolled back frames get simulated again, so whatever drives the rollback
+    // records frames once they're confirmed instead
     fn advance_frame(&mut self, input: InputSet<'_, Self::Input>) {
-        self.update([input.inputs[0], input.inputs[1]].into())
+        self.advance(input.inputs.into_iter().collect())
     }
 
     fn save_state(&self) -> Self::SavedState {
@@ -1003,11 +1048,45 @@ impl<Writer: Write> RollbackableGameState for Match<Writer> {
         )
     }
 
-    fn load_state(&mut self, (players, game_state): Self::SavedState) {
-        for (player, new_state) in self.players.iter_mut().zip(players.iter().cloned()) {
+    fn save_state_into(&self, (players, game_state): &mut Self::SavedState) {
+        for (player, state) in self.players.iter().zip(players.iter_mut()) {
+            player.save_into(state).unwrap();
+        }
+        game_state.clone_from(&self.game_state);
+    }
+
+    fn load_state(&mut self, (players, game_state): &Self::SavedState) {
+        for (player, new_state) in self.players.iter_mut().zip(players.iter()) {
             player.load(new_state).unwrap();
             // TODO log load error
         }
-        self.game_state = game_state;
+        self.game_state.clone_from(game_state);
+    }
+
+    fn checksum(&self) -> u64 {
+        // DefaultHasher::new is unkeyed, so this is stable between clients on the same build
+        let mut hasher = DefaultHasher::new();
+        for player in self.players.iter() {
+            player.hash_state(&mut hasher);
+        }
+
+        let GameState {
+            current_frame,
+            mode,
+            wins,
+            round,
+            timer,
+            p1_install,
+            // only decide what's drawn and played, and are never read back by the simulation
+            flash: _,
+            sound_state: _,
+        } = &self.game_state;
+        current_frame.hash(&mut hasher);
+        mode.hash(&mut hasher);
+        wins.hash(&mut hasher);
+        round.hash(&mut hasher);
+        timer.hash(&mut hasher);
+        p1_install.hash(&mut hasher);
+        hasher.finish()
     }
 }
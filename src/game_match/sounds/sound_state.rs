@@ -2,11 +2,23 @@ use super::ChannelName;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct PlayerSoundState<LocalPath> {
     pub channels: HashMap<ChannelName, SoundState<LocalPath>>,
 }
 
+impl<LocalPath: Clone> Clone for PlayerSoundState<LocalPath> {
+    fn clone(&self) -> Self {
+        Self {
+            channels: self.channels.clone(),
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.channels.clone_from(&source.channels);
+    }
+}
+
 impl<LocalPath> PlayerSoundState<LocalPath> {
     pub fn new() -> Self {
         Self {
@@ -22,7 +22,6 @@ mod hitbox;
 mod imgui_wrapper;
 mod input;
 mod menus;
-mod netcode;
 mod player_list;
 mod replay;
 mod roster;
@@ -1,14 +1,13 @@
 pub mod character_select;
 pub mod controller_select;
-pub mod netcode_select;
 
 pub mod local_versus;
 pub mod netplay_versus;
 pub mod retry_screen;
+pub mod spectate_versus;
 pub mod training_mode;
 pub mod watch_replay;
 
 // TODO RREMOVE THESE RE-EXPORTS
 pub use character_select::CharacterSelect;
 pub use controller_select::ControllerSelect;
-pub use netcode_select::NetworkConnect;
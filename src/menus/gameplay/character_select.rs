@@ -9,12 +9,10 @@ use fg_controller::backend::ControllerBackend;
 use fg_datastructures::{player_data::PlayerData, roster::RosterCharacter};
 use fg_ui::{
     delay::Delay,
-    menu::{Menu, MenuAction, MenuState},
+    menu::{Menu, MenuAction},
 };
 use ggez::{graphics, Context, GameResult};
 use imgui::im_str;
-use laminar::{Packet, SocketEvent};
-use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
 
 enum NextState {
@@ -22,12 +20,6 @@ enum NextState {
     Back,
 }
 
-#[derive(PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Debug)]
-pub enum CharacterPacket {
-    Update(MenuState),
-    Quit,
-}
-
 impl<Target> FromControllerList for CharacterSelect<Target> {
     fn from_controllers(data: PlayerList) -> GameResult<Box<Self>> {
         Ok(Box::new(Self::new(data, None)))
@@ -70,7 +62,6 @@ where
         ctx: &mut Context,
         AppContext {
             ref mut controllers,
-            ref mut socket,
             ..
         }: &mut AppContext,
     ) -> GameResult<crate::app_state::Transition> {
@@ -87,32 +78,11 @@ where
                     let action = state.update(&controllers.current_state(&player));
                     if action == MenuAction::Back {
                         self.next = Some(NextState::Back);
-
-                        if let Some(ref mut socket) = socket {
-                            for addr in self.player_list.network_addrs() {
-                                let _ = socket.send(Packet::reliable_ordered(
-                                    addr,
-                                    bincode::serialize(&CharacterPacket::Quit).unwrap(),
-                                    None,
-                                ));
-                            }
-                        }
                     }
 
                     if !matches!(action, MenuAction::Confirm | MenuAction::None) {
                         self.delay.unready();
                     }
-
-                    if let Some(ref mut socket) = socket {
-                        for addr in self.player_list.network_addrs() {
-                            let _ = socket.send(Packet::reliable_ordered(
-                                addr,
-                                bincode::serialize(&CharacterPacket::Update(*state.state()))
-                                    .unwrap(),
-                                None,
-                            ));
-                        }
-                    }
                 }
 
                 if let (PlayerType::LocalGamepad(player), PlayerType::Dummy) = (
@@ -127,41 +97,6 @@ where
             }
         }
 
-        if let Some(ref mut socket) = socket {
-            socket.manual_poll(std::time::Instant::now());
-            while let Some(packet) = socket.recv() {
-                match packet {
-                    SocketEvent::Packet(packet) => {
-                        let data: CharacterPacket = match bincode::deserialize(packet.payload()) {
-                            Ok(payload) => payload,
-                            Err(_) => break,
-                        };
-                        let player = if let Some(idx) = self
-                            .player_list
-                            .current_players
-                            .iter()
-                            .position(|item| item == &packet.addr().into())
-                        {
-                            idx
-                        } else {
-                            continue;
-                        };
-
-                        match data {
-                            CharacterPacket::Update(state) => {
-                                self.chosen_characters[player].set_state(state);
-                            }
-                            CharacterPacket::Quit => {
-                                self.next = Some(NextState::Back);
-                            }
-                        }
-                    }
-                    SocketEvent::Timeout(_) => {}
-                    SocketEvent::Connect(_) => {}
-                }
-            }
-        }
-
         if self.chosen_characters.iter().all(|state| state.confirmed()) {
             self.next = Some(NextState::Next);
         }
@@ -1,24 +1,31 @@
 use crate::app_state::{AppContext, AppState, Transition};
-use crate::game_match::{FromMatchSettings, Match, MatchSettings};
-use crate::netcode::{NetcodeClient as Client, Packet as NetcodeClientPacket, PlayerHandle};
+use crate::game_match::{Match, MatchSettings};
+use crate::menus::networked::match_result::{MatchOutcome, MatchResult};
 use fg_controller::backend::ControllerBackend;
+use fg_datastructures::player_data::PlayerData;
 use fg_input::InputState;
+use fg_netcode::game::{Match as LobbyMatch, MatchMessage, WhoIs};
+use fg_netcode::player_list::Player;
+use fg_rollback::{
+    NetcodeClient as Client, NetcodeError, Packet as NetcodeClientPacket, PlayerHandle,
+};
+use ggez::input::keyboard::{self, KeyCode};
 use ggez::{graphics, Context, GameResult};
-use laminar::{Packet as SocketPacket, SocketEvent};
+use imgui::im_str;
+use sdl_controller_backend::ControllerId;
 use serde::{Deserialize, Serialize};
 use std::time::Instant;
 
-use super::retry_screen::RetryScreen;
 use crate::player_list::{PlayerList, PlayerType};
 use std::collections::HashMap;
 
 type NetplayMatch = Match<crate::replay::ReplayWriterFile>;
 
 type NetcodeClient =
-    Client<InputState, <NetplayMatch as crate::netcode::RollbackableGameState>::SavedState>;
+    Client<InputState, <NetplayMatch as fg_rollback::RollbackableGameState>::SavedState>;
 
 enum NextState {
-    Back,
+    Ended(MatchOutcome),
 }
 
 pub struct NetplayVersus {
@@ -30,51 +37,81 @@ pub struct NetplayVersus {
 
     start_time: Instant,
 
+    show_stats: bool,
+    stats_key_held: bool,
+
+    confirmed_inputs: Vec<PlayerData<InputState>>,
+
+    lobby: LobbyMatch,
+
     game_state: NetplayMatch,
     client: NetcodeClient,
 }
-impl FromMatchSettings for NetplayVersus {
-    fn from_settings(
-        ctx: &mut Context,
-        player_list: PlayerList,
-        settings: MatchSettings,
-    ) -> GameResult<Box<Self>> {
-        let mut client = NetcodeClient::new(60);
-
-        for (idx, player) in player_list.current_players.iter().enumerate() {
-            if player.is_local() {
-                client.add_local_player(idx);
-            } else if player.is_networked() {
-                client.add_network_player(idx);
-            }
-        }
+fn new_client(player_list: &PlayerList) -> NetcodeClient {
+    let mut client = NetcodeClient::new(60);
 
-        Ok(Box::new(NetplayVersus::new(
-            ctx,
-            player_list,
-            settings,
-            client,
-        )?))
+    for (idx, player) in player_list.current_players.iter().enumerate() {
+        match player {
+            PlayerType::LocalGamepad(_) => client.add_local_player(idx),
+            PlayerType::Networked(_) => client.add_network_player(idx),
+            PlayerType::Dummy => client.add_dummy_player(idx),
+        }
     }
+
+    client
+}
+
+enum Event {
+    Packet(WhoIs, NetworkData),
+    Disconnected(WhoIs),
 }
 
+// input delay is picked from the ping at the start of each round, within these bounds
+const MIN_INPUT_DELAY: usize = 1;
+const MAX_INPUT_DELAY: usize = 4;
+
+// frames without input from a player before showing a countdown, and before giving up on them
+const DISCONNECT_NOTIFY_START: usize = 60;
+const DISCONNECT_TIMEOUT: usize = 60 * 5;
+
+// toggles the network stats overlay
+const STATS_KEY: KeyCode = KeyCode::F3;
+
+// the most input frames that will be sent to a spectator in one packet
+const SPECTATE_PACKET_FRAMES: usize = 16;
+
 #[derive(Serialize, Deserialize)]
-enum NetworkData {
+pub(super) enum NetworkData {
     Client(NetcodeClientPacket<InputState>),
     Ping(u128),
     Pong(u128),
+    // confirmed inputs starting at the specified frame
+    Spectate(usize, Vec<PlayerData<InputState>>),
+    SpectateRequest(usize),
 }
 
 impl NetplayVersus {
-    pub fn new(
+    /// Plays the seated user's side of a lobby game, against `opponent`.
+    pub fn from_lobby(
         ctx: &mut Context,
-        player_list: PlayerList,
+        lobby: LobbyMatch,
         settings: MatchSettings,
-        mut client: NetcodeClient,
+        main_player: ControllerId,
+        opponent: Player,
     ) -> GameResult<Self> {
-        client.set_input_delay(3);
+        let current_players: [PlayerType; 2] = if lobby.user() == WhoIs::P1 {
+            [main_player.into(), opponent.into()]
+        } else {
+            [opponent.into(), main_player.into()]
+        };
+        let player_list = PlayerList::new(current_players.into());
+        let mut client = new_client(&player_list);
+
+        client.set_input_delay(MAX_INPUT_DELAY);
+        client.set_input_delay_bounds(MIN_INPUT_DELAY, MAX_INPUT_DELAY);
         client.set_allowed_rollback(10);
         client.set_packet_buffer_size(13);
+        client.set_disconnect_timeout(DISCONNECT_NOTIFY_START, DISCONNECT_TIMEOUT);
 
         Ok(Self {
             next: None,
@@ -97,11 +134,7 @@ impl NetplayVersus {
                 .iter()
                 .enumerate()
                 .filter_map(|(idx, player)| {
-                    if player.is_local() {
-                        Some((idx, InputState::default()))
-                    } else {
-                        None
-                    }
+                    player.gamepad_id().map(|_| (idx, InputState::default()))
                 })
                 .collect(),
 
@@ -113,8 +146,82 @@ impl NetplayVersus {
             client,
             player_list,
             start_time: Instant::now(),
+            show_stats: false,
+            stats_key_held: false,
+            confirmed_inputs: Vec::new(),
+            lobby,
         })
     }
+
+    fn is_host(&self) -> bool {
+        self.player_list.current_players.p1().is_local()
+    }
+
+    fn disconnect(&mut self, player: PlayerHandle) {
+        self.game_state.disconnect(player);
+        self.next = Some(NextState::Ended(MatchOutcome::Disconnected(player)));
+    }
+
+    fn poll_events(&mut self) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        while let Some(message) = self.lobby.poll() {
+            match message {
+                MatchMessage::Packet(who, data) | MatchMessage::Control(who, data) => {
+                    if let Ok(data) = bincode::deserialize(&data) {
+                        events.push(Event::Packet(who, data));
+                    }
+                }
+                MatchMessage::Disconnected(who) => events.push(Event::Disconnected(who)),
+            }
+        }
+
+        events
+    }
+
+    fn send(&mut self, to: WhoIs, data: &NetworkData) {
+        self.lobby.send(to, bincode::serialize(data).unwrap());
+    }
+
+    fn broadcast(&mut self, data: &NetworkData) {
+        for peer in self.players() {
+            self.send(peer, data);
+        }
+    }
+
+    // the other networked players
+    fn players(&self) -> Vec<WhoIs> {
+        self.player_list
+            .current_players
+            .iter()
+            .enumerate()
+            .filter(|(_, player)| player.is_networked())
+            .map(|(idx, _)| if idx == 0 { WhoIs::P1 } else { WhoIs::P2 })
+            .collect()
+    }
+
+    fn spectators(&self) -> Vec<WhoIs> {
+        self.lobby.spectators().collect()
+    }
+
+    fn player_of(&self, who: WhoIs) -> Option<usize> {
+        match who {
+            WhoIs::P1 => Some(0),
+            WhoIs::P2 => Some(1),
+            WhoIs::Spectator(_) => None,
+        }
+    }
+
+    fn spectate_packet(&self, start_frame: usize) -> NetworkData {
+        let frames = spectate_frames(self.confirmed_inputs.len(), start_frame);
+        NetworkData::Spectate(frames.start, self.confirmed_inputs[frames].to_vec())
+    }
+}
+
+// the start frame comes from a spectator, so it can be anything
+fn spectate_frames(confirmed_frames: usize, start_frame: usize) -> std::ops::Range<usize> {
+    let end_frame = confirmed_frames.min(start_frame.saturating_add(SPECTATE_PACKET_FRAMES));
+    start_frame.min(end_frame)..end_frame
 }
 
 impl AppState for NetplayVersus {
@@ -125,72 +232,62 @@ impl AppState for NetplayVersus {
             ref mut controllers,
             ref control_schemes,
             ref audio,
-            ref mut socket,
             ..
         }: &mut AppContext,
     ) -> GameResult<crate::app_state::Transition> {
-        if let Some(ref mut socket) = socket {
-            socket.manual_poll(Instant::now());
-            while let Some(event) = socket.recv() {
-                match event {
-                    SocketEvent::Packet(packet) => {
-                        let data = match bincode::deserialize(packet.payload()) {
-                            Ok(data) => data,
-                            Err(_) => continue,
-                        };
-                        match data {
-                            NetworkData::Client(client_packet) => {
-                                if let Some(response) = self.client.handle_packet(client_packet) {
-                                    let _ = socket.send(SocketPacket::unreliable(
-                                        packet.addr(),
-                                        bincode::serialize(&NetworkData::Client(response)).unwrap(),
-                                    ));
-                                }
-                            }
-                            NetworkData::Ping(ping_time) => {
-                                let _ = socket.send(SocketPacket::unreliable_sequenced(
-                                    packet.addr(),
-                                    bincode::serialize(&NetworkData::Pong(ping_time)).unwrap(),
-                                    Some(2),
-                                ));
-                            }
-                            NetworkData::Pong(pong_time) => {
-                                let ping_time =
-                                    (Instant::now() - self.start_time).as_millis() - pong_time;
-
-                                let player = self
-                                    .player_list
-                                    .current_players
-                                    .iter()
-                                    .position(|item| item == &packet.addr().into());
-
-                                if let Some(player) = player {
-                                    *self.pings.get_mut(&player).unwrap() =
-                                        self.pings[&player] * 0.5 + (ping_time as f32 / 2.0) * 0.5;
-                                    self.client.set_network_delay(
-                                        ((self.pings[&player]) / 16.0).ceil() as usize,
-                                        player,
-                                    );
-                                }
-                            }
+        let stats_key_held = keyboard::is_key_pressed(ctx, STATS_KEY);
+        if stats_key_held && !self.stats_key_held {
+            self.show_stats = !self.show_stats;
+        }
+        self.stats_key_held = stats_key_held;
+
+        for event in self.poll_events() {
+            match event {
+                Event::Packet(from, NetworkData::Client(client_packet)) => {
+                    let sender: Vec<_> = self.player_of(from).into_iter().collect();
+                    match self.client.handle_packet_from(&sender, client_packet) {
+                        Ok(Some(response)) => {
+                            self.send(from, &NetworkData::Client(response));
                         }
+                        Ok(None) => (),
+                        // a bad packet is dropped, but if we can't stay in sync the match is over
+                        Err(err) if err.is_recoverable() => (),
+                        Err(err) => self.next = Some(NextState::Ended(MatchOutcome::Failed(err))),
                     }
-                    SocketEvent::Timeout(timed_out_addr) => {
-                        if self
-                            .player_list
-                            .current_players
-                            .iter()
-                            .any(|item| item == &timed_out_addr.into())
-                        {
-                            self.next = Some(NextState::Back);
-                        }
+                }
+                Event::Packet(from, NetworkData::Ping(ping_time)) => {
+                    self.send(from, &NetworkData::Pong(ping_time));
+                }
+                Event::Packet(from, NetworkData::SpectateRequest(frame)) => {
+                    if self.is_host() {
+                        let packet = self.spectate_packet(frame);
+                        self.send(from, &packet);
+                    }
+                }
+                Event::Packet(_, NetworkData::Spectate(..)) => (),
+                Event::Packet(from, NetworkData::Pong(pong_time)) => {
+                    let ping_time = (Instant::now() - self.start_time).as_millis() - pong_time;
+
+                    if let Some(player) = self.player_of(from) {
+                        let _ = self.client.add_rtt_sample(player, ping_time as f32);
+                        *self.pings.get_mut(&player).unwrap() =
+                            self.pings[&player] * 0.5 + (ping_time as f32 / 2.0) * 0.5;
+                        let _ = self.client.set_network_delay(
+                            ((self.pings[&player]) / 16.0).ceil() as usize,
+                            player,
+                        );
+                    }
+                }
+                Event::Disconnected(peer) => {
+                    if let Some(player) = self.player_of(peer) {
+                        self.disconnect(player);
                     }
-                    SocketEvent::Connect(_) => {}
                 }
             }
         }
 
         while ggez::timer::check_update_time(ctx, 60) {
+            let mut outputs = Vec::new();
             for ((handle, input), player) in self.local_input.iter_mut().zip(
                 self.player_list
                     .current_players
@@ -201,51 +298,74 @@ impl AppState for NetplayVersus {
 
                 *input = control_scheme.map(*input, &controllers.current_state(&player));
 
-                if let Some(output) = self.client.handle_local_input(*input, *handle) {
-                    if let Some(ref mut socket) = socket {
-                        let output = NetworkData::Client(output);
-                        for addr in self.player_list.network_addrs() {
-                            let _ = socket.send(SocketPacket::unreliable(
-                                addr,
-                                bincode::serialize(&output).unwrap(),
-                            ));
-                        }
-                    }
+                match self.client.handle_local_input(*input, *handle) {
+                    Ok(Some(output)) => outputs.push(NetworkData::Client(output)),
+                    Ok(None) => (),
+                    // the user's own inputs can't be dropped, so the match can't go on without them
+                    Err(err) => self.next = Some(NextState::Ended(MatchOutcome::Failed(err))),
                 }
             }
+            for output in outputs {
+                self.broadcast(&output);
+            }
+            if self.next.is_some() {
+                break;
+            }
 
-            if let Some(ref mut socket) = socket {
-                let time = (Instant::now() - self.start_time).as_millis();
-                let ping_packet = NetworkData::Ping(time);
-                for addr in self
-                    .player_list
-                    .current_players
-                    .iter()
-                    .filter_map(PlayerType::addr)
-                {
-                    let _ = socket.send(SocketPacket::unreliable_sequenced(
-                        addr,
-                        bincode::serialize(&ping_packet).unwrap(),
-                        Some(1),
-                    ));
+            let time = (Instant::now() - self.start_time).as_millis();
+            self.broadcast(&NetworkData::Ping(time));
+
+            match self.client.update(&mut self.game_state) {
+                Ok(Some(output)) => {
+                    self.broadcast(&NetworkData::Client(output));
+                }
+                Ok(None) => (),
+                Err(NetcodeError::Disconnected(player)) => {
+                    self.disconnect(player);
+                    break;
+                }
+                Err(err) => {
+                    self.next = Some(NextState::Ended(MatchOutcome::Failed(err)));
+                    break;
                 }
             }
 
-            self.client.update(&mut self.game_state);
-            if self.game_state.game_over().is_some() {
-                self.next = Some(NextState::Back);
+            // the round only changes while inputs are ignored, so it's safe to change the delay
+            if let Some(output) = self.client.propose_input_delay(self.game_state.round()) {
+                self.broadcast(&NetworkData::Client(output));
+            }
+
+            // only confirmed frames go in the replay, so it never has to rewind
+            let first_new_frame = self.confirmed_inputs.len();
+            for (frame, inputs) in self.client.drain_confirmed_inputs() {
+                let inputs: PlayerData<_> = inputs.into_iter().collect();
+                self.game_state.record_frame(frame, inputs);
+                self.confirmed_inputs.push(inputs);
+            }
+
+            // spectators are only ever sent confirmed inputs, and never waited on
+            if self.is_host() && self.confirmed_inputs.len() > first_new_frame {
+                let output = self.spectate_packet(
+                    self.confirmed_inputs
+                        .len()
+                        .saturating_sub(SPECTATE_PACKET_FRAMES),
+                );
+                for peer in self.spectators() {
+                    self.send(peer, &output);
+                }
+            }
+            if let Some(winners) = self.game_state.game_over() {
+                self.next = Some(NextState::Ended(MatchOutcome::Finished(winners)));
             }
             self.game_state.render_sounds(60, audio)?;
         }
 
         match std::mem::replace(&mut self.next, None) {
             Some(state) => match state {
-                NextState::Back => Ok(Transition::Replace(Box::new(
-                    RetryScreen::<NetplayVersus>::new(
-                        self.player_list.clone(),
-                        self.game_state.settings.clone(),
-                    ),
-                ))),
+                // the result screen goes back to the lobby, where the players can ready up again
+                NextState::Ended(outcome) => {
+                    Ok(Transition::Replace(Box::new(MatchResult::new(outcome))))
+                }
             },
             None => Ok(Transition::None),
         }
@@ -263,13 +383,115 @@ impl AppState for NetplayVersus {
         }
         Ok(())
     }
-    fn draw(&mut self, ctx: &mut Context, AppContext { .. }: &mut AppContext) -> GameResult<()> {
+    fn draw(
+        &mut self,
+        ctx: &mut Context,
+        AppContext { imgui, .. }: &mut AppContext,
+    ) -> GameResult<()> {
         graphics::clear(ctx, graphics::BLACK);
 
         self.game_state.draw(ctx)?;
 
+        let desync = self.client.desync();
+        let waiting = self.client.disconnect_countdown();
+        let stats = if self.show_stats {
+            Some(self.client.stats())
+        } else {
+            None
+        };
+
+        if desync.is_some() || waiting.is_some() || stats.is_some() {
+            imgui
+                .frame()
+                .run(|ui| {
+                    if let Some((player, frames_left)) = waiting {
+                        imgui::Window::new(im_str!("Waiting"))
+                            .no_nav()
+                            .always_auto_resize(true)
+                            .build(ui, || {
+                                ui.text(im_str!("Waiting for player {}...", player + 1));
+                                ui.text(im_str!("Disconnecting in {}", (frames_left + 59) / 60));
+                            });
+                    }
+                    if let Some(desync) = desync {
+                        imgui::Window::new(im_str!("Desync"))
+                            .no_nav()
+                            .always_auto_resize(true)
+                            .build(ui, || {
+                                ui.text(im_str!("Desync detected on frame {}.", desync.frame));
+                                ui.text(im_str!(
+                                    "local: {:016x}, remote: {:016x}",
+                                    desync.local,
+                                    desync.remote
+                                ));
+                            });
+                    }
+                    if let Some(stats) = stats {
+                        imgui::Window::new(im_str!("Network"))
+                            .no_nav()
+                            .always_auto_resize(true)
+                            .build(ui, || {
+                                ui.text(im_str!("rollbacks/s: {}", stats.rollbacks_per_second));
+                                ui.text(im_str!(
+                                    "rollback depth: {:.1} avg, {} max",
+                                    stats.average_rollback_depth,
+                                    stats.max_rollback_depth
+                                ));
+                                ui.text(im_str!("frames skipped: {}", stats.frames_skipped));
+                                ui.text(im_str!(
+                                    "mispredictions: {:.1}%",
+                                    stats.prediction_error_rate * 100.0
+                                ));
+                                ui.text(im_str!(
+                                    "packets: {} sent, {} received",
+                                    stats.packets_sent,
+                                    stats.packets_received
+                                ));
+
+                                let mut players: Vec<_> = stats.players.into_iter().collect();
+                                players.sort_by_key(|(handle, _)| *handle);
+                                for (handle, player) in players {
+                                    ui.separator();
+                                    ui.text(im_str!("player {}", handle + 1));
+                                    match player.ping {
+                                        Some(ping) => ui.text(im_str!(
+                                            "ping: {:.0}ms (+/- {:.0}ms)",
+                                            ping.rtt,
+                                            ping.jitter
+                                        )),
+                                        None => ui.text(im_str!("ping: ---")),
+                                    }
+                                    ui.text(im_str!(
+                                        "frame advantage: {:.1} local, {:.1} remote",
+                                        player.local_frame_advantage,
+                                        player.remote_frame_advantage
+                                    ));
+                                }
+                            });
+                    }
+                })
+                .render(ctx);
+        }
+
         graphics::present(ctx)?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn spectate_frames_in_range() {
+        assert_eq!(spectate_frames(100, 20), 20..36);
+        assert_eq!(spectate_frames(30, 20), 20..30);
+        assert_eq!(spectate_frames(10, 20), 10..10);
+        assert_eq!(spectate_frames(10, usize::MAX), 10..10);
+        assert_eq!(
+            spectate_frames(usize::MAX, usize::MAX - 1),
+            usize::MAX - 1..usize::MAX
+        );
+    }
+}
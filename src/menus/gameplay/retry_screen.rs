@@ -4,10 +4,9 @@ use crate::game_match::MatchSettings;
 use crate::player_list::PlayerList;
 use fg_controller::backend::ControllerBackend;
 use fg_datastructures::player_data::PlayerData;
-use fg_ui::menu::{self, Menu};
+use fg_ui::menu::Menu;
 use ggez::{graphics, Context, GameResult};
 use imgui::im_str;
-use laminar::{Packet, SocketEvent};
 use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
 use strum::{Display, EnumCount, EnumIter};
@@ -62,7 +61,6 @@ impl<Target: FromMatchSettings + AppState + 'static> AppState for RetryScreen<Ta
         ctx: &mut Context,
         AppContext {
             ref mut controllers,
-            ref mut socket,
             ..
         }: &mut AppContext,
     ) -> GameResult<crate::app_state::Transition> {
@@ -74,46 +72,6 @@ impl<Target: FromMatchSettings + AppState + 'static> AppState for RetryScreen<Ta
             .filter_map(|(player, state)| player.gamepad_id().map(|id| (id, state)))
         {
             state.update(&controllers.current_state(&player));
-
-            if let Some(ref mut socket) = socket {
-                for addr in self.player_list.network_addrs() {
-                    let _ = socket.send(Packet::reliable_ordered(
-                        addr,
-                        bincode::serialize(state.state()).unwrap(),
-                        None,
-                    ));
-                }
-            }
-        }
-
-        if let Some(socket) = socket {
-            socket.manual_poll(std::time::Instant::now());
-
-            while let Some(packet) = socket.recv() {
-                match packet {
-                    SocketEvent::Packet(packet) => {
-                        let payload: menu::MenuState = match bincode::deserialize(packet.payload())
-                        {
-                            Ok(payload) => payload,
-                            Err(_) => continue,
-                        };
-                        let player = if let Some(idx) = self
-                            .player_list
-                            .current_players
-                            .iter()
-                            .position(|item| item == &packet.addr().into())
-                        {
-                            idx
-                        } else {
-                            continue;
-                        };
-
-                        self.state[player].set_state(payload);
-                    }
-                    SocketEvent::Timeout(_) => {}
-                    SocketEvent::Connect(_) => {}
-                }
-            }
         }
 
         if self.state.iter().all(|item| item.confirmed()) {
@@ -0,0 +1,148 @@
+use super::netplay_versus::NetworkData;
+use crate::app_state::{AppContext, AppState, Transition};
+use crate::game_match::{Match, MatchSettings};
+use crate::menus::networked::match_result::{MatchOutcome, MatchResult};
+use fg_datastructures::player_data::PlayerData;
+use fg_input::InputState;
+use fg_netcode::game::{Match as LobbyMatch, MatchMessage, WhoIs};
+use ggez::{graphics, Context, GameResult};
+
+type SpectateMatch = Match<crate::replay::ReplayWriterFile>;
+
+/// How many frames of input a spectator holds before playing them back.
+pub const DEFAULT_BUFFER_DELAY: usize = 30;
+
+// how often a stalled spectator asks the host for inputs again
+const REQUEST_INTERVAL: usize = 15;
+
+enum NextState {
+    Ended(MatchOutcome),
+}
+
+pub struct SpectateVersus {
+    next: Option<NextState>,
+    lobby: LobbyMatch,
+
+    inputs: PlayerData<Vec<InputState>>,
+    buffer_delay: usize,
+    buffering: bool,
+    stalled_frames: usize,
+
+    game_state: SpectateMatch,
+}
+
+impl SpectateVersus {
+    /// Watches a lobby game, with confirmed inputs coming from P1.
+    pub fn from_lobby(
+        ctx: &mut Context,
+        lobby: LobbyMatch,
+        settings: MatchSettings,
+        buffer_delay: usize,
+    ) -> GameResult<Self> {
+        Ok(Self {
+            next: None,
+            lobby,
+            inputs: [vec![], vec![]].into(),
+            buffer_delay,
+            buffering: true,
+            stalled_frames: 0,
+            game_state: SpectateMatch::new(
+                ctx,
+                settings,
+                crate::replay::create_new_replay_file("spectate")?,
+            )?,
+        })
+    }
+
+    fn received_frames(&self) -> usize {
+        self.inputs.p1().len()
+    }
+
+    fn add_inputs(&mut self, start_frame: usize, inputs: Vec<PlayerData<InputState>>) {
+        // frames are only accepted in order, anything missing is requested again
+        for (frame, input) in (start_frame..).zip(inputs) {
+            if frame == self.received_frames() {
+                for (list, input) in self.inputs.iter_mut().zip(input.iter()) {
+                    list.push(*input);
+                }
+            }
+        }
+    }
+}
+
+impl AppState for SpectateVersus {
+    fn update(
+        &mut self,
+        ctx: &mut Context,
+        &mut AppContext { ref audio, .. }: &mut AppContext,
+    ) -> GameResult<crate::app_state::Transition> {
+        while let Some(message) = self.lobby.poll() {
+            match message {
+                MatchMessage::Packet(WhoIs::P1, data) | MatchMessage::Control(WhoIs::P1, data) => {
+                    if let Ok(NetworkData::Spectate(start_frame, inputs)) =
+                        bincode::deserialize(&data)
+                    {
+                        self.add_inputs(start_frame, inputs);
+                    }
+                }
+                MatchMessage::Disconnected(WhoIs::P1) => {
+                    self.next = Some(NextState::Ended(MatchOutcome::Disconnected(0)));
+                }
+                _ => (),
+            }
+        }
+
+        while ggez::timer::check_update_time(ctx, 60) {
+            let current_frame = self.game_state.current_frame() as usize;
+            let buffered = self.received_frames().saturating_sub(current_frame);
+
+            if self.buffering {
+                self.buffering = buffered < self.buffer_delay;
+            } else if buffered == 0 {
+                // ran out of inputs, so wait until the delay has been built back up
+                self.buffering = true;
+            }
+
+            if self.buffering {
+                if self.stalled_frames % REQUEST_INTERVAL == 0 {
+                    self.lobby.send(
+                        WhoIs::P1,
+                        bincode::serialize(&NetworkData::SpectateRequest(self.received_frames()))
+                            .unwrap(),
+                    );
+                }
+                self.stalled_frames += 1;
+                continue;
+            }
+            self.stalled_frames = 0;
+
+            self.game_state
+                .update(self.inputs.as_ref().map(|item| &item[..=current_frame]));
+            if let Some(winners) = self.game_state.game_over() {
+                self.next = Some(NextState::Ended(MatchOutcome::Finished(winners)));
+            }
+            self.game_state.render_sounds(60, audio)?;
+        }
+
+        match std::mem::replace(&mut self.next, None) {
+            Some(state) => match state {
+                NextState::Ended(outcome) => {
+                    Ok(Transition::Replace(Box::new(MatchResult::new(outcome))))
+                }
+            },
+            None => Ok(Transition::None),
+        }
+    }
+    fn on_enter(&mut self, _: &mut Context, _: &mut AppContext) -> GameResult<()> {
+        Ok(())
+    }
+    fn draw(&mut self, ctx: &mut Context, AppContext { .. }: &mut AppContext) -> GameResult<()> {
+        graphics::clear(ctx, graphics::BLACK);
+
+        self.game_state.draw(ctx)?;
+
+        graphics::present(ctx)?;
+
+        Ok(())
+    }
+}
@@ -4,11 +4,70 @@ use crate::player_list::PlayerList;
 use fg_controller::backend::ControllerBackend;
 use fg_datastructures::player_data::PlayerData;
 use fg_input::InputState;
+use fg_rollback::{NetcodeClient, RollbackableGameState, SyncTestFailure};
 use ggez::{graphics, Context, GameResult};
 use inspect_design::traits::*;
 
 type TrainingMatch = Match<crate::replay::ReplayWriterFile>;
 
+const SYNC_TEST_DISTANCE: usize = 8;
+
+/// A client that sync tests both players locally, as used by training mode and the replay tests.
+pub fn sync_test_client<GameState>() -> NetcodeClient<InputState, GameState> {
+    let mut client = NetcodeClient::new(60);
+    client.add_local_player(0);
+    client.add_local_player(1);
+    client.set_input_delay(0);
+    client.set_allowed_rollback(SYNC_TEST_DISTANCE);
+    client.set_sync_test(Some(SYNC_TEST_DISTANCE));
+    client
+}
+
+// sync testing can be turned on partway through a match, but the client counts frames from
+// when it was made, and the replay has to keep counting from the start of the match
+struct SyncTest<GameState> {
+    client: NetcodeClient<InputState, GameState>,
+    first_frame: usize,
+}
+
+impl<GameState> SyncTest<GameState> {
+    fn new(first_frame: usize) -> Self {
+        Self {
+            client: sync_test_client(),
+            first_frame,
+        }
+    }
+
+    fn update<Game: RollbackableGameState<Input = InputState, SavedState = GameState>>(
+        &mut self,
+        game: &mut Game,
+        inputs: PlayerData<InputState>,
+    ) {
+        // sync testing is local only, so there's nothing that can fail here
+        for (handle, input) in inputs.iter().enumerate() {
+            self.client.handle_local_input(*input, handle).unwrap();
+        }
+        self.client.update(game).unwrap();
+    }
+
+    /// Every frame confirmed since the last call, numbered from the start of the match.
+    fn confirmed_inputs(&mut self) -> impl Iterator<Item = (usize, Vec<InputState>)> + '_ {
+        let first_frame = self.first_frame;
+        self.client
+            .drain_confirmed_inputs()
+            .map(move |(frame, inputs)| (first_frame + frame, inputs))
+    }
+
+    fn failure(&self) -> Option<SyncTestFailure> {
+        self.client
+            .sync_test_failure()
+            .map(|failure| SyncTestFailure {
+                frame: self.first_frame + failure.frame,
+                ..failure
+            })
+    }
+}
+
 enum NextState {
     Back,
 }
@@ -21,6 +80,7 @@ pub struct TrainingMode {
     dirty: bool,
     // inspect_state: <crate::roster::yuyuko::Yuyuko as Inspect>::State,
     fps: u32,
+    sync_test: Option<SyncTest<<TrainingMatch as RollbackableGameState>::SavedState>>,
 }
 
 impl FromMatchSettings for TrainingMode {
@@ -51,6 +111,7 @@ impl TrainingMode {
             dirty: true,
             // inspect_state: Default::default(),
             fps: 60,
+            sync_test: None,
         })
     }
 }
@@ -87,8 +148,19 @@ impl AppState for TrainingMode {
             }
 
             count += 1;
-            self.game_state
-                .update(self.inputs.as_ref().map(|item| item.as_slice()));
+            if let Some(sync_test) = &mut self.sync_test {
+                sync_test.update(
+                    &mut self.game_state,
+                    self.inputs.as_ref().map(|input| *input.last().unwrap()),
+                );
+                for (frame, inputs) in sync_test.confirmed_inputs() {
+                    self.game_state
+                        .record_frame(frame, inputs.into_iter().collect());
+                }
+            } else {
+                self.game_state
+                    .update(self.inputs.as_ref().map(|item| item.as_slice()));
+            }
             self.game_state.render_sounds(60, audio)?;
 
             if self.game_state.game_over().is_some() {
@@ -134,6 +206,8 @@ impl AppState for TrainingMode {
 
             // let inspect_state = &mut self.inspect_state;
             let fps = &mut self.fps;
+            let current_frame = self.game_state.current_frame() as usize;
+            let sync_test = &mut self.sync_test;
             match self.game_state.players.p1_mut() {
                 crate::roster::CharacterBehavior::YuyukoPlayer(value) => {
                     imgui
@@ -148,6 +222,22 @@ impl AppState for TrainingMode {
                                 .no_nav()
                                 .build(ui, || {
                                     fps.inspect_mut("fps", &mut (), ui);
+                                    let mut sync_testing = sync_test.is_some();
+                                    if ui.checkbox(imgui::im_str!("Sync Test"), &mut sync_testing) {
+                                        *sync_test = if sync_testing {
+                                            Some(SyncTest::new(current_frame))
+                                        } else {
+                                            None
+                                        };
+                                    }
+                                    if let Some(failure) =
+                                        sync_test.as_ref().and_then(SyncTest::failure)
+                                    {
+                                        ui.text(imgui::im_str!(
+                                            "Sync test failed on frame {}.",
+                                            failure.frame
+                                        ));
+                                    }
                                     value.state.current_state.inspect(
                                         "state",
                                         &mut Default::default(),
@@ -165,3 +255,66 @@ impl AppState for TrainingMode {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fg_rollback::InputSet;
+
+    // counts its frames the same way the match does
+    #[derive(Default)]
+    struct Game {
+        frame: usize,
+    }
+
+    impl RollbackableGameState for Game {
+        type Input = InputState;
+        type SavedState = usize;
+
+        fn player_count(&self) -> usize {
+            2
+        }
+        fn advance_frame(&mut self, _: InputSet<'_, Self::Input>) {
+            self.frame += 1;
+        }
+        fn save_state(&self) -> Self::SavedState {
+            self.frame
+        }
+        fn load_state(&mut self, load: &Self::SavedState) {
+            self.frame = *load;
+        }
+        fn checksum(&self) -> u64 {
+            self.frame as u64
+        }
+    }
+
+    // without a sync test, each frame is recorded as it's played, like `Match::update` does
+    fn play(game: &mut Game, replay: &mut Vec<usize>, frames: usize) {
+        for _ in 0..frames {
+            replay.push(game.frame);
+            game.frame += 1;
+        }
+    }
+
+    fn sync_test(game: &mut Game, replay: &mut Vec<usize>, frames: usize) {
+        let mut sync_test = SyncTest::new(game.frame);
+        for _ in 0..frames {
+            sync_test.update(game, [InputState::default(); 2].into());
+            replay.extend(sync_test.confirmed_inputs().map(|(frame, _)| frame));
+        }
+        assert_eq!(sync_test.failure(), None);
+    }
+
+    #[test]
+    fn replay_across_sync_test_toggles() {
+        let mut game = Game::default();
+        let mut replay = Vec::new();
+
+        play(&mut game, &mut replay, 10);
+        sync_test(&mut game, &mut replay, 20);
+        play(&mut game, &mut replay, 5);
+        sync_test(&mut game, &mut replay, 10);
+
+        assert_eq!(replay, (0..45).collect::<Vec<_>>());
+    }
+}
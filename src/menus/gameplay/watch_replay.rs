@@ -1,8 +1,8 @@
 use crate::app_state::{AppContext, AppState, Transition};
 use crate::game_match::{MatchSettings, MatchSettingsError, NoLogMatch};
-use crate::netcode::RollbackableGameState;
 use fg_datastructures::player_data::PlayerData;
 use fg_input::InputState;
+use fg_rollback::RollbackableGameState;
 use ggez::{graphics, Context, GameResult};
 
 use std::io::Read;
@@ -65,6 +65,11 @@ impl<Reader: Read> AppState for WatchReplay<Reader> {
                             break 'game_play;
                         }
                     };
+                    if next_frame == crate::replay::DISCONNECT_FRAME {
+                        // the match ended early, so there's nothing left to watch
+                        self.next = Some(NextState::Back);
+                        break 'game_play;
+                    }
                     let p1_input: InputState = bincode::deserialize_from(&mut self.reader).unwrap();
                     let p2_input: InputState = bincode::deserialize_from(&mut self.reader).unwrap();
 
@@ -74,13 +79,15 @@ impl<Reader: Read> AppState for WatchReplay<Reader> {
                             self.inputs.p2_mut().push(p2_input);
                             break 'stream_inputs;
                         }
+                        // replays written from every simulated frame, rollbacks included,
+                        // go back and replace inputs that have already been played
                         std::cmp::Ordering::Less => {
                             let next_frame = next_frame as usize;
                             self.inputs.p1_mut()[next_frame] = p1_input;
                             self.inputs.p2_mut()[next_frame] = p2_input;
                             let target_frame = self.game_state.current_frame();
                             self.game_state
-                                .load_state(self.previous_states[next_frame].clone());
+                                .load_state(&self.previous_states[next_frame]);
                             while self.game_state.current_frame() < target_frame {
                                 self.previous_states[self.game_state.current_frame() as usize] =
                                     self.game_state.save_state();
@@ -1,6 +1,6 @@
 use super::gameplay::training_mode::TrainingMode;
 use super::gameplay::watch_replay::WatchReplay;
-use super::gameplay::{CharacterSelect, ControllerSelect, NetworkConnect};
+use super::gameplay::{CharacterSelect, ControllerSelect};
 use super::SettingsMenu;
 use super::{gameplay::local_versus::LocalVersus, networked::lobby_select::LobbySelect};
 
@@ -16,7 +16,6 @@ enum NextState {
     Settings,
     TrainingModeControllerSelect,
     VsModeControllerSelect,
-    NetworkSelect,
     LobbySelect,
     WatchReplay(
         crate::game_match::MatchSettings,
@@ -45,11 +44,6 @@ impl AppState for MainMenu {
                 NextState::Quit => Ok(Transition::Pop),
                 NextState::Editor => Ok(Transition::Push(Box::new(EditorMenu::new()))),
                 NextState::Settings => Ok(Transition::Push(Box::new(SettingsMenu::new()))),
-                NextState::NetworkSelect => {
-                    Ok(Transition::Push(Box::new(
-                        ControllerSelect::<NetworkConnect>::new([true, false].into()),
-                    )))
-                }
                 NextState::LobbySelect => {
                     Ok(Transition::Push(Box::new(
                         ControllerSelect::<LobbySelect>::new([true, false].into()),
@@ -102,9 +96,6 @@ impl AppState for MainMenu {
                     if ui.small_button(im_str!("Training Mode")) {
                         self.next = Some(NextState::TrainingModeControllerSelect);
                     }
-                    if ui.small_button(im_str!("Network")) {
-                        self.next = Some(NextState::NetworkSelect);
-                    }
                     if ui.small_button(im_str!("Lobby Select")) {
                         self.next = Some(NextState::LobbySelect);
                     }
@@ -1,2 +1,3 @@
 pub mod lobby_select;
 pub mod lobby_view;
+pub mod match_result;
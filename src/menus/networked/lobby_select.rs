@@ -1,12 +1,17 @@
 use std::net::SocketAddr;
 
 use crate::player_list::PlayerList;
+use crate::roster;
 use crate::{
     app_state::{AppContext, AppState, Transition},
+    imgui_extra::UiExtensions,
     menus::gameplay::controller_select::FromControllerList,
 };
 use fg_datastructures::roster::RosterCharacter;
-use fg_netcode::{lobby::Lobby, player_info::PlayerInfo, NetworkingMessage};
+use fg_netcode::{
+    compatibility::Compatibility, discovery::Discovery, error::JoinLobbyError, lobby::Lobby,
+    player_info::PlayerInfo, NetworkingMessage,
+};
 use ggez::{graphics, Context, GameResult};
 use imgui::im_str;
 use inspect_design::{from_str::InspectAsText, traits::InspectMut};
@@ -38,8 +43,14 @@ pub struct LobbySelect {
     next: NextState,
     state: UiState,
     join_ip: InspectAsText<SocketAddr>,
+    // used both to host with and to join with, and left empty for no password
+    password: String,
+    // `None` if something else on this machine is already listening for lobbies
+    discovery: Option<Discovery>,
     main_player: ControllerId,
     user: PlayerInfo,
+    // hashed the first time the menu is entered, since it reads every character's data
+    compatibility: Option<Compatibility>,
 }
 
 impl LobbySelect {
@@ -48,16 +59,28 @@ impl LobbySelect {
             next: NextState::None,
             state: UiState::Main(None),
             join_ip: InspectAsText::default(),
+            password: String::new(),
+            discovery: Discovery::new().ok(),
             main_player,
             user: PlayerInfo {
                 name: "THE Angel of Sol".to_string(),
                 character: RosterCharacter::default(),
                 addr: "192.168.1.1:10800".parse().unwrap(),
             },
+            compatibility: None,
         })
     }
 }
 
+impl LobbySelect {
+    fn password(&self) -> Option<String> {
+        Some(self.password.clone()).filter(|password| !password.is_empty())
+    }
+    fn compatibility(&self) -> Compatibility {
+        self.compatibility.clone().unwrap()
+    }
+}
+
 impl AppState for LobbySelect {
     fn update(
         &mut self,
@@ -69,16 +92,48 @@ impl AppState for LobbySelect {
                 NetworkingMessage::Host(Ok(lobby)) | NetworkingMessage::Join(Ok(lobby)) => {
                     self.next = NextState::Lobby(lobby)
                 }
+                NetworkingMessage::Join(Err(JoinLobbyError::IncorrectPassword)) => {
+                    self.state = UiState::Main(Some("Incorrect password.".to_string()))
+                }
+                NetworkingMessage::Join(Err(JoinLobbyError::Denied)) => {
+                    self.state = UiState::Main(Some("The host didn't let you in.".to_string()))
+                }
+                NetworkingMessage::Join(Err(JoinLobbyError::Banned)) => {
+                    self.state = UiState::Main(Some("You're banned from this lobby.".to_string()))
+                }
+                NetworkingMessage::Join(Err(JoinLobbyError::Locked)) => {
+                    self.state = UiState::Main(Some("This lobby is locked.".to_string()))
+                }
+                NetworkingMessage::Join(Err(JoinLobbyError::Incompatible(differences))) => {
+                    self.state = UiState::Main(Some(format!(
+                        "Your game is different from the host's: {}.",
+                        differences
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )))
+                }
+                NetworkingMessage::Join(Err(JoinLobbyError::CertificateChanged)) => {
+                    self.state = UiState::Main(Some(
+                        "This host's identity changed since you last connected.".to_string(),
+                    ))
+                }
                 _ => self.state = UiState::Main(Some("Connection failed.".to_string())),
             }
         }
 
         while ggez::timer::check_update_time(ctx, 60) {}
 
+        if let Some(discovery) = &mut self.discovery {
+            discovery.poll();
+        }
+
         match std::mem::replace(&mut self.next, NextState::None) {
             NextState::Lobby(lobby) => Ok(Transition::Push(Box::new(LobbyView::new(
                 self.main_player,
                 lobby,
+                self.password().is_some(),
             )))),
             NextState::Back => Ok(Transition::Pop),
             NextState::None => Ok(Transition::None),
@@ -86,6 +141,9 @@ impl AppState for LobbySelect {
     }
     fn on_enter(&mut self, _: &mut Context, _: &mut AppContext) -> GameResult<()> {
         self.state = UiState::Main(None);
+        if self.compatibility.is_none() {
+            self.compatibility = Some(roster::compatibility()?);
+        }
         Ok(())
     }
     fn draw(
@@ -113,14 +171,55 @@ impl AppState for LobbySelect {
                             if let Some(text) = text {
                                 ui.text(im_str!("{}", text));
                             }
+                            ui.input_string(im_str!("Password"), &mut self.password);
                             if ui.small_button(im_str!("Host")) {
-                                networking.request_host(self.user.clone());
+                                networking.request_host(
+                                    self.user.clone(),
+                                    self.password(),
+                                    self.compatibility(),
+                                );
                                 self.state = UiState::Hosting;
                             }
 
                             if ui.small_button(im_str!("Join")) {
                                 ui.open_popup(im_str!("JoinModal"));
                             }
+
+                            ui.separator();
+                            ui.text("LAN Lobbies");
+                            let lobbies = self
+                                .discovery
+                                .as_ref()
+                                .map(|discovery| discovery.lobbies())
+                                .unwrap_or_default();
+                            if lobbies.is_empty() {
+                                ui.text("None found.");
+                            }
+                            for (idx, lobby) in lobbies.iter().enumerate() {
+                                let announcement = &lobby.announcement;
+                                ui.text(im_str!(
+                                    "{} ({} players){}",
+                                    announcement.name,
+                                    announcement.players,
+                                    if announcement.password {
+                                        ", needs a password"
+                                    } else {
+                                        ""
+                                    }
+                                ));
+                                ui.same_line(0.0);
+                                if announcement.version != env!("CARGO_PKG_VERSION") {
+                                    ui.text(im_str!("Version {}", announcement.version));
+                                } else if ui.small_button(&im_str!("Join##{}", idx)) {
+                                    networking.request_join(
+                                        lobby.addr,
+                                        self.user.clone(),
+                                        self.password(),
+                                        self.compatibility(),
+                                    );
+                                    self.state = UiState::Joining;
+                                }
+                            }
                         }
                         UiState::Joining => ui.text("Joining..."),
                         UiState::Hosting => ui.text("Hosting..."),
@@ -139,6 +238,8 @@ impl AppState for LobbySelect {
                                     networking.request_join(
                                         self.join_ip.take().unwrap(),
                                         self.user.clone(),
+                                        self.password(),
+                                        self.compatibility(),
                                     );
                                     self.state = UiState::Joining;
                                     ui.close_current_popup();
@@ -1,10 +1,28 @@
-use crate::app_state::{AppContext, AppState, Transition};
-use fg_netcode::{lobby::Lobby, player_info::PlayerInfo};
+use crate::{
+    app_state::{AppContext, AppState, Transition},
+    game_match::MatchSettings,
+    imgui_extra::UiExtensions,
+    menus::gameplay::{
+        netplay_versus::NetplayVersus,
+        spectate_versus::{self, SpectateVersus},
+    },
+};
+use fg_controller::backend::ControllerBackend;
+use fg_netcode::{
+    discovery::{Announcer, LobbyAnnouncement},
+    error::{CreateGameError, JoinGameError, LeaveGameError, SpectateGameError, UpdateMetaError},
+    game::{GameMessage, Match, MatchStart, WhoIs},
+    lobby::{GameId, Lobby, LobbyMessage, Removal},
+    player_info::PlayerInfo,
+    player_list::Player,
+};
+use fg_ui::menu::{Menu, MenuAction};
 use ggez::{graphics, Context, GameResult};
 use imgui::{im_str, Condition};
 
 use inspect_design::traits::InspectMut;
 use sdl_controller_backend::ControllerId;
+use std::time::{Duration, Instant};
 
 enum NextState {
     Back,
@@ -13,16 +31,155 @@ enum NextState {
 pub struct LobbyView {
     next: NextState,
     lobby: Lobby,
-    _main_player: ControllerId,
+    error: Option<&'static str>,
+    chat_input: String,
+    phrases: Menu<&'static str>,
+    // the loaded match, and when to start it
+    starting: Option<(Box<dyn AppState>, Instant)>,
+    main_player: ControllerId,
+    // only announces while the user is host, which can change if the host leaves
+    announcer: Option<Announcer>,
+    password: bool,
+    // why the host removed the user, after which the lobby is gone
+    removed: Option<&'static str>,
 }
 
+// so players on a controller can still chat
+const PRESET_PHRASES: &[&str] = &[
+    "Hello!",
+    "Good game!",
+    "One more?",
+    "Thanks!",
+    "Sorry, I have to go.",
+    "Be right back.",
+];
+
 impl LobbyView {
-    pub fn new(main_player: ControllerId, lobby: Lobby) -> Self {
+    /// `password` is whether anyone joining needs one.
+    pub fn new(main_player: ControllerId, lobby: Lobby, password: bool) -> Self {
         Self {
             next: NextState::None,
             lobby,
-            _main_player: main_player,
+            error: None,
+            chat_input: String::new(),
+            phrases: Menu::new(PRESET_PHRASES.to_vec()),
+            starting: None,
+            main_player,
+            announcer: Announcer::new().ok(),
+            password,
+            removed: None,
+        }
+    }
+
+    fn announce(&mut self) {
+        let state = self.lobby.state();
+        if !state.is_user_host() {
+            return;
+        }
+        if let Some(announcer) = &mut self.announcer {
+            let _ = announcer.announce(&LobbyAnnouncement {
+                name: state.host().name.clone(),
+                players: state.players().len(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                port: state.host().addr.port(),
+                password: self.password,
+            });
+        }
+    }
+
+    fn load_match(
+        &mut self,
+        ctx: &mut Context,
+        lobby_match: Match,
+        start: MatchStart,
+    ) -> GameResult<()> {
+        // taken before loading, so however long loading takes comes out of the wait
+        let start_at = Instant::now() + Duration::from_secs_f32(start.start_frame as f32 / 60.0);
+
+        // spectators have nobody to play against
+        let opponent = match lobby_match.user() {
+            WhoIs::P1 => Some(WhoIs::P2),
+            WhoIs::P2 => Some(WhoIs::P1),
+            WhoIs::Spectator(_) => None,
+        };
+        let opponent = match opponent.map(|opponent| {
+            lobby_match
+                .player(opponent)
+                .filter(|player| self.lobby.state().player_list.get(*player).is_some())
+        }) {
+            Some(None) => {
+                self.error = Some("Your opponent left the lobby.");
+                return Ok(());
+            }
+            opponent => opponent.flatten(),
+        };
+
+        let mut settings = MatchSettings::new();
+        settings.first_to = start.first_to;
+        settings.characters = start.characters;
+        settings.load(ctx)?;
+
+        let next: Box<dyn AppState> = match opponent {
+            Some(opponent) => Box::new(NetplayVersus::from_lobby(
+                ctx,
+                lobby_match,
+                settings,
+                self.main_player,
+                opponent,
+            )?),
+            None => Box::new(SpectateVersus::from_lobby(
+                ctx,
+                lobby_match,
+                settings,
+                spectate_versus::DEFAULT_BUFFER_DELAY,
+            )?),
+        };
+        self.starting = Some((next, start_at));
+
+        Ok(())
+    }
+}
+
+fn describe_error(message: LobbyMessage) -> Option<&'static str> {
+    match message {
+        LobbyMessage::CreateGame(Err(CreateGameError::AlreadyInGame))
+        | LobbyMessage::JoinGame(Err(JoinGameError::AlreadyInGame))
+        | LobbyMessage::SpectateGame(Err(SpectateGameError::AlreadyInGame)) => {
+            Some("You're already in a game.")
+        }
+        LobbyMessage::CreateGame(Err(CreateGameError::OutOfGames)) => {
+            Some("There's no room for another game.")
+        }
+        LobbyMessage::JoinGame(Err(JoinGameError::NoSuchGame))
+        | LobbyMessage::SpectateGame(Err(SpectateGameError::NoSuchGame)) => {
+            Some("That game doesn't exist anymore.")
+        }
+        LobbyMessage::JoinGame(Err(JoinGameError::GameFull)) => Some("That game is full."),
+        LobbyMessage::JoinGame(Err(JoinGameError::GameAlreadyStarted)) => {
+            Some("That game has already started.")
+        }
+        LobbyMessage::LeaveGame(Err(LeaveGameError::NotInGame)) => Some("You're not in a game."),
+        LobbyMessage::Kick(Err(UpdateMetaError::InvalidPermission))
+        | LobbyMessage::Ban(Err(UpdateMetaError::InvalidPermission))
+        | LobbyMessage::Lock(Err(UpdateMetaError::InvalidPermission)) => {
+            Some("Only the host can do that.")
         }
+        LobbyMessage::Kick(Err(UpdateMetaError::InvalidUpdate))
+        | LobbyMessage::Ban(Err(UpdateMetaError::InvalidUpdate)) => {
+            Some("That player isn't in the lobby.")
+        }
+        LobbyMessage::Kick(Err(_)) | LobbyMessage::Ban(Err(_)) | LobbyMessage::Lock(Err(_)) => {
+            Some("The lobby couldn't be updated.")
+        }
+        LobbyMessage::Removed(Removal::Kicked) => Some("The host kicked you from the lobby."),
+        LobbyMessage::Removed(Removal::Banned) => Some("The host banned you from the lobby."),
+        LobbyMessage::CreateGame(Ok(_))
+        | LobbyMessage::JoinGame(Ok(_))
+        | LobbyMessage::SpectateGame(Ok(_))
+        | LobbyMessage::LeaveGame(Ok(_))
+        | LobbyMessage::Kick(Ok(_))
+        | LobbyMessage::Ban(Ok(_))
+        | LobbyMessage::Lock(Ok(_)) => None,
     }
 }
 
@@ -30,9 +187,45 @@ impl AppState for LobbyView {
     fn update(
         &mut self,
         ctx: &mut Context,
-        AppContext { .. }: &mut AppContext,
+        AppContext { controllers, .. }: &mut AppContext,
     ) -> GameResult<crate::app_state::Transition> {
-        while ggez::timer::check_update_time(ctx, 60) {}
+        while ggez::timer::check_update_time(ctx, 60) {
+            if let MenuAction::Select = self
+                .phrases
+                .update(&controllers.current_state(&self.main_player))
+            {
+                self.lobby.chat(self.phrases.selected().to_string());
+                self.phrases.deselect();
+            }
+        }
+
+        // nothing is sent after the removal, so the lobby isn't polled again
+        while self.removed.is_none() {
+            match self.lobby.poll() {
+                Some(message @ LobbyMessage::Removed(_)) => self.removed = describe_error(message),
+                Some(message) => self.error = describe_error(message),
+                None => break,
+            }
+        }
+
+        if self.removed.is_none() {
+            self.announce();
+        }
+
+        while let Some(message) = self.lobby.game().poll() {
+            match message {
+                GameMessage::PlayersReady(lobby_match, start) => {
+                    self.load_match(ctx, lobby_match, start)?
+                }
+            }
+        }
+
+        if let Some((_, start_at)) = &self.starting {
+            if Instant::now() >= *start_at {
+                let (next, _) = self.starting.take().unwrap();
+                return Ok(Transition::Push(next));
+            }
+        }
 
         match std::mem::replace(&mut self.next, NextState::None) {
             NextState::Back => Ok(Transition::Pop),
@@ -53,8 +246,16 @@ impl AppState for LobbyView {
         enum Action {
             None,
             CreateGame,
-            JoinGame(usize),
+            JoinGame(GameId),
+            SpectateGame(GameId),
+            LeaveGame,
+            Ready,
+            Pass,
             UpdateUser(PlayerInfo),
+            Chat(String),
+            Kick(Player),
+            Ban(Player),
+            Lock(bool),
         }
         let mut action = Action::None;
         frame
@@ -64,6 +265,14 @@ impl AppState for LobbyView {
                     .size([0.0, 0.0], Condition::Always)
                     .resizable(false)
                     .build(ui, || {
+                        if let Some(reason) = self.removed {
+                            ui.text(im_str!("{}", reason));
+                            if ui.small_button(im_str!("Back")) {
+                                self.next = NextState::Back;
+                            }
+                            return;
+                        }
+
                         let lobby_state = self.lobby.state();
                         ui.text(im_str!("Host: {}", lobby_state.host().name));
                         ui.separator();
@@ -76,10 +285,31 @@ impl AppState for LobbyView {
                         ui.separator();
                         ui.text(im_str!("Players:"));
                         ui.indent();
-                        for player in lobby_state.players().iter() {
-                            ui.text(im_str!("{}", player.name));
+                        for (idx, (player, info)) in lobby_state.player_list.pairs().enumerate() {
+                            ui.text(im_str!("{}", info.name));
+                            if lobby_state.is_user_host() && *player != lobby_state.user {
+                                ui.same_line(0.0);
+                                if ui.small_button(&im_str!("Kick##{}", idx)) {
+                                    action = Action::Kick(*player);
+                                }
+                                ui.same_line(0.0);
+                                if ui.small_button(&im_str!("Ban##{}", idx)) {
+                                    action = Action::Ban(*player);
+                                }
+                            }
                         }
                         ui.unindent();
+                        if lobby_state.is_user_host() {
+                            if lobby_state.locked {
+                                if ui.small_button(im_str!("Unlock Lobby")) {
+                                    action = Action::Lock(false);
+                                }
+                            } else if ui.small_button(im_str!("Lock Lobby")) {
+                                action = Action::Lock(true);
+                            }
+                        } else if lobby_state.locked {
+                            ui.text(im_str!("The lobby is locked."));
+                        }
                         ui.separator();
 
                         if ui.small_button(im_str!("Create Game")) {
@@ -94,12 +324,13 @@ impl AppState for LobbyView {
 
                             ui.text(im_str!("Players:"));
                             ui.indent();
-                            for player in game
-                                .players()
-                                .iter()
-                                .map(|player| lobby_state.player_list.get(*player).unwrap())
-                            {
-                                ui.text(im_str!("{}", player.name));
+                            for (seat, player) in game.players().iter().enumerate() {
+                                let info = lobby_state.player_list.get(*player).unwrap();
+                                if game.ready[seat] {
+                                    ui.text(im_str!("{} (Ready)", info.name));
+                                } else {
+                                    ui.text(im_str!("{}", info.name));
+                                }
                             }
                             ui.unindent();
 
@@ -115,8 +346,32 @@ impl AppState for LobbyView {
                             ui.unindent();
 
                             ui.unindent();
-                            if ui.small_button(im_str!("Join")) {
-                                action = Action::JoinGame(idx);
+                            if game.contains(&lobby_state.user) {
+                                if let Some(seat) = game
+                                    .players()
+                                    .iter()
+                                    .position(|player| *player == lobby_state.user)
+                                {
+                                    if game.ready[seat] {
+                                        if ui.small_button(&im_str!("Not Ready##{}", idx)) {
+                                            action = Action::Pass;
+                                        }
+                                    } else if ui.small_button(&im_str!("Ready##{}", idx)) {
+                                        action = Action::Ready;
+                                    }
+                                    ui.same_line(0.0);
+                                }
+                                if ui.small_button(&im_str!("Leave Game##{}", idx)) {
+                                    action = Action::LeaveGame;
+                                }
+                            } else {
+                                if ui.small_button(&im_str!("Join##{}", idx)) {
+                                    action = Action::JoinGame(game.id);
+                                }
+                                ui.same_line(0.0);
+                                if ui.small_button(&im_str!("Spectate##{}", idx)) {
+                                    action = Action::SpectateGame(game.id);
+                                }
                             }
                             ui.separator();
                         }
@@ -132,6 +387,59 @@ impl AppState for LobbyView {
 
                         ui.separator();
 
+                        ui.text(im_str!("Chat:"));
+                        imgui::ChildWindow::new(im_str!("Chat History"))
+                            .size([400.0, 150.0])
+                            .border(true)
+                            .build(ui, || {
+                                for message in lobby_state.chat.iter() {
+                                    let sender = lobby_state
+                                        .player_list
+                                        .get(message.sender)
+                                        .map_or("(left)", |info| info.name.as_str());
+                                    let sent =
+                                        chrono::DateTime::<chrono::Local>::from(message.sent);
+                                    ui.text(im_str!(
+                                        "[{}] {}: {}",
+                                        sent.format("%H:%M"),
+                                        sender,
+                                        message.text
+                                    ));
+                                }
+                            });
+                        ui.input_string(im_str!("##Chat Input"), &mut self.chat_input);
+                        ui.same_line(0.0);
+                        if ui.small_button(im_str!("Send")) && !self.chat_input.is_empty() {
+                            action = Action::Chat(std::mem::take(&mut self.chat_input));
+                        }
+
+                        ui.text(im_str!("Quick Chat (Up/Down to pick, A to send):"));
+                        ui.indent();
+                        for phrase in self.phrases.items() {
+                            let color = if phrase == self.phrases.selected() {
+                                [1.0, 0.0, 0.0, 1.0]
+                            } else {
+                                [1.0, 1.0, 1.0, 1.0]
+                            };
+                            ui.text_colored(color, &im_str!("{}", phrase));
+                        }
+                        ui.unindent();
+                        ui.separator();
+
+                        if let Some((_, start_at)) = &self.starting {
+                            let frames_left = start_at
+                                .saturating_duration_since(Instant::now())
+                                .as_secs_f32()
+                                * 60.0;
+                            ui.text(im_str!("Starting in {} frames...", frames_left.ceil()));
+                            ui.separator();
+                        }
+
+                        if let Some(error) = self.error {
+                            ui.text(im_str!("{}", error));
+                            ui.separator();
+                        }
+
                         if ui.small_button(im_str!("Leave")) {
                             self.next = NextState::Back;
                         }
@@ -140,10 +448,18 @@ impl AppState for LobbyView {
             .render(ctx);
 
         match action {
-            Action::JoinGame(idx) => self.lobby.join_game(idx).unwrap(),
+            Action::JoinGame(game) => self.lobby.join_game(game),
+            Action::SpectateGame(game) => self.lobby.spectate_game(game),
+            Action::LeaveGame => self.lobby.leave_game(),
+            Action::Ready => self.lobby.game().ready(),
+            Action::Pass => self.lobby.game().pass(),
             Action::CreateGame => self.lobby.create_game(),
             Action::None => (),
             Action::UpdateUser(user) => self.lobby.update_player_data(move |data| *data = user),
+            Action::Chat(text) => self.lobby.chat(text),
+            Action::Kick(player) => self.lobby.kick(player),
+            Action::Ban(player) => self.lobby.ban(player),
+            Action::Lock(locked) => self.lobby.lock(locked),
         }
 
         graphics::present(ctx)?;
@@ -0,0 +1,84 @@
+use crate::app_state::{AppContext, AppState, Transition};
+use fg_datastructures::player_data::PlayerData;
+use fg_rollback::NetcodeError;
+use ggez::{graphics, Context, GameResult};
+use imgui::im_str;
+
+/// How a lobby game's match came to an end.
+#[derive(Debug, Clone, Copy)]
+pub enum MatchOutcome {
+    /// The match was played out, with whoever won marked.
+    Finished(PlayerData<bool>),
+    /// The player at this index stopped responding.
+    Disconnected(usize),
+    /// The players' games couldn't be kept in step, so the match was abandoned.
+    Failed(NetcodeError),
+}
+
+enum NextState {
+    Back,
+    None,
+}
+
+/// Shows how a lobby game ended, and then goes back to the lobby.
+pub struct MatchResult {
+    next: NextState,
+    outcome: MatchOutcome,
+}
+
+impl MatchResult {
+    pub fn new(outcome: MatchOutcome) -> Self {
+        Self {
+            next: NextState::None,
+            outcome,
+        }
+    }
+}
+
+impl AppState for MatchResult {
+    fn update(&mut self, _: &mut Context, _: &mut AppContext) -> GameResult<Transition> {
+        match std::mem::replace(&mut self.next, NextState::None) {
+            NextState::Back => Ok(Transition::Pop),
+            NextState::None => Ok(Transition::None),
+        }
+    }
+    fn on_enter(&mut self, _: &mut Context, _: &mut AppContext) -> GameResult<()> {
+        Ok(())
+    }
+    fn draw(
+        &mut self,
+        ctx: &mut Context,
+        AppContext { imgui, .. }: &mut AppContext,
+    ) -> GameResult<()> {
+        graphics::clear(ctx, graphics::BLACK);
+
+        let frame = imgui.frame();
+
+        frame
+            .run(|ui| {
+                imgui::Window::new(im_str!("Match Over")).build(ui, || {
+                    match self.outcome {
+                        MatchOutcome::Finished(winners) => {
+                            for (player, _) in winners.iter().enumerate().filter(|(_, won)| **won) {
+                                ui.text(im_str!("Player {} wins!", player + 1));
+                            }
+                        }
+                        MatchOutcome::Disconnected(player) => {
+                            ui.text(im_str!("Player {} disconnected.", player + 1));
+                        }
+                        MatchOutcome::Failed(err) => {
+                            ui.text(im_str!("The match couldn't continue: {}.", err));
+                        }
+                    }
+                    if ui.small_button(im_str!("Back to Lobby")) {
+                        self.next = NextState::Back;
+                    }
+                });
+            })
+            .render(ctx);
+
+        graphics::present(ctx)?;
+
+        Ok(())
+    }
+}
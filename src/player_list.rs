@@ -1,12 +1,12 @@
 use fg_datastructures::player_data::PlayerData;
+use fg_netcode::player_list::Player;
 use sdl_controller_backend::ControllerId;
-use std::net::SocketAddr;
 use strum::Display;
 
 #[derive(Debug, Copy, Clone, PartialEq, Display)]
 pub enum PlayerType {
     LocalGamepad(ControllerId),
-    Networked(SocketAddr),
+    Networked(Player),
     Dummy,
 }
 
@@ -35,13 +35,6 @@ impl PlayerType {
             Self::Networked(_) => None,
         }
     }
-    pub fn addr(&self) -> Option<SocketAddr> {
-        match self {
-            Self::Networked(id) => Some(*id),
-            Self::Dummy => None,
-            Self::LocalGamepad(_) => None,
-        }
-    }
 }
 
 impl From<ControllerId> for PlayerType {
@@ -50,8 +43,8 @@ impl From<ControllerId> for PlayerType {
     }
 }
 
-impl From<SocketAddr> for PlayerType {
-    fn from(value: SocketAddr) -> Self {
+impl From<Player> for PlayerType {
+    fn from(value: Player) -> Self {
         Self::Networked(value)
     }
 }
@@ -75,11 +68,4 @@ impl PlayerList {
             .iter()
             .filter_map(PlayerType::gamepad_id)
     }
-
-    pub fn network_addrs(&self) -> impl Iterator<Item = SocketAddr> + '_ {
-        self.current_players
-            .iter()
-            .chain(self.spectators.iter())
-            .filter_map(PlayerType::addr)
-    }
 }
@@ -11,6 +11,10 @@ pub type ReplayReader<T> = DeflateDecoder<BufReader<T>>;
 pub type ReplayWriterFile = ReplayWriter<File>;
 pub type ReplayReaderFile = ReplayReader<File>;
 
+/// Written in place of a frame number when a player disconnects, followed by that player.
+/// Replays never get anywhere near this many frames.
+pub const DISCONNECT_FRAME: u32 = u32::MAX;
+
 pub fn create_new_replay_file(folder: &str) -> std::io::Result<ReplayWriterFile> {
     let mut path = PathBuf::new();
     path.push("replay");
@@ -35,3 +39,99 @@ pub fn create_new_replay_file(folder: &str) -> std::io::Result<ReplayWriterFile>
 pub fn open_replay_file<P: AsRef<Path>>(path: P) -> std::io::Result<ReplayReaderFile> {
     Ok(DeflateDecoder::new(BufReader::new(File::open(path)?)))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game_match::NoLogMatch;
+    use crate::menus::gameplay::training_mode::sync_test_client;
+    use crate::menus::gameplay::watch_replay::WatchReplay;
+    use fg_datastructures::player_data::PlayerData;
+    use fg_input::InputState;
+    use fg_rollback::SyncTestFailure;
+    use ggez::{Context, ContextBuilder};
+    use std::io::Read;
+
+    fn read_inputs<Reader: Read>(mut reader: Reader) -> Vec<PlayerData<InputState>> {
+        let mut inputs = Vec::new();
+        loop {
+            let frame: u32 = match bincode::deserialize_from(&mut reader) {
+                Ok(frame) => frame,
+                Err(kind) => match kind.as_ref() {
+                    bincode::ErrorKind::Io(err)
+                        if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+                    {
+                        break
+                    }
+                    _ => panic!("corrupt replay: {}", kind),
+                },
+            };
+            if frame == DISCONNECT_FRAME {
+                break;
+            }
+            let input: PlayerData<InputState> = [
+                bincode::deserialize_from(&mut reader).unwrap(),
+                bincode::deserialize_from(&mut reader).unwrap(),
+            ]
+            .into();
+
+            // replays recorded before only confirmed frames were written can replace earlier frames
+            let frame = frame as usize;
+            if frame < inputs.len() {
+                inputs[frame] = input;
+            } else {
+                assert_eq!(frame, inputs.len(), "replay skipped a frame");
+                inputs.push(input);
+            }
+        }
+        inputs
+    }
+
+    // None when the replay was made by a different version of the game
+    fn sync_test(ctx: &mut Context, path: &Path) -> Option<Option<SyncTestFailure>> {
+        let mut reader = open_replay_file(path).unwrap();
+        let settings = WatchReplay::read_match_settings(&mut reader).ok()?;
+        let inputs = read_inputs(reader);
+
+        let mut game = NoLogMatch::new(ctx, settings, ().into()).unwrap();
+        let mut client = sync_test_client();
+        for input in inputs {
+            for (handle, input) in input.iter().enumerate() {
+                client.handle_local_input(*input, handle).unwrap();
+            }
+            client.update(&mut game).unwrap();
+            if client.sync_test_failure().is_some() {
+                break;
+            }
+        }
+
+        Some(client.sync_test_failure())
+    }
+
+    // needs a window to load the characters, and replays recorded into ./replay,
+    // so it's run by hand with `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn sync_test_recorded_replays() {
+        let (mut ctx, _event_loop) = ContextBuilder::new("world_scared", "aos-studios")
+            .add_resource_path("./resources")
+            .build()
+            .unwrap();
+
+        let mut tested = 0;
+        for path in glob::glob("./replay/**/*.rep")
+            .unwrap()
+            .filter_map(Result::ok)
+        {
+            match sync_test(&mut ctx, &path) {
+                Some(failure) => {
+                    assert_eq!(failure, None, "{} desynced", path.display());
+                    tested += 1;
+                }
+                None => println!("skipping {}, it's from another version", path.display()),
+            }
+        }
+
+        assert!(tested > 0, "no replays to test, play a match first");
+    }
+}
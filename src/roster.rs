@@ -17,8 +17,10 @@ use fg_datastructures::{
     roster::RosterCharacter,
 };
 use fg_input::{Facing, InputState};
+use fg_netcode::compatibility::Compatibility;
+use flate2::Crc;
 pub use generic_character::*;
-use ggez::{Context, GameResult};
+use ggez::{Context, GameError, GameResult};
 use hecs::Entity;
 use hit_info::{ComboEffect, HitEffect, HitResult, HitType, Source};
 use rodio::Device;
@@ -26,6 +28,7 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::rc::Rc;
+use strum::IntoEnumIterator;
 use yuyuko::YuyukoType;
 
 #[enum_dispatch]
@@ -38,6 +41,12 @@ pub enum CharacterData {
     Yuyuko(Rc<Data<YuyukoType>>),
 }
 
+fn data_path(value: RosterCharacter) -> PathBuf {
+    match value {
+        RosterCharacter::Yuyuko => PathBuf::from("./resources/yuyuko.json"),
+    }
+}
+
 pub fn load_data(
     value: RosterCharacter,
     ctx: &mut Context,
@@ -45,14 +54,37 @@ pub fn load_data(
 ) -> GameResult<CharacterData> {
     match value {
         RosterCharacter::Yuyuko => Ok(CharacterData::Yuyuko(Rc::new(
-            Data::<YuyukoType>::new_with_path(
-                ctx,
-                assets,
-                PathBuf::from("./resources/yuyuko.json"),
-            )?,
+            Data::<YuyukoType>::new_with_path(ctx, assets, data_path(value))?,
         ))),
     }
 }
+
+/// What another player needs to have the same of to play against this build.
+/// Builds made outside of git can't be told apart, so they fall back to the crate version.
+pub fn compatibility() -> GameResult<Compatibility> {
+    let characters = RosterCharacter::iter()
+        .map(|character| Ok((character, data_hash(character)?)))
+        .collect::<GameResult<_>>()?;
+    Ok(Compatibility::new(
+        option_env!("FG_BUILD_ID")
+            .unwrap_or(env!("CARGO_PKG_VERSION"))
+            .to_string(),
+        characters,
+    ))
+}
+
+/// Hashes a character's data file, without loading any of the assets it uses.
+/// Only changes when the data does, no matter how the file is formatted.
+fn data_hash(character: RosterCharacter) -> GameResult<u32> {
+    let file = std::fs::read(data_path(character))?;
+    // a `Value` keeps its maps sorted, so the same data always comes out the same
+    let value: serde_json::Value = serde_json::from_slice(&file)
+        .map_err(|err| GameError::FilesystemError(format!("{}", err)))?;
+    let mut crc = Crc::new();
+    crc.update(&serde_json::to_vec(&value).unwrap());
+    Ok(crc.sum())
+}
+
 // TODO TEST
 impl CharacterData {
     pub fn make_character(&self) -> CharacterBehavior {
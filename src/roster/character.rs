@@ -24,6 +24,7 @@ use hecs::Entity;
 use player_state::PlayerState;
 use rodio::Device;
 use std::cell::RefCell;
+use std::hash::Hasher;
 use typedefs::{Character, Timed};
 
 pub struct Player<C: Character> {
@@ -132,6 +133,11 @@ impl<C: Character> Player<C> {
         self.state.health
     }
 
+    pub fn hash_state(&self, mut hasher: &mut dyn Hasher) {
+        self.state.hash_state(&mut hasher);
+        self.world.hash_state(hasher);
+    }
+
     pub fn get_tier(&self, entity: Entity) -> Option<BulletTier> {
         self.world
             .get::<BulletHp>(entity)
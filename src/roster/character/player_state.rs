@@ -21,8 +21,9 @@ use crate::{
 use fg_datastructures::math::collision;
 use fg_input::Facing;
 use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct PlayerState<C: Character> {
     pub velocity: collision::Vec2,
     pub position: collision::Vec2,
@@ -48,6 +49,87 @@ pub struct PlayerState<C: Character> {
     pub sound_state: PlayerSoundState<SoundPath<C::Sound>>,
 }
 
+// written out so that saving over an old state reuses its sets and maps, which the derived
+// clone_from would throw away
+impl<C: Character> Clone for PlayerState<C> {
+    fn clone(&self) -> Self {
+        Self {
+            velocity: self.velocity,
+            position: self.position,
+            current_state: self.current_state,
+            last_hit_using: self.last_hit_using,
+            allowed_cancels: self.allowed_cancels.clone(),
+            rebeat_chain: self.rebeat_chain.clone(),
+            smp: self.smp.clone(),
+            most_recent_command: self.most_recent_command,
+            air_actions: self.air_actions,
+            stun: self.stun,
+            health: self.health,
+            spirit_gauge: self.spirit_gauge,
+            spirit_delay: self.spirit_delay,
+            hitstop: self.hitstop,
+            meter: self.meter,
+            lockout: self.lockout,
+            dead: self.dead,
+            should_pushback: self.should_pushback,
+            facing: self.facing,
+            current_combo: self.current_combo.clone(),
+            other: self.other.clone(),
+            sound_state: self.sound_state.clone(),
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        let Self {
+            velocity,
+            position,
+            current_state,
+            last_hit_using,
+            allowed_cancels,
+            rebeat_chain,
+            smp,
+            most_recent_command,
+            air_actions,
+            stun,
+            health,
+            spirit_gauge,
+            spirit_delay,
+            hitstop,
+            meter,
+            lockout,
+            dead,
+            should_pushback,
+            facing,
+            current_combo,
+            other,
+            sound_state,
+        } = source;
+
+        self.velocity = *velocity;
+        self.position = *position;
+        self.current_state = *current_state;
+        self.last_hit_using = *last_hit_using;
+        self.allowed_cancels.clone_from(allowed_cancels);
+        self.rebeat_chain.clone_from(rebeat_chain);
+        self.smp.clone_from(smp);
+        self.most_recent_command = *most_recent_command;
+        self.air_actions = *air_actions;
+        self.stun = *stun;
+        self.health = *health;
+        self.spirit_gauge = *spirit_gauge;
+        self.spirit_delay = *spirit_delay;
+        self.hitstop = *hitstop;
+        self.meter = *meter;
+        self.lockout = *lockout;
+        self.dead = *dead;
+        self.should_pushback = *should_pushback;
+        self.facing = *facing;
+        self.current_combo.clone_from(current_combo);
+        self.other.clone_from(other);
+        self.sound_state.clone_from(sound_state);
+    }
+}
+
 impl<C: Character> PlayerState<C> {
     pub fn new(data: &Data<C>) -> Self {
         Self {
@@ -81,4 +163,59 @@ impl<C: Character> PlayerState<C> {
             other: C::default(),
         }
     }
+
+    /// Hashes everything that affects the simulation. Every field is listed, so adding one
+    /// without deciding whether it belongs in the checksum won't compile.
+    pub fn hash_state<H: Hasher>(&self, state: &mut H) {
+        let Self {
+            velocity,
+            position,
+            current_state,
+            last_hit_using,
+            allowed_cancels,
+            rebeat_chain,
+            smp,
+            most_recent_command,
+            air_actions,
+            stun,
+            health,
+            spirit_gauge,
+            spirit_delay,
+            hitstop,
+            meter,
+            lockout,
+            dead,
+            should_pushback,
+            facing,
+            current_combo,
+            other,
+            // only decides what gets played, and is never read back by the simulation
+            sound_state: _,
+        } = self;
+
+        velocity.hash(state);
+        position.hash(state);
+        current_state.hash(state);
+        last_hit_using.hash(state);
+        allowed_cancels.hash(state);
+        // sets iterate in a different order on every client
+        let mut rebeat_chain: Vec<_> = rebeat_chain.iter().collect();
+        rebeat_chain.sort_unstable();
+        rebeat_chain.hash(state);
+        smp.hash(state);
+        most_recent_command.hash(state);
+        air_actions.hash(state);
+        stun.hash(state);
+        health.hash(state);
+        spirit_gauge.hash(state);
+        spirit_delay.hash(state);
+        hitstop.hash(state);
+        meter.hash(state);
+        lockout.hash(state);
+        dead.hash(state);
+        should_pushback.hash(state);
+        facing.hash(state);
+        current_combo.hash(state);
+        other.hash(state);
+    }
 }
@@ -1,13 +1,30 @@
-use std::{collections::HashMap, hash::Hash};
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+};
 
 use super::typedefs::Timed;
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct SmpList<Id> {
     pub smp_list: HashMap<Id, usize>,
     pub first_command: Option<Timed<Id>>,
 }
 
+impl<Id: Clone> Clone for SmpList<Id> {
+    fn clone(&self) -> Self {
+        Self {
+            smp_list: self.smp_list.clone(),
+            first_command: self.first_command.clone(),
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.smp_list.clone_from(&source.smp_list);
+        self.first_command.clone_from(&source.first_command);
+    }
+}
+
 impl<Id> Default for SmpList<Id> {
     fn default() -> Self {
         Self {
@@ -44,3 +61,13 @@ impl<Id: Hash + Eq> SmpList<Id> {
             .unwrap_or(false)
     }
 }
+
+impl<Id: Hash + Ord> Hash for SmpList<Id> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // maps iterate in a different order on every client
+        let mut smp_list: Vec<_> = self.smp_list.iter().collect();
+        smp_list.sort_unstable();
+        smp_list.hash(state);
+        self.first_command.hash(state);
+    }
+}
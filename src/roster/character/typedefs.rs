@@ -82,7 +82,9 @@ pub trait AttackObjectData {}
 
 impl<T> AttackObjectData for T {}
 
-pub trait Character: Sized + Default + Clone + Debug + PartialEq + Eq + 'static {
+pub trait Character:
+    Sized + Default + Clone + Debug + PartialEq + Eq + Hash + Serialize + 'static
+{
     type Sound: Id;
     type State: Id + StateConsts;
     type Attack: Id;
@@ -101,13 +103,13 @@ pub trait Character: Sized + Default + Clone + Debug + PartialEq + Eq + 'static
     ) -> bool;
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Inspect, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Inspect, Serialize, Deserialize)]
 pub struct Timed<Id> {
     pub time: usize,
     pub id: Id,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Inspect, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Inspect, Default)]
 pub struct HitId<Id> {
     pub hitbox_id: usize,
     pub id: Id,
@@ -16,8 +16,9 @@ use hecs::Entity;
 use hit_info::{ComboEffect, HitEffect, HitResult, HitType, Source};
 use rodio::Device;
 use std::collections::HashMap;
+use std::hash::Hasher;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Hash)]
 pub enum AllowedCancel {
     Always,
     Hit,
@@ -99,7 +100,11 @@ pub trait GenericCharacterBehaviour {
     fn draw_order_priority(&self) -> i32;
 
     fn save(&self) -> GameResult<OpaqueStateData>;
-    fn load(&mut self, value: OpaqueStateData) -> GameResult<()>;
+    /// Like `save`, but reuses the allocations of a state that was saved earlier.
+    fn save_into(&self, value: &mut OpaqueStateData) -> GameResult<()>;
+    /// Loads a saved state into the character's own allocations.
+    fn load(&mut self, value: &OpaqueStateData) -> GameResult<()>;
+    fn hash_state(&self, hasher: &mut dyn Hasher);
 
     fn get_flash(&self) -> Option<FlashType>;
     fn get_lockout(&self) -> (i32, bool);
@@ -20,7 +20,7 @@ pub enum Force {
     Airborne(Vec2),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Hash)]
 pub struct ComboEffect {
     pub hits: u32,
     pub total_damage: i32,
@@ -2,10 +2,12 @@ use core::any::TypeId;
 use std::{
     any::type_name,
     fmt::Debug,
+    hash::{Hash, Hasher},
     ops::{Deref, DerefMut},
 };
 
-use hecs::{Archetype, ColumnBatchBuilder, ColumnBatchType, Component, World as HecsWorld};
+use hecs::{Archetype, ColumnBatchBuilder, ColumnBatchType, Component, Entity, World as HecsWorld};
+use serde::Serialize;
 
 /// An opaque registry that holds data that helps a World clone itself.
 #[derive(Clone, Default, Debug)]
@@ -13,8 +15,8 @@ pub struct CloneRegistry(Vec<CloneEntry>);
 
 impl CloneRegistry {
     /// Registers `T` with the registry, enabling `T` to be cloned in any
-    /// archetypes that contain it.
-    pub fn register<T: Clone + Component>(mut self) -> Self {
+    /// archetypes that contain it, and hashed along with the rest of the world.
+    pub fn register<T: Clone + Component + Serialize>(mut self) -> Self {
         if !self.0.iter().any(|item| item.type_id == TypeId::of::<T>()) {
             self.0.push(register::<T>());
         }
@@ -28,8 +30,9 @@ struct CloneEntry {
     type_name: &'static str,
     add_type: fn(&mut ColumnBatchType) -> (),
     add_values: fn(&mut ColumnBatchBuilder, &Archetype) -> (),
+    hash_value: fn(&HecsWorld, Entity, &mut dyn Hasher) -> bool,
 }
-fn register<T: Component + Clone>() -> CloneEntry {
+fn register<T: Component + Clone + Serialize>() -> CloneEntry {
     CloneEntry {
         type_id: TypeId::of::<T>(),
         type_name: type_name::<T>(),
@@ -50,6 +53,15 @@ fn register<T: Component + Clone>() -> CloneEntry {
                 }
             }
         },
+        hash_value: |world, entity, hasher| match world.get::<T>(entity) {
+            Ok(value) => {
+                // components don't all implement Hash, but they all serialize the same way
+                // on every client
+                hasher.write(&bincode::serialize(&*value).unwrap());
+                true
+            }
+            Err(_) => false,
+        },
     }
 }
 
@@ -92,7 +104,41 @@ impl DerefMut for World {
 impl Clone for World {
     fn clone(&self) -> Self {
         let mut new_world = Self::new(self.clone_registry.clone());
+        self.clone_entities_into(&mut new_world);
+        new_world
+    }
 
+    fn clone_from(&mut self, source: &Self) {
+        // clearing keeps the archetypes allocated, so cloning the same kinds of entities
+        // again doesn't need to allocate them all over
+        self.inner.clear();
+        self.clone_registry.clone_from(&source.clone_registry);
+        source.clone_entities_into(self);
+    }
+}
+
+impl World {
+    /// Hashes every registered component of every entity, in entity order, so it doesn't
+    /// matter what order the archetypes were created in.
+    pub fn hash_state(&self, mut hasher: &mut dyn Hasher) {
+        let mut entities: Vec<_> = self
+            .archetypes()
+            .flat_map(|archetype| archetype.ids().iter())
+            .map(|id| unsafe { self.find_entity_from_id(*id) })
+            .collect();
+        entities.sort_unstable_by_key(|entity| entity.id());
+
+        for entity in entities {
+            entity.id().hash(&mut hasher);
+            for (idx, entry) in self.clone_registry.0.iter().enumerate() {
+                if (entry.hash_value)(&self.inner, entity, hasher) {
+                    idx.hash(&mut hasher);
+                }
+            }
+        }
+    }
+
+    fn clone_entities_into(&self, new_world: &mut World) {
         for archetype in self.archetypes().filter(|item| !item.is_empty()) {
             assert!(archetype.component_types().all(|item| self
                 .clone_registry
@@ -125,8 +171,6 @@ impl Clone for World {
                 .collect();
             new_world.spawn_column_batch_at(&entities, batch.build().unwrap());
         }
-
-        new_world
     }
 }
 
@@ -173,4 +217,46 @@ mod test {
             assert_eq!(left, right);
         }
     }
+
+    #[test]
+    fn hash_state() {
+        fn hash(world: &World) -> u64 {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            world.hash_state(&mut hasher);
+            hasher.finish()
+        }
+
+        let registry = CloneRegistry::default()
+            .register::<String>()
+            .register::<u32>();
+        let mut world = World::new(registry.clone());
+        world.spawn((4u32,));
+        let entity = world.spawn((8u32, "test".to_string()));
+
+        // the clone's archetypes are left over from what it held before
+        let mut cloned = World::new(registry);
+        cloned.spawn(("other".to_string(),));
+        cloned.spawn((15u32, "other".to_string()));
+        cloned.clone_from(&world);
+        assert_eq!(hash(&world), hash(&cloned));
+
+        *cloned.get_mut::<u32>(entity).unwrap() += 1;
+        assert_ne!(hash(&world), hash(&cloned));
+    }
+
+    #[test]
+    fn clone_from() {
+        let registry = CloneRegistry::default().register::<u32>();
+        let mut world = World::new(registry.clone());
+        world.spawn((4u32,));
+        world.spawn((8u32,));
+
+        let mut cloned = World::new(registry);
+        cloned.spawn((15u32,));
+        cloned.clone_from(&world);
+
+        let mut values: Vec<_> = cloned.query::<&u32>().iter().map(|(_, x)| *x).collect();
+        values.sort();
+        assert_eq!(values, vec![4, 8]);
+    }
 }
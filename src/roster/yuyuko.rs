@@ -33,6 +33,7 @@ use hecs::Entity;
 use rodio::Device;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
+use std::hash::Hasher;
 
 pub use attacks::Attack;
 pub use commands::Command;
@@ -41,7 +42,9 @@ pub use object::ObjectData;
 pub use sounds::Sound;
 pub use state::State;
 
-#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(
+    Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
 pub struct YuyukoType;
 
 impl Character for YuyukoType {
@@ -235,11 +238,23 @@ impl GenericCharacterBehaviour for Player<YuyukoType> {
         Ok(OpaqueStateData::Yuyuko(self.state.clone(), w))
     }
 
-    fn load(&mut self, value: OpaqueStateData) -> GameResult<()> {
+    fn save_into(&self, value: &mut OpaqueStateData) -> GameResult<()> {
         match value {
             OpaqueStateData::Yuyuko(state, world) => {
-                self.state = state;
-                self.world = world;
+                state.clone_from(&self.state);
+                world.clone_from(&self.world);
+            }
+            _ => *value = self.save()?,
+        }
+
+        Ok(())
+    }
+
+    fn load(&mut self, value: &OpaqueStateData) -> GameResult<()> {
+        match value {
+            OpaqueStateData::Yuyuko(state, world) => {
+                self.state.clone_from(state);
+                self.world.clone_from(world);
             }
             _ => unreachable!(),
         }
@@ -247,6 +262,10 @@ impl GenericCharacterBehaviour for Player<YuyukoType> {
         Ok(())
     }
 
+    fn hash_state(&self, hasher: &mut dyn Hasher) {
+        self.hash_state(hasher)
+    }
+
     fn get_flash(&self) -> Option<FlashType> {
         self.get_flash()
     }